@@ -0,0 +1,108 @@
+//! The deploy state file, recording the accounts created by the CLI
+//!
+//! Like the DEPLOY_FILE and USER_FILE of the TypeScript client, commands skip
+//! the steps whose accounts already exist, so setup can be rerun after a
+//! failure.
+
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::Result;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeployState {
+    #[serde(default, with = "optional_pubkey")]
+    pub program_id: Option<Pubkey>,
+
+    #[serde(default, with = "optional_pubkey")]
+    pub debt_type: Option<Pubkey>,
+    #[serde(default, with = "optional_pubkey")]
+    pub debt_token: Option<Pubkey>,
+
+    #[serde(default, with = "optional_pubkey")]
+    pub price_oracle: Option<Pubkey>,
+    #[serde(default, with = "optional_pubkey")]
+    pub vault_type: Option<Pubkey>,
+    // a test token minted by the wallet
+    #[serde(default, with = "optional_pubkey")]
+    pub collateral_token: Option<Pubkey>,
+    #[serde(default, with = "optional_pubkey")]
+    pub collateral_token_holder: Option<Pubkey>,
+
+    // accounts of the wallet
+    #[serde(default, with = "optional_pubkey")]
+    pub collateral_account: Option<Pubkey>,
+    #[serde(default, with = "optional_pubkey")]
+    pub debt_account: Option<Pubkey>,
+    #[serde(default, with = "optional_pubkey")]
+    pub vault: Option<Pubkey>,
+}
+
+impl DeployState {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(DeployState::default());
+        }
+
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Get a required account of the deploy state, naming the command that
+/// creates it if it's missing.
+pub fn require(key: Option<Pubkey>, name: &str, command: &str) -> Result<Pubkey> {
+    key.ok_or_else(|| format!("no {} in the deploy state, run `{}` first", name, command).into())
+}
+
+/// Public keys are stored as base58 strings.
+mod optional_pubkey {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use solana_sdk::pubkey::Pubkey;
+
+    pub fn serialize<S: Serializer>(
+        key: &Option<Pubkey>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match key {
+            Some(key) => serializer.serialize_some(&key.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Pubkey>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|key| key.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deploy_state_json() {
+        let vault = Pubkey::new_unique();
+        let state = DeployState {
+            vault: Some(vault),
+            ..DeployState::default()
+        };
+
+        let json = serde_json::to_value(&state).unwrap();
+        assert_eq!(json["vault"], vault.to_string());
+        assert!(json["debt_type"].is_null());
+
+        let state: DeployState = serde_json::from_value(json).unwrap();
+        assert_eq!(state.vault, Some(vault));
+        assert_eq!(state.debt_type, None);
+    }
+}
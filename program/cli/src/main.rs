@@ -0,0 +1,922 @@
+//! Command line tool to operate Parrot debt markets and vaults
+//!
+//! Covers what `yarn parrot` does (setup, setup-vault, stake, borrow, info),
+//! plus the admin and keeper instructions. The accounts created are recorded in
+//! a deploy state file, and every command prints its result as JSON.
+
+mod deploy;
+mod rpc;
+
+use std::{error::Error, path::PathBuf, process::exit};
+
+use borsh::BorshDeserialize;
+use clap::{value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
+use serde_json::{json, Value};
+use solana_faucet::{
+    borsh_utils::get_packed_len,
+    health::{vault_health, Price},
+    instruction,
+    processor::{COLLATERAL_HOLDER_ROLE, MINTER_ROLE},
+    state::{
+        CollateralPricing, DebtType, LpPool, PriceFeed, PublicKey, Savings, Vault, VaultType,
+        VaultTypeParams,
+    },
+};
+use solana_sdk::{
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signature, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+
+use crate::{
+    deploy::{require, DeployState},
+    rpc::RpcClient,
+};
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+struct Context {
+    rpc: RpcClient,
+    payer: Keypair,
+
+    state_path: PathBuf,
+    state: DeployState,
+}
+
+impl Context {
+    fn program_id(&self) -> Result<Pubkey> {
+        require(self.state.program_id, "program_id", "setup --program-id")
+    }
+
+    fn save(&self) -> Result<()> {
+        self.state.save(&self.state_path)
+    }
+
+    /// Sign the instructions with the payer and `signers`, and send them in
+    /// one transaction.
+    fn send(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<Signature> {
+        let mut all_signers: Vec<&dyn Signer> = vec![&self.payer];
+        all_signers.extend(signers.iter().map(|signer| *signer as &dyn Signer));
+
+        let blockhash = self.rpc.get_recent_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+
+        self.rpc.send_and_confirm_transaction(&transaction)
+    }
+
+    /// Allocate a rent exempt account of `space` bytes, funded by the payer.
+    fn create_account(
+        &self,
+        account: &Pubkey,
+        space: usize,
+        owner: &Pubkey,
+    ) -> Result<Instruction> {
+        let lamports = self.rpc.get_minimum_balance_for_rent_exemption(space)?;
+        Ok(system_instruction::create_account(
+            &self.payer.pubkey(),
+            account,
+            lamports,
+            space as u64,
+            owner,
+        ))
+    }
+
+    fn create_state_account<T: borsh::BorshSchema>(&self, account: &Pubkey) -> Result<Instruction> {
+        self.create_account(account, get_packed_len::<T>(), &self.program_id()?)
+    }
+
+    /// Load a state account, checking that the program owns it.
+    fn load<T: BorshDeserialize>(&self, key: &Pubkey) -> Result<T> {
+        let account = self
+            .rpc
+            .get_account(key)?
+            .ok_or_else(|| format!("account {} not found", key))?;
+        if account.owner != self.program_id()? {
+            return Err(format!("account {} is not owned by the program", key).into());
+        }
+
+        Ok(T::try_from_slice(&account.data)?)
+    }
+
+    fn program_address(&self, parent: &Pubkey, role: &str) -> Result<(Pubkey, u8)> {
+        Ok(Pubkey::find_program_address(
+            &[&parent.to_bytes()[..], role.as_bytes()],
+            &self.program_id()?,
+        ))
+    }
+
+    fn create_mint(
+        &self,
+        mint: &Keypair,
+        authority: &Pubkey,
+        decimals: u8,
+    ) -> Result<Vec<Instruction>> {
+        Ok(vec![
+            self.create_account(
+                &mint.pubkey(),
+                spl_token::state::Mint::LEN,
+                &spl_token::id(),
+            )?,
+            spl_token::instruction::initialize_mint(
+                &spl_token::id(),
+                &mint.pubkey(),
+                authority,
+                None,
+                decimals,
+            )?,
+        ])
+    }
+
+    fn create_token_account(
+        &self,
+        account: &Keypair,
+        mint: &Pubkey,
+        owner: &Pubkey,
+    ) -> Result<Vec<Instruction>> {
+        Ok(vec![
+            self.create_account(
+                &account.pubkey(),
+                spl_token::state::Account::LEN,
+                &spl_token::id(),
+            )?,
+            spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                &account.pubkey(),
+                mint,
+                owner,
+            )?,
+        ])
+    }
+}
+
+fn pubkey(key: &PublicKey) -> Pubkey {
+    Pubkey::new_from_array(key.0)
+}
+
+fn is_unset(key: &PublicKey) -> bool {
+    *key == PublicKey::default()
+}
+
+/// An unset key is an account that isn't used, and isn't passed.
+fn optional(key: &PublicKey) -> Option<Pubkey> {
+    Some(pubkey(key)).filter(|_| !is_unset(key))
+}
+
+fn setup(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    if let Some(program_id) = args.value_of("program_id") {
+        ctx.state.program_id = Some(program_id.parse()?);
+    }
+    let program_id = ctx.program_id()?;
+    let mut signatures = vec![];
+
+    if ctx.state.debt_type.is_none() {
+        let debt_type = Keypair::new();
+        let debt_token = Keypair::new();
+        let (debt_minter, _) = ctx.program_address(&debt_type.pubkey(), MINTER_ROLE)?;
+
+        // the debt type records the decimals of the mint, so the mint goes first
+        let mut instructions =
+            ctx.create_mint(&debt_token, &debt_minter, value_t!(args, "decimals", u8)?)?;
+        instructions.extend(vec![
+            ctx.create_state_account::<DebtType>(&debt_type.pubkey())?,
+            instruction::init_debt_type(
+                &program_id,
+                &debt_type.pubkey(),
+                &debt_token.pubkey(),
+                &ctx.payer.pubkey(),
+            )?,
+        ]);
+        signatures.push(ctx.send(&instructions, &[&debt_type, &debt_token])?);
+
+        ctx.state.debt_type = Some(debt_type.pubkey());
+        ctx.state.debt_token = Some(debt_token.pubkey());
+        ctx.save()?;
+    }
+    let debt_type = require(ctx.state.debt_type, "debt_type", "setup")?;
+
+    if ctx.state.vault_type.is_none() {
+        // a price feed updated by the wallet, and a test collateral token
+        // minted by the wallet
+        let price_oracle = Keypair::new();
+        let collateral_token = Keypair::new();
+        let collateral_token_holder = Keypair::new();
+        let vault_type = Keypair::new();
+        let (holder_authority, _) =
+            ctx.program_address(&vault_type.pubkey(), COLLATERAL_HOLDER_ROLE)?;
+
+        let mut instructions = ctx.create_mint(
+            &collateral_token,
+            &ctx.payer.pubkey(),
+            value_t!(args, "decimals", u8)?,
+        )?;
+        instructions.extend(ctx.create_token_account(
+            &collateral_token_holder,
+            &collateral_token.pubkey(),
+            &holder_authority,
+        )?);
+        signatures.push(ctx.send(
+            &instructions,
+            &[&collateral_token, &collateral_token_holder],
+        )?);
+
+        signatures.push(ctx.send(
+            &[
+                ctx.create_state_account::<PriceFeed>(&price_oracle.pubkey())?,
+                instruction::init_price_feed(
+                    &program_id,
+                    &price_oracle.pubkey(),
+                    &collateral_token.pubkey(),
+                    &ctx.payer.pubkey(),
+                    0,
+                )?,
+                instruction::update_price(
+                    &program_id,
+                    &price_oracle.pubkey(),
+                    &ctx.payer.pubkey(),
+                    value_t!(args, "price", u64)?,
+                )?,
+            ],
+            &[&price_oracle],
+        )?);
+
+        signatures.push(ctx.send(
+            &[
+                ctx.create_state_account::<VaultType>(&vault_type.pubkey())?,
+                instruction::init_vault_type(
+                    &program_id,
+                    &vault_type.pubkey(),
+                    &debt_type,
+                    &ctx.payer.pubkey(),
+                    &collateral_token.pubkey(),
+                    &collateral_token_holder.pubkey(),
+                    &price_oracle.pubkey(),
+                    CollateralPricing::PriceFeed,
+                    VaultTypeParams {
+                        collateral_ratio: value_t!(args, "collateral_ratio", u64)?,
+                        ..VaultTypeParams::default()
+                    },
+                    false,
+                )?,
+            ],
+            &[&vault_type],
+        )?);
+
+        ctx.state.price_oracle = Some(price_oracle.pubkey());
+        ctx.state.collateral_token = Some(collateral_token.pubkey());
+        ctx.state.collateral_token_holder = Some(collateral_token_holder.pubkey());
+        ctx.state.vault_type = Some(vault_type.pubkey());
+        ctx.save()?;
+    }
+
+    Ok(json!({
+        "signatures": signatures.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        "state": ctx.state,
+    }))
+}
+
+fn setup_vault(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let program_id = ctx.program_id()?;
+    let vault_type = require(ctx.state.vault_type, "vault_type", "setup")?;
+    let collateral_token = require(ctx.state.collateral_token, "collateral_token", "setup")?;
+    let debt_token = require(ctx.state.debt_token, "debt_token", "setup")?;
+    let owner = ctx.payer.pubkey();
+    let mut signatures = vec![];
+
+    if ctx.state.collateral_account.is_none() {
+        let account = Keypair::new();
+        let mut instructions = ctx.create_token_account(&account, &collateral_token, &owner)?;
+        instructions.push(spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &collateral_token,
+            &account.pubkey(),
+            &owner,
+            &[],
+            value_t!(args, "mint_amount", u64)?,
+        )?);
+        signatures.push(ctx.send(&instructions, &[&account])?);
+
+        ctx.state.collateral_account = Some(account.pubkey());
+        ctx.save()?;
+    }
+
+    if ctx.state.debt_account.is_none() {
+        let account = Keypair::new();
+        let instructions = ctx.create_token_account(&account, &debt_token, &owner)?;
+        signatures.push(ctx.send(&instructions, &[&account])?);
+
+        ctx.state.debt_account = Some(account.pubkey());
+        ctx.save()?;
+    }
+
+    if ctx.state.vault.is_none() {
+        let vtype: VaultType = ctx.load(&vault_type)?;
+        let allowlist = optional(&vtype.params.allowlist);
+
+        let vault = Keypair::new();
+        signatures.push(ctx.send(
+            &[
+                ctx.create_state_account::<Vault>(&vault.pubkey())?,
+                instruction::init_vault(
+                    &program_id,
+                    &vault.pubkey(),
+                    &vault_type,
+                    &owner,
+                    None,
+                    allowlist.as_ref(),
+                )?,
+            ],
+            &[&vault],
+        )?);
+
+        ctx.state.vault = Some(vault.pubkey());
+        ctx.save()?;
+    }
+
+    Ok(json!({
+        "signatures": signatures.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        "state": ctx.state,
+    }))
+}
+
+/// The accounts of the vault of the wallet.
+struct VaultAccounts {
+    debt_type: Pubkey,
+    vault_type: Pubkey,
+    vault: Pubkey,
+
+    debt: DebtType,
+    vtype: VaultType,
+}
+
+fn vault_accounts(ctx: &Context) -> Result<VaultAccounts> {
+    let vault = require(ctx.state.vault, "vault", "setup-vault")?;
+    let vault_state: Vault = ctx.load(&vault)?;
+    let vault_type = pubkey(&vault_state.vault_type);
+    let vtype: VaultType = ctx.load(&vault_type)?;
+    let debt_type = pubkey(&vtype.debt_type);
+    let debt: DebtType = ctx.load(&debt_type)?;
+
+    if vtype.native {
+        return Err("native vault types are not supported by the CLI".into());
+    }
+
+    Ok(VaultAccounts {
+        debt_type,
+        vault_type,
+        vault,
+        debt,
+        vtype,
+    })
+}
+
+fn signature(signature: Signature) -> Value {
+    json!({ "signature": signature.to_string() })
+}
+
+fn stake(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let accounts = vault_accounts(ctx)?;
+    let (_, nonce) = ctx.program_address(&accounts.vault_type, COLLATERAL_HOLDER_ROLE)?;
+
+    let inx = instruction::stake(
+        &ctx.program_id()?,
+        &require(
+            ctx.state.collateral_account,
+            "collateral_account",
+            "setup-vault",
+        )?,
+        &ctx.payer.pubkey(),
+        &pubkey(&accounts.vtype.collateral_token_holder),
+        &accounts.vault_type,
+        &accounts.vault,
+        &[],
+        value_t!(args, "amount", u64)?,
+        nonce,
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn unstake(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let accounts = vault_accounts(ctx)?;
+    let (holder_authority, nonce) =
+        ctx.program_address(&accounts.vault_type, COLLATERAL_HOLDER_ROLE)?;
+
+    let inx = instruction::unstake(
+        &ctx.program_id()?,
+        &pubkey(&accounts.vtype.collateral_token_holder),
+        &holder_authority,
+        &require(
+            ctx.state.collateral_account,
+            "collateral_account",
+            "setup-vault",
+        )?,
+        &accounts.vault_type,
+        &accounts.vault,
+        &ctx.payer.pubkey(),
+        &pubkey(&accounts.vtype.price_oracle),
+        &[],
+        None,
+        None,
+        value_t!(args, "amount", u64)?,
+        nonce,
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn borrow(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let accounts = vault_accounts(ctx)?;
+    let (debt_minter, nonce) = ctx.program_address(&accounts.debt_type, MINTER_ROLE)?;
+    let treasury = pubkey(&accounts.debt.treasury);
+    let charges_fee = accounts.vtype.params.origination_fee > 0;
+
+    let allowlist = optional(&accounts.vtype.params.allowlist);
+
+    let inx = instruction::borrow(
+        &ctx.program_id()?,
+        &pubkey(&accounts.debt.debt_token),
+        &debt_minter,
+        &require(ctx.state.debt_account, "debt_account", "setup-vault")?,
+        &accounts.debt_type,
+        &accounts.vault_type,
+        &accounts.vault,
+        &ctx.payer.pubkey(),
+        &pubkey(&accounts.vtype.price_oracle),
+        Some(&treasury).filter(|_| charges_fee),
+        allowlist.as_ref(),
+        None,
+        None,
+        value_t!(args, "amount", u64)?,
+        nonce,
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn repay(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let accounts = vault_accounts(ctx)?;
+
+    let inx = instruction::repay(
+        &ctx.program_id()?,
+        &pubkey(&accounts.debt.debt_token),
+        &require(ctx.state.debt_account, "debt_account", "setup-vault")?,
+        &ctx.payer.pubkey(),
+        &accounts.debt_type,
+        &accounts.vault_type,
+        &accounts.vault,
+        value_t!(args, "amount", u64)?,
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn info(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let vault_key = match args.value_of("vault") {
+        Some(vault) => vault.parse()?,
+        None => require(ctx.state.vault, "vault", "setup-vault")?,
+    };
+    let vault: Vault = ctx.load(&vault_key)?;
+    let vault_type: VaultType = ctx.load(&pubkey(&vault.vault_type))?;
+
+    let oracle = pubkey(&vault_type.price_oracle);
+    let price = match vault_type.pricing {
+        CollateralPricing::PriceFeed => Price::from_price_feed(&ctx.load(&oracle)?, &vault_type)?,
+        CollateralPricing::LpPool => Price::from_lp_pool(&ctx.load(&oracle)?, &vault_type)?,
+    };
+    let health = vault_health(&vault, &vault_type, &price)?;
+
+    Ok(json!({
+        "vault": vault_key.to_string(),
+        "vault_type": pubkey(&vault.vault_type).to_string(),
+        "owner": pubkey(&vault.owner).to_string(),
+        "collateral_amount": vault.collateral_amount,
+        "debt_amount": vault.debt_amount,
+        "collateral_value": health.collateral_value,
+        "debt_value": health.debt_value,
+        "collateral_ratio": health.collateral_ratio,
+        "required_collateral_ratio": vault_type.params.collateral_ratio,
+        "max_debt": health.max_debt,
+        "max_borrowable": health.max_borrowable,
+        "max_withdrawable": health.max_withdrawable,
+        // in whole debt tokens per collateral token
+        "liquidation_price": health.liquidation_price.map(|price| price.to_string()),
+        "healthy": health.is_healthy(),
+    }))
+}
+
+fn update_price(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let price_feed = match args.value_of("price_feed") {
+        Some(price_feed) => price_feed.parse()?,
+        None => require(ctx.state.price_oracle, "price_oracle", "setup")?,
+    };
+
+    let inx = instruction::update_price(
+        &ctx.program_id()?,
+        &price_feed,
+        &ctx.payer.pubkey(),
+        value_t!(args, "price", u64)?,
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn update_vault_type_params(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let vault_type = match args.value_of("vault_type") {
+        Some(vault_type) => vault_type.parse()?,
+        None => require(ctx.state.vault_type, "vault_type", "setup")?,
+    };
+    let vtype: VaultType = ctx.load(&vault_type)?;
+
+    let mut params = vtype.params.clone();
+    if args.is_present("collateral_ratio") {
+        params.collateral_ratio = value_t!(args, "collateral_ratio", u64)?;
+    }
+    if args.is_present("origination_fee") {
+        params.origination_fee = value_t!(args, "origination_fee", u64)?;
+    }
+    if args.is_present("collateral_cap") {
+        params.collateral_cap = value_t!(args, "collateral_cap", u64)?;
+    }
+    if args.is_present("liquidation_penalty") {
+        params.liquidation_penalty = value_t!(args, "liquidation_penalty", u64)?;
+    }
+    if args.is_present("stability_fee") {
+        params.stability_fee = value_t!(args, "stability_fee", u64)?;
+    }
+    if let Some(swap_program) = args.value_of("swap_program") {
+        params.swap_program = (&swap_program.parse::<Pubkey>()?).into();
+    }
+    if let Some(allowlist) = args.value_of("allowlist") {
+        params.allowlist = (&allowlist.parse::<Pubkey>()?).into();
+    }
+
+    let inx = instruction::update_vault_type_params(
+        &ctx.program_id()?,
+        &vault_type,
+        &pubkey(&vtype.debt_type),
+        &ctx.payer.pubkey(),
+        params,
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn withdraw_surplus(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let debt_type = require(ctx.state.debt_type, "debt_type", "setup")?;
+    let debt: DebtType = ctx.load(&debt_type)?;
+    if is_unset(&debt.treasury) {
+        return Err("the debt type has no treasury".into());
+    }
+    let (debt_minter, nonce) = ctx.program_address(&debt_type, MINTER_ROLE)?;
+    let receiver = match args.value_of("receiver") {
+        Some(receiver) => receiver.parse()?,
+        None => require(ctx.state.debt_account, "debt_account", "setup-vault")?,
+    };
+
+    let inx = instruction::withdraw_surplus(
+        &ctx.program_id()?,
+        &pubkey(&debt.treasury),
+        &debt_type,
+        &ctx.payer.pubkey(),
+        &pubkey(&debt.debt_token),
+        &debt_minter,
+        &receiver,
+        value_t!(args, "amount", u64)?,
+        nonce,
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn update_allowlist(ctx: &mut Context, args: &ArgMatches, add: bool) -> Result<Value> {
+    let vault_type = require(ctx.state.vault_type, "vault_type", "setup")?;
+    let vtype: VaultType = ctx.load(&vault_type)?;
+    if is_unset(&vtype.params.allowlist) {
+        return Err("the vault type is permissionless".into());
+    }
+
+    let program_id = ctx.program_id()?;
+    let address: Pubkey = args.value_of("address").unwrap().parse()?;
+    let (allowlist, debt_type, owner) = (
+        pubkey(&vtype.params.allowlist),
+        pubkey(&vtype.debt_type),
+        ctx.payer.pubkey(),
+    );
+    let inx = if add {
+        instruction::add_to_allowlist(&program_id, &allowlist, &debt_type, &owner, &address)?
+    } else {
+        instruction::remove_from_allowlist(&program_id, &allowlist, &debt_type, &owner, &address)?
+    };
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn refresh_lp_pool(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let lp_pool_key: Pubkey = args.value_of("lp_pool").unwrap().parse()?;
+    let lp_pool: LpPool = ctx.load(&lp_pool_key)?;
+
+    let inx = instruction::refresh_lp_pool(
+        &ctx.program_id()?,
+        &lp_pool_key,
+        &pubkey(&lp_pool.price_feed_a),
+        &pubkey(&lp_pool.price_feed_b),
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn drip(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let savings_key: Pubkey = args.value_of("savings").unwrap().parse()?;
+    let savings: Savings = ctx.load(&savings_key)?;
+    let debt_type = pubkey(&savings.debt_type);
+    let debt: DebtType = ctx.load(&debt_type)?;
+    let (debt_minter, nonce) = ctx.program_address(&debt_type, MINTER_ROLE)?;
+    let vault_types = args
+        .values_of("vault_type")
+        .into_iter()
+        .flatten()
+        .map(|vault_type| vault_type.parse::<Pubkey>())
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let inx = instruction::drip(
+        &ctx.program_id()?,
+        &savings_key,
+        &pubkey(&savings.share_token),
+        &pubkey(&savings.debt_holder),
+        &debt_type,
+        &pubkey(&debt.debt_token),
+        &debt_minter,
+        &pubkey(&debt.treasury),
+        &vault_types,
+        nonce,
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn write_off_vault(ctx: &mut Context, args: &ArgMatches) -> Result<Value> {
+    let vault_key: Pubkey = args.value_of("vault").unwrap().parse()?;
+    let vault: Vault = ctx.load(&vault_key)?;
+    let vault_type = pubkey(&vault.vault_type);
+    let vtype: VaultType = ctx.load(&vault_type)?;
+    let debt_type = pubkey(&vtype.debt_type);
+    let debt: DebtType = ctx.load(&debt_type)?;
+
+    let inx = instruction::write_off_vault(
+        &ctx.program_id()?,
+        &pubkey(&debt.treasury),
+        &debt_type,
+        &vault_type,
+        &vault_key,
+    )?;
+    Ok(signature(ctx.send(&[inx], &[])?))
+}
+
+fn amount_arg() -> Arg<'static, 'static> {
+    Arg::with_name("amount")
+        .required(true)
+        .help("Token amount, in base units")
+}
+
+fn pubkey_arg(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name).required(true).help(help)
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("parrot")
+        .about("Operate Parrot debt markets and vaults")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .short("u")
+                .global(true)
+                .default_value("http://127.0.0.1:8899")
+                .help("JSON RPC URL of the cluster"),
+        )
+        .arg(
+            Arg::with_name("keypair")
+                .long("keypair")
+                .short("k")
+                .global(true)
+                .takes_value(true)
+                .help("Wallet keypair file [default: ~/.config/solana/id.json]"),
+        )
+        .arg(
+            Arg::with_name("state")
+                .long("state")
+                .short("s")
+                .global(true)
+                .default_value("parrot.json")
+                .help("Deploy state file"),
+        )
+        .subcommand(
+            SubCommand::with_name("setup")
+                .about("Create a debt type, and a vault type of a test collateral token")
+                .arg(
+                    Arg::with_name("program_id")
+                        .long("program-id")
+                        .takes_value(true)
+                        .help("Address of the deployed program"),
+                )
+                .arg(
+                    Arg::with_name("price")
+                        .long("price")
+                        .default_value("45000")
+                        .help("Debt tokens per collateral token, in whole tokens"),
+                )
+                .arg(
+                    Arg::with_name("decimals")
+                        .long("decimals")
+                        .default_value("9")
+                        .help("Decimals of the debt and collateral token mints"),
+                )
+                .arg(
+                    Arg::with_name("collateral_ratio")
+                        .long("collateral-ratio")
+                        .default_value("15000")
+                        .help("In basis points"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("setup-vault")
+                .about("Create the token accounts and a vault of the wallet")
+                .arg(
+                    Arg::with_name("mint_amount")
+                        .long("mint-amount")
+                        .default_value("1000000000000000")
+                        .help("Test collateral tokens minted to the wallet"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stake")
+                .about("Move collateral into the vault")
+                .arg(amount_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("unstake")
+                .about("Move collateral out of the vault")
+                .arg(amount_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("borrow")
+                .about("Mint debt tokens against the vault collateral")
+                .arg(amount_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("repay")
+                .about("Burn debt tokens to pay back the vault debt")
+                .arg(amount_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Show the collateral and debt of a vault")
+                .arg(Arg::with_name("vault").help("Vault address [default: the wallet's vault]")),
+        )
+        .subcommand(
+            SubCommand::with_name("update-price")
+                .about("Admin: update a price feed")
+                .arg(
+                    Arg::with_name("price")
+                        .required(true)
+                        .help("Debt tokens per collateral token, in whole tokens"),
+                )
+                .arg(
+                    Arg::with_name("price_feed")
+                        .long("price-feed")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("update-vault-type-params")
+                .about("Admin: update the parameters of a vault type")
+                .arg(
+                    Arg::with_name("vault_type")
+                        .long("vault-type")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("collateral_ratio")
+                        .long("collateral-ratio")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("origination_fee")
+                        .long("origination-fee")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("collateral_cap")
+                        .long("collateral-cap")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("liquidation_penalty")
+                        .long("liquidation-penalty")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("stability_fee")
+                        .long("stability-fee")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("swap_program")
+                        .long("swap-program")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("allowlist")
+                        .long("allowlist")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("withdraw-surplus")
+                .about("Admin: mint debt tokens out of the treasury surplus")
+                .arg(amount_arg())
+                .arg(
+                    Arg::with_name("receiver")
+                        .long("receiver")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("add-to-allowlist")
+                .about("Admin: allow an address to open vaults")
+                .arg(pubkey_arg("address", "Vault owner address")),
+        )
+        .subcommand(
+            SubCommand::with_name("remove-from-allowlist")
+                .about("Admin: disallow an address to open vaults")
+                .arg(pubkey_arg("address", "Vault owner address")),
+        )
+        .subcommand(
+            SubCommand::with_name("refresh-lp-pool")
+                .about("Keeper: revalue an lp pool at the current prices")
+                .arg(pubkey_arg("lp_pool", "LpPool address")),
+        )
+        .subcommand(
+            SubCommand::with_name("drip")
+                .about("Keeper: accrue the interest of a savings")
+                .arg(pubkey_arg("savings", "Savings address"))
+                .arg(
+                    Arg::with_name("vault_type")
+                        .long("vault-type")
+                        .takes_value(true)
+                        .multiple(true)
+                        .help("VaultType whose stability fees fund the interest"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("write-off-vault")
+                .about("Keeper: record the debt of an empty vault as bad debt")
+                .arg(pubkey_arg("vault", "Vault address")),
+        )
+}
+
+fn run(args: &ArgMatches) -> Result<Value> {
+    let keypair_path = match args.value_of("keypair") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(std::env::var("HOME")?).join(".config/solana/id.json"),
+    };
+    let payer = read_keypair_file(&keypair_path)
+        .map_err(|err| format!("reading keypair {}: {}", keypair_path.display(), err))?;
+
+    let state_path = PathBuf::from(args.value_of("state").unwrap());
+    let mut ctx = Context {
+        rpc: RpcClient::new(args.value_of("url").unwrap()),
+        payer,
+        state: DeployState::load(&state_path)?,
+        state_path,
+    };
+
+    match args.subcommand() {
+        ("setup", Some(args)) => setup(&mut ctx, args),
+        ("setup-vault", Some(args)) => setup_vault(&mut ctx, args),
+        ("stake", Some(args)) => stake(&mut ctx, args),
+        ("unstake", Some(args)) => unstake(&mut ctx, args),
+        ("borrow", Some(args)) => borrow(&mut ctx, args),
+        ("repay", Some(args)) => repay(&mut ctx, args),
+        ("info", Some(args)) => info(&mut ctx, args),
+        ("update-price", Some(args)) => update_price(&mut ctx, args),
+        ("update-vault-type-params", Some(args)) => update_vault_type_params(&mut ctx, args),
+        ("withdraw-surplus", Some(args)) => withdraw_surplus(&mut ctx, args),
+        ("add-to-allowlist", Some(args)) => update_allowlist(&mut ctx, args, true),
+        ("remove-from-allowlist", Some(args)) => update_allowlist(&mut ctx, args, false),
+        ("refresh-lp-pool", Some(args)) => refresh_lp_pool(&mut ctx, args),
+        ("drip", Some(args)) => drip(&mut ctx, args),
+        ("write-off-vault", Some(args)) => write_off_vault(&mut ctx, args),
+        _ => unreachable!("clap requires a subcommand"),
+    }
+}
+
+fn main() {
+    let args = app().get_matches();
+
+    match run(&args) {
+        Ok(output) => println!("{}", serde_json::to_string_pretty(&output).unwrap()),
+        Err(err) => {
+            println!("{}", json!({ "error": err.to_string() }));
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! The handful of JSON RPC methods the CLI needs
+//!
+//! solana-client pulls in hidapi (for hardware wallets), which needs libudev to
+//! build. Talking JSON RPC directly keeps the CLI buildable anywhere the
+//! program builds.
+
+use std::{
+    convert::TryFrom,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use solana_faucet::error::Error;
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::Signature, transaction::Transaction};
+
+use crate::Result;
+
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct Account {
+    pub owner: Pubkey,
+    pub data: Vec<u8>,
+}
+
+pub struct RpcClient {
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> Self {
+        RpcClient {
+            url: url.to_string(),
+        }
+    }
+
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let response: Value = ureq::post(&self.url)
+            .send_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))?
+            .into_json()?;
+
+        if let Some(error) = response.get("error") {
+            // a failed preflight simulation has the transaction error as data
+            let message = format!("{}: {}", method, error);
+            return Err(describe_program_error(message, &error["data"]["err"]).into());
+        }
+
+        Ok(response["result"].clone())
+    }
+
+    pub fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>> {
+        let result = self.request(
+            "getAccountInfo",
+            json!([pubkey.to_string(), { "encoding": "base64" }]),
+        )?;
+
+        let value = &result["value"];
+        if value.is_null() {
+            return Ok(None);
+        }
+
+        let data = value["data"][0].as_str().ok_or("invalid account data")?;
+        let owner = value["owner"].as_str().ok_or("invalid account owner")?;
+        Ok(Some(Account {
+            owner: owner.parse()?,
+            data: base64::decode(data)?,
+        }))
+    }
+
+    pub fn get_minimum_balance_for_rent_exemption(&self, len: usize) -> Result<u64> {
+        self.request("getMinimumBalanceForRentExemption", json!([len]))?
+            .as_u64()
+            .ok_or_else(|| "invalid rent exemption".into())
+    }
+
+    pub fn get_recent_blockhash(&self) -> Result<Hash> {
+        let result = self.request("getRecentBlockhash", json!([]))?;
+        let blockhash = result["value"]["blockhash"]
+            .as_str()
+            .ok_or("invalid blockhash")?;
+        Ok(blockhash.parse()?)
+    }
+
+    /// Send the transaction, and wait until the cluster confirms it.
+    pub fn send_and_confirm_transaction(&self, transaction: &Transaction) -> Result<Signature> {
+        let wire = base64::encode(bincode::serialize(transaction)?);
+        let signature: Signature = self
+            .request("sendTransaction", json!([wire, { "encoding": "base64" }]))?
+            .as_str()
+            .ok_or("invalid signature")?
+            .parse()?;
+
+        let started = Instant::now();
+        while started.elapsed() < CONFIRM_TIMEOUT {
+            let result = self.request("getSignatureStatuses", json!([[signature.to_string()]]))?;
+
+            let status = &result["value"][0];
+            if !status.is_null() {
+                if !status["err"].is_null() {
+                    let message = format!("transaction {} failed: {}", signature, status["err"]);
+                    return Err(describe_program_error(message, &status["err"]).into());
+                }
+
+                let confirmed = match status["confirmationStatus"].as_str() {
+                    Some(level) => level != "processed",
+                    // older nodes only count confirmations, null once rooted
+                    None => status["confirmations"].as_u64().is_none_or(|n| n > 0),
+                };
+                if confirmed {
+                    return Ok(signature);
+                }
+            }
+
+            sleep(Duration::from_millis(500));
+        }
+
+        Err(format!("transaction {} was not confirmed", signature).into())
+    }
+}
+
+/// Add the name of the program's error to the message, if the transaction
+/// error, e.g. `{"InstructionError":[0,{"Custom":1347551248}]}`, is one.
+fn describe_program_error(message: String, err: &Value) -> String {
+    let error = err["InstructionError"][1]["Custom"]
+        .as_u64()
+        .and_then(|code| u32::try_from(code).ok())
+        .and_then(Error::from_code);
+
+    match error {
+        Some(error) => format!("{}: {:?} ({})", message, error, error),
+        None => message,
+    }
+}
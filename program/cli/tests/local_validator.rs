@@ -0,0 +1,50 @@
+//! Run the CLI against a program deployed on a local validator
+//!
+//! Deploy the program, fund the default wallet, then:
+//!
+//!     PARROT_PROGRAM_ID=<PROGRAM_ID> cargo test -p parrot-cli -- --ignored
+//!
+//! `PARROT_RPC_URL` points at a validator other than the default local one.
+//! The accounts are recorded in a fresh state file, so every run sets up a new
+//! debt type and vault.
+
+use std::{path::Path, process::Command};
+
+use serde_json::Value;
+
+fn parrot(url: &str, state: &Path, args: &[&str]) -> Value {
+    let output = Command::new(env!("CARGO_BIN_EXE_parrot"))
+        .arg("--url")
+        .arg(url)
+        .arg("--state")
+        .arg(state)
+        .args(args)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "parrot {:?}: {}", args, stdout);
+
+    serde_json::from_str(&stdout).unwrap()
+}
+
+#[test]
+#[ignore]
+fn test_cli_local_validator() {
+    let program_id = std::env::var("PARROT_PROGRAM_ID").expect("PARROT_PROGRAM_ID");
+    let url =
+        std::env::var("PARROT_RPC_URL").unwrap_or_else(|_| "http://127.0.0.1:8899".to_string());
+    let state = std::env::temp_dir().join(format!("parrot-cli-test-{}.json", std::process::id()));
+
+    let setup = parrot(&url, &state, &["setup", "--program-id", &program_id]);
+    assert_eq!(setup["state"]["program_id"], program_id.as_str());
+    parrot(&url, &state, &["setup-vault"]);
+
+    parrot(&url, &state, &["stake", "1000000000"]);
+    parrot(&url, &state, &["borrow", "1000"]);
+
+    let info = parrot(&url, &state, &["info"]);
+    assert_eq!(info["collateral_amount"], 1_000_000_000u64);
+    assert_eq!(info["debt_amount"], 1_000u64);
+
+    std::fs::remove_file(&state).unwrap();
+}
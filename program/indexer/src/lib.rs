@@ -0,0 +1,14 @@
+//! Off-chain indexer of Parrot debt types, vault types and vaults
+//!
+//! `scan::scan` loads every account of the program with getProgramAccounts,
+//! filtered by the data size of each state type, and values the vaults at the
+//! prices of their oracles. `store::Store` keeps the snapshots in SQLite, with
+//! aggregate stats per vault type, for dashboards and risk monitors to query.
+
+use std::error::Error;
+
+pub mod rpc;
+pub mod scan;
+pub mod store;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
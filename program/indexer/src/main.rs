@@ -0,0 +1,196 @@
+//! Command line tool to index Parrot vaults into SQLite, and query the index
+//!
+//! `index` snapshots the program accounts, once or every `--interval` seconds.
+//! The other commands read the database, and every command prints its result as
+//! JSON, like the parrot CLI.
+
+use std::{
+    process::exit,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::{value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
+use parrot_indexer::{
+    rpc::RpcClient,
+    scan::scan,
+    store::{Store, VaultRecord, VaultTypeStats},
+    Result,
+};
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+
+fn index_once(rpc: &RpcClient, store: &mut Store, program_id: &Pubkey) -> Result<Value> {
+    let snapshot = scan(rpc, program_id)?;
+    let taken_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let id = store.insert_snapshot(&snapshot, taken_at)?;
+
+    Ok(json!({
+        "snapshot": id,
+        "slot": snapshot.slot,
+        "debt_types": snapshot.debt_types.len(),
+        "vault_types": snapshot.vault_types.len(),
+        "vaults": snapshot.vaults.len(),
+    }))
+}
+
+fn index(store: &mut Store, args: &ArgMatches) -> Result<Value> {
+    let rpc = RpcClient::new(args.value_of("url").unwrap());
+    let program_id: Pubkey = args.value_of("program_id").unwrap().parse()?;
+
+    if !args.is_present("interval") {
+        return index_once(&rpc, store, &program_id);
+    }
+
+    // keep going through RPC failures, a monitor shouldn't die with the node
+    let interval = Duration::from_secs(value_t!(args, "interval", u64)?);
+    loop {
+        match index_once(&rpc, store, &program_id) {
+            Ok(output) => println!("{}", output),
+            Err(err) => println!("{}", json!({ "error": err.to_string() })),
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn stats_json(stats: &[VaultTypeStats]) -> Value {
+    stats
+        .iter()
+        .map(|stats| {
+            json!({
+                "slot": stats.slot,
+                "taken_at": stats.taken_at,
+                "vault_type": stats.vault_type.to_string(),
+                "vault_count": stats.vault_count,
+                "collateral_amount": stats.collateral_amount,
+                "debt_amount": stats.debt_amount,
+                "collateral_value": stats.collateral_value,
+                "max_debt": stats.max_debt,
+                "unhealthy_count": stats.unhealthy_count,
+            })
+        })
+        .collect()
+}
+
+fn vaults_json(records: &[VaultRecord]) -> Value {
+    records
+        .iter()
+        .map(|record| {
+            json!({
+                "slot": record.slot,
+                "taken_at": record.taken_at,
+                "vault": record.address.to_string(),
+                "vault_type": record.vault_type.to_string(),
+                "owner": record.owner.to_string(),
+                "collateral_amount": record.collateral_amount,
+                "debt_amount": record.debt_amount,
+                "collateral_value": record.collateral_value,
+                "max_debt": record.max_debt,
+                "collateral_ratio": record.collateral_ratio,
+                "healthy": record.healthy,
+            })
+        })
+        .collect()
+}
+
+fn stats(store: &mut Store, args: &ArgMatches) -> Result<Value> {
+    let stats = match args.value_of("vault_type") {
+        Some(vault_type) => store.stats_history(&vault_type.parse()?)?,
+        None => store.latest_stats()?,
+    };
+    Ok(stats_json(&stats))
+}
+
+fn vault(store: &mut Store, args: &ArgMatches) -> Result<Value> {
+    let vault = args.value_of("vault").unwrap().parse()?;
+    Ok(vaults_json(&store.vault_history(&vault)?))
+}
+
+fn unhealthy(store: &mut Store, _args: &ArgMatches) -> Result<Value> {
+    Ok(vaults_json(&store.unhealthy_vaults()?))
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("parrot-indexer")
+        .about("Index Parrot vaults into SQLite, and query the index")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .short("u")
+                .global(true)
+                .default_value("http://127.0.0.1:8899")
+                .help("JSON RPC URL of the cluster"),
+        )
+        .arg(
+            Arg::with_name("db")
+                .long("db")
+                .short("d")
+                .global(true)
+                .default_value("parrot-index.db")
+                .help("SQLite database file"),
+        )
+        .subcommand(
+            SubCommand::with_name("index")
+                .about("Snapshot the debt types, vault types and vaults of the program")
+                .arg(
+                    Arg::with_name("program_id")
+                        .long("program-id")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Address of the deployed program"),
+                )
+                .arg(
+                    Arg::with_name("interval")
+                        .long("interval")
+                        .takes_value(true)
+                        .help("Keep snapshotting every this many seconds"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Stats of every vault type in the latest snapshot")
+                .arg(
+                    Arg::with_name("vault_type")
+                        .long("vault-type")
+                        .takes_value(true)
+                        .help("Stats of the vault type in every snapshot instead"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("vault")
+                .about("The vault in every snapshot")
+                .arg(
+                    Arg::with_name("vault")
+                        .required(true)
+                        .help("Address of the vault"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("unhealthy").about("The unhealthy vaults in the latest snapshot"),
+        )
+}
+
+fn run(args: &ArgMatches) -> Result<Value> {
+    let mut store = Store::open(args.value_of("db").unwrap())?;
+
+    match args.subcommand() {
+        ("index", Some(args)) => index(&mut store, args),
+        ("stats", Some(args)) => stats(&mut store, args),
+        ("vault", Some(args)) => vault(&mut store, args),
+        ("unhealthy", Some(args)) => unhealthy(&mut store, args),
+        _ => unreachable!(),
+    }
+}
+
+fn main() {
+    let args = app().get_matches();
+
+    match run(&args) {
+        Ok(output) => println!("{}", serde_json::to_string_pretty(&output).unwrap()),
+        Err(err) => {
+            println!("{}", json!({ "error": err.to_string() }));
+            exit(1);
+        }
+    }
+}
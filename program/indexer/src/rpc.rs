@@ -0,0 +1,104 @@
+//! The JSON RPC methods the indexer needs
+//!
+//! Like the CLI, this talks JSON RPC directly rather than through
+//! solana-client, which needs libudev to build.
+
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::Result;
+
+/// Owner and data of an account.
+pub type Account = (Pubkey, Vec<u8>);
+
+pub struct RpcClient {
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> Self {
+        RpcClient {
+            url: url.to_string(),
+        }
+    }
+
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let response: Value = ureq::post(&self.url)
+            .send_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))?
+            .into_json()?;
+
+        if let Some(error) = response.get("error") {
+            return Err(format!("{}: {}", method, error).into());
+        }
+
+        Ok(response["result"].clone())
+    }
+
+    pub fn get_slot(&self) -> Result<u64> {
+        self.request("getSlot", json!([{ "commitment": "confirmed" }]))?
+            .as_u64()
+            .ok_or_else(|| "invalid slot".into())
+    }
+
+    /// Accounts owned by the program with exactly `data_size` bytes of data.
+    pub fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        data_size: usize,
+    ) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let result = self.request(
+            "getProgramAccounts",
+            json!([
+                program_id.to_string(),
+                {
+                    "commitment": "confirmed",
+                    "encoding": "base64",
+                    "filters": [{ "dataSize": data_size }],
+                },
+            ]),
+        )?;
+
+        result
+            .as_array()
+            .ok_or("invalid program accounts")?
+            .iter()
+            .map(|keyed| {
+                let pubkey = keyed["pubkey"].as_str().ok_or("invalid account pubkey")?;
+                Ok((pubkey.parse()?, account_data(&keyed["account"])?))
+            })
+            .collect()
+    }
+
+    /// Owner and data of each account, None for one that doesn't exist.
+    pub fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        let keys: Vec<String> = pubkeys.iter().map(|key| key.to_string()).collect();
+        let result = self.request(
+            "getMultipleAccounts",
+            json!([keys, { "commitment": "confirmed", "encoding": "base64" }]),
+        )?;
+
+        result["value"]
+            .as_array()
+            .ok_or("invalid accounts")?
+            .iter()
+            .map(|account| {
+                if account.is_null() {
+                    return Ok(None);
+                }
+
+                let owner = account["owner"].as_str().ok_or("invalid account owner")?;
+                Ok(Some((owner.parse()?, account_data(account)?)))
+            })
+            .collect()
+    }
+}
+
+fn account_data(account: &Value) -> Result<Vec<u8>> {
+    let data = account["data"][0].as_str().ok_or("invalid account data")?;
+    Ok(base64::decode(data)?)
+}
@@ -0,0 +1,159 @@
+//! Scan the program accounts into a snapshot
+//!
+//! Debt types, vault types and vaults are told apart by their data size, which
+//! is unique to each state type. Every vault is valued with the price oracle of
+//! its vault type, with the same health math as the program.
+
+use std::collections::HashMap;
+
+use borsh::{BorshDeserialize, BorshSchema};
+use solana_faucet::{
+    borsh_utils::get_packed_len,
+    health::{vault_health, Health, Price},
+    state::{CollateralPricing, DebtType, LpPool, PriceFeed, PublicKey, Vault, VaultType},
+};
+use solana_sdk::{program_pack::IsInitialized, pubkey::Pubkey};
+
+use crate::{rpc::RpcClient, Result};
+
+// getMultipleAccounts takes at most 100 keys
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+pub struct VaultEntry {
+    pub address: Pubkey,
+    pub vault: Vault,
+    // None if the vault type's collateral couldn't be priced
+    pub health: Option<Health>,
+}
+
+pub struct Snapshot {
+    // slot when the scan started
+    pub slot: u64,
+    pub debt_types: Vec<(Pubkey, DebtType)>,
+    pub vault_types: Vec<(Pubkey, VaultType)>,
+    pub vaults: Vec<VaultEntry>,
+}
+
+/// Load all the debt types, vault types and vaults of the program.
+pub fn scan(rpc: &RpcClient, program_id: &Pubkey) -> Result<Snapshot> {
+    let slot = rpc.get_slot()?;
+    let debt_types = load_all::<DebtType>(rpc, program_id)?;
+    let vault_types = load_all::<VaultType>(rpc, program_id)?;
+    let vaults = load_all::<Vault>(rpc, program_id)?;
+
+    let oracle_keys: Vec<Pubkey> = vault_types
+        .iter()
+        .map(|(_, vault_type)| pubkey(&vault_type.price_oracle))
+        .collect();
+    let mut oracles = vec![];
+    for keys in oracle_keys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        oracles.extend(rpc.get_multiple_accounts(keys)?);
+    }
+
+    let prices = vault_types
+        .iter()
+        .zip(oracles)
+        .filter_map(|((key, vault_type), oracle)| {
+            let (owner, data) = oracle?;
+            if owner != *program_id {
+                return None;
+            }
+            Some((*key, price(vault_type, &data)?))
+        })
+        .collect();
+
+    Ok(Snapshot::new(
+        slot,
+        debt_types,
+        vault_types,
+        vaults,
+        &prices,
+    ))
+}
+
+impl Snapshot {
+    /// Value the vaults at the prices of their vault types.
+    pub fn new(
+        slot: u64,
+        debt_types: Vec<(Pubkey, DebtType)>,
+        vault_types: Vec<(Pubkey, VaultType)>,
+        vaults: Vec<(Pubkey, Vault)>,
+        prices: &HashMap<Pubkey, Price>,
+    ) -> Snapshot {
+        let types: HashMap<Pubkey, &VaultType> =
+            vault_types.iter().map(|(key, vt)| (*key, vt)).collect();
+
+        let vaults = vaults
+            .into_iter()
+            .map(|(address, vault)| {
+                let vault_type = pubkey(&vault.vault_type);
+                let health = match (types.get(&vault_type), prices.get(&vault_type)) {
+                    (Some(vault_type), Some(price)) => vault_health(&vault, vault_type, price).ok(),
+                    _ => None,
+                };
+
+                VaultEntry {
+                    address,
+                    vault,
+                    health,
+                }
+            })
+            .collect();
+
+        Snapshot {
+            slot,
+            debt_types,
+            vault_types,
+            vaults,
+        }
+    }
+}
+
+/// Load the initialized program accounts of a state type.
+fn load_all<T>(rpc: &RpcClient, program_id: &Pubkey) -> Result<Vec<(Pubkey, T)>>
+where
+    T: BorshDeserialize + BorshSchema + IsInitialized,
+{
+    let accounts = rpc.get_program_accounts(program_id, get_packed_len::<T>())?;
+    decode_all(accounts)
+}
+
+/// Decode the accounts, skipping those allocated but not yet initialized.
+pub fn decode_all<T>(accounts: Vec<(Pubkey, Vec<u8>)>) -> Result<Vec<(Pubkey, T)>>
+where
+    T: BorshDeserialize + IsInitialized,
+{
+    let mut states = vec![];
+    for (key, data) in accounts {
+        let state = T::try_from_slice(&data).map_err(|err| format!("decoding {}: {}", key, err))?;
+        if state.is_initialized() {
+            states.push((key, state));
+        }
+    }
+
+    Ok(states)
+}
+
+/// Price of the collateral of the vault type from its oracle account data.
+fn price(vault_type: &VaultType, oracle: &[u8]) -> Option<Price> {
+    match vault_type.pricing {
+        CollateralPricing::PriceFeed => {
+            let feed = PriceFeed::try_from_slice(oracle).ok()?;
+            if !feed.is_initialized {
+                return None;
+            }
+            Price::from_price_feed(&feed, vault_type).ok()
+        }
+        CollateralPricing::LpPool => {
+            let pool = LpPool::try_from_slice(oracle).ok()?;
+            if !pool.is_initialized {
+                return None;
+            }
+            Price::from_lp_pool(&pool, vault_type).ok()
+        }
+    }
+}
+
+pub fn pubkey(key: &PublicKey) -> Pubkey {
+    Pubkey::new_from_array(key.0)
+}
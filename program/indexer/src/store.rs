@@ -0,0 +1,471 @@
+//! SQLite store of the snapshots
+//!
+//! Every snapshot keeps a row per debt type, vault type and vault, so the
+//! history of any of them can be queried, plus aggregate stats per vault type
+//! computed from its vaults when the snapshot is inserted. Accounts are keyed
+//! by their base58 address.
+//!
+//! Amounts are SQLite integers, which are signed: an amount above i64::MAX fails
+//! the insert rather than wrap.
+
+use std::path::Path;
+
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    scan::{pubkey, Snapshot},
+    Result,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS snapshots (
+    id INTEGER PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    -- unix timestamp
+    taken_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS debt_types (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id),
+    address TEXT NOT NULL,
+    debt_token TEXT NOT NULL,
+    decimals INTEGER NOT NULL,
+    owner TEXT NOT NULL,
+    treasury TEXT NOT NULL,
+    PRIMARY KEY (snapshot_id, address)
+);
+
+CREATE TABLE IF NOT EXISTS vault_types (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id),
+    address TEXT NOT NULL,
+    debt_type TEXT NOT NULL,
+    collateral_token TEXT NOT NULL,
+    price_oracle TEXT NOT NULL,
+    -- basis points
+    collateral_ratio INTEGER NOT NULL,
+    origination_fee INTEGER NOT NULL,
+    collateral_cap INTEGER NOT NULL,
+    collateral_amount INTEGER NOT NULL,
+    PRIMARY KEY (snapshot_id, address)
+);
+
+CREATE TABLE IF NOT EXISTS vaults (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id),
+    address TEXT NOT NULL,
+    vault_type TEXT NOT NULL,
+    owner TEXT NOT NULL,
+    collateral_amount INTEGER NOT NULL,
+    debt_amount INTEGER NOT NULL,
+    -- in debt tokens, NULL if the collateral couldn't be priced
+    collateral_value INTEGER,
+    max_debt INTEGER,
+    -- basis points, also NULL without debt
+    collateral_ratio INTEGER,
+    healthy INTEGER,
+    PRIMARY KEY (snapshot_id, address)
+);
+
+CREATE INDEX IF NOT EXISTS vaults_by_address ON vaults (address, snapshot_id);
+
+CREATE TABLE IF NOT EXISTS vault_type_stats (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id),
+    vault_type TEXT NOT NULL,
+    vault_count INTEGER NOT NULL,
+    collateral_amount INTEGER NOT NULL,
+    debt_amount INTEGER NOT NULL,
+    collateral_value INTEGER,
+    max_debt INTEGER,
+    unhealthy_count INTEGER NOT NULL,
+    PRIMARY KEY (snapshot_id, vault_type)
+);
+";
+
+// the vaults of a vault type that couldn't be priced have no value, but a vault
+// type without vaults is worth 0
+const INSERT_STATS: &str = "
+INSERT INTO vault_type_stats
+SELECT
+    t.snapshot_id,
+    t.address,
+    COUNT(v.address),
+    COALESCE(SUM(v.collateral_amount), 0),
+    COALESCE(SUM(v.debt_amount), 0),
+    CASE WHEN COUNT(v.address) = 0 THEN 0 ELSE SUM(v.collateral_value) END,
+    CASE WHEN COUNT(v.address) = 0 THEN 0 ELSE SUM(v.max_debt) END,
+    COUNT(CASE WHEN v.healthy = 0 THEN 1 END)
+FROM vault_types t
+LEFT JOIN vaults v ON v.snapshot_id = t.snapshot_id AND v.vault_type = t.address
+WHERE t.snapshot_id = ?1
+GROUP BY t.address
+";
+
+const SELECT_STATS: &str = "
+SELECT s.slot, s.taken_at, st.vault_type, st.vault_count, st.collateral_amount,
+    st.debt_amount, st.collateral_value, st.max_debt, st.unhealthy_count
+FROM vault_type_stats st
+JOIN snapshots s ON s.id = st.snapshot_id
+";
+
+const SELECT_VAULTS: &str = "
+SELECT s.slot, s.taken_at, v.address, v.vault_type, v.owner, v.collateral_amount,
+    v.debt_amount, v.collateral_value, v.max_debt, v.collateral_ratio, v.healthy
+FROM vaults v
+JOIN snapshots s ON s.id = v.snapshot_id
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VaultTypeStats {
+    pub slot: u64,
+    pub taken_at: i64,
+    pub vault_type: Pubkey,
+    pub vault_count: u64,
+    pub collateral_amount: u64,
+    pub debt_amount: u64,
+    pub collateral_value: Option<u64>,
+    pub max_debt: Option<u64>,
+    pub unhealthy_count: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VaultRecord {
+    pub slot: u64,
+    pub taken_at: i64,
+    pub address: Pubkey,
+    pub vault_type: Pubkey,
+    pub owner: Pubkey,
+    pub collateral_amount: u64,
+    pub debt_amount: u64,
+    pub collateral_value: Option<u64>,
+    pub max_debt: Option<u64>,
+    pub collateral_ratio: Option<u64>,
+    pub healthy: Option<bool>,
+}
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Store> {
+        Store::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Store> {
+        Store::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Store> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Store { conn })
+    }
+
+    /// Insert the snapshot and its stats, taken at the unix timestamp. Returns
+    /// the snapshot id.
+    pub fn insert_snapshot(&mut self, snapshot: &Snapshot, taken_at: i64) -> Result<i64> {
+        let tx = self.conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO snapshots (slot, taken_at) VALUES (?1, ?2)",
+            params![snapshot.slot, taken_at],
+        )?;
+        let id = tx.last_insert_rowid();
+
+        {
+            let mut insert =
+                tx.prepare("INSERT INTO debt_types VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+            for (address, debt_type) in &snapshot.debt_types {
+                insert.execute(params![
+                    id,
+                    address.to_string(),
+                    pubkey(&debt_type.debt_token).to_string(),
+                    debt_type.decimals,
+                    pubkey(&debt_type.owner).to_string(),
+                    pubkey(&debt_type.treasury).to_string(),
+                ])?;
+            }
+
+            let mut insert =
+                tx.prepare("INSERT INTO vault_types VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")?;
+            for (address, vault_type) in &snapshot.vault_types {
+                insert.execute(params![
+                    id,
+                    address.to_string(),
+                    pubkey(&vault_type.debt_type).to_string(),
+                    pubkey(&vault_type.collateral_token).to_string(),
+                    pubkey(&vault_type.price_oracle).to_string(),
+                    vault_type.params.collateral_ratio,
+                    vault_type.params.origination_fee,
+                    vault_type.params.collateral_cap,
+                    vault_type.collateral_amount,
+                ])?;
+            }
+
+            let mut insert =
+                tx.prepare("INSERT INTO vaults VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)")?;
+            for entry in &snapshot.vaults {
+                let vault = &entry.vault;
+                let health = entry.health.as_ref();
+                insert.execute(params![
+                    id,
+                    entry.address.to_string(),
+                    pubkey(&vault.vault_type).to_string(),
+                    pubkey(&vault.owner).to_string(),
+                    vault.collateral_amount,
+                    vault.debt_amount,
+                    health.map(|health| health.collateral_value),
+                    health.map(|health| health.max_debt),
+                    health.and_then(|health| health.collateral_ratio),
+                    health.map(|health| health.is_healthy()),
+                ])?;
+            }
+
+            tx.execute(INSERT_STATS, params![id])?;
+        }
+
+        tx.commit()?;
+
+        Ok(id)
+    }
+
+    /// Stats of every vault type in the latest snapshot.
+    pub fn latest_stats(&self) -> Result<Vec<VaultTypeStats>> {
+        let latest = match self.latest_snapshot()? {
+            Some(id) => id,
+            None => return Ok(vec![]),
+        };
+
+        let mut query = self.conn.prepare(&format!(
+            "{} WHERE st.snapshot_id = ?1 ORDER BY st.vault_type",
+            SELECT_STATS
+        ))?;
+        let stats = query
+            .query_map(params![latest], stats_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(stats)
+    }
+
+    /// Stats of the vault type in every snapshot, oldest first.
+    pub fn stats_history(&self, vault_type: &Pubkey) -> Result<Vec<VaultTypeStats>> {
+        let mut query = self.conn.prepare(&format!(
+            "{} WHERE st.vault_type = ?1 ORDER BY s.id",
+            SELECT_STATS
+        ))?;
+        let stats = query
+            .query_map(params![vault_type.to_string()], stats_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(stats)
+    }
+
+    /// The vault in every snapshot, oldest first.
+    pub fn vault_history(&self, vault: &Pubkey) -> Result<Vec<VaultRecord>> {
+        let mut query = self.conn.prepare(&format!(
+            "{} WHERE v.address = ?1 ORDER BY s.id",
+            SELECT_VAULTS
+        ))?;
+        let records = query
+            .query_map(params![vault.to_string()], vault_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(records)
+    }
+
+    /// The unhealthy vaults in the latest snapshot.
+    pub fn unhealthy_vaults(&self) -> Result<Vec<VaultRecord>> {
+        let latest = match self.latest_snapshot()? {
+            Some(id) => id,
+            None => return Ok(vec![]),
+        };
+
+        let mut query = self.conn.prepare(&format!(
+            "{} WHERE v.snapshot_id = ?1 AND v.healthy = 0 ORDER BY v.address",
+            SELECT_VAULTS
+        ))?;
+        let records = query
+            .query_map(params![latest], vault_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(records)
+    }
+
+    fn latest_snapshot(&self) -> Result<Option<i64>> {
+        let id = self
+            .conn
+            .query_row("SELECT MAX(id) FROM snapshots", [], |row| row.get(0))
+            .optional()?;
+        Ok(id.flatten())
+    }
+}
+
+fn get_pubkey(row: &Row, index: usize) -> rusqlite::Result<Pubkey> {
+    let key: String = row.get(index)?;
+    key.parse()
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(err)))
+}
+
+fn stats_from_row(row: &Row) -> rusqlite::Result<VaultTypeStats> {
+    Ok(VaultTypeStats {
+        slot: row.get(0)?,
+        taken_at: row.get(1)?,
+        vault_type: get_pubkey(row, 2)?,
+        vault_count: row.get(3)?,
+        collateral_amount: row.get(4)?,
+        debt_amount: row.get(5)?,
+        collateral_value: row.get(6)?,
+        max_debt: row.get(7)?,
+        unhealthy_count: row.get(8)?,
+    })
+}
+
+fn vault_from_row(row: &Row) -> rusqlite::Result<VaultRecord> {
+    Ok(VaultRecord {
+        slot: row.get(0)?,
+        taken_at: row.get(1)?,
+        address: get_pubkey(row, 2)?,
+        vault_type: get_pubkey(row, 3)?,
+        owner: get_pubkey(row, 4)?,
+        collateral_amount: row.get(5)?,
+        debt_amount: row.get(6)?,
+        collateral_value: row.get(7)?,
+        max_debt: row.get(8)?,
+        collateral_ratio: row.get(9)?,
+        healthy: row.get(10)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use solana_faucet::{
+        health::Price,
+        state::{DebtType, PublicKey, Vault, VaultType, VaultTypeParams},
+    };
+
+    use super::*;
+
+    fn vault(vault_type: &Pubkey, collateral_amount: u64, debt_amount: u64) -> (Pubkey, Vault) {
+        let vault = Vault {
+            is_initialized: true,
+            vault_type: PublicKey(vault_type.to_bytes()),
+            owner: PublicKey(Pubkey::new_unique().to_bytes()),
+            collateral_amount,
+            debt_amount,
+            ..Vault::default()
+        };
+        (Pubkey::new_unique(), vault)
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let mut store = Store::open_in_memory().unwrap();
+        assert_eq!(store.latest_stats().unwrap(), vec![]);
+
+        let debt_type = Pubkey::new_unique();
+        let vault_type = Pubkey::new_unique();
+        let empty_type = Pubkey::new_unique();
+        let debt_types = vec![(debt_type, DebtType::default())];
+        let vault_types = vec![
+            (
+                vault_type,
+                VaultType {
+                    params: VaultTypeParams {
+                        collateral_ratio: 15_000,
+                        ..VaultTypeParams::default()
+                    },
+                    ..VaultType::default()
+                },
+            ),
+            (empty_type, VaultType::default()),
+        ];
+
+        // 2 debt per collateral
+        let mut prices = HashMap::new();
+        prices.insert(
+            vault_type,
+            Price {
+                value: 2,
+                amount: 1,
+            },
+        );
+
+        let healthy = vault(&vault_type, 1_000, 1_000);
+        let unhealthy = vault(&vault_type, 1_000, 1_500);
+        let snapshot = Snapshot::new(
+            10,
+            debt_types.clone(),
+            vault_types.clone(),
+            vec![healthy.clone(), unhealthy.clone()],
+            &prices,
+        );
+        store.insert_snapshot(&snapshot, 1_000).unwrap();
+
+        let mut stats = store.latest_stats().unwrap();
+        stats.sort_by_key(|stats| stats.vault_count);
+        assert_eq!(
+            stats,
+            vec![
+                VaultTypeStats {
+                    slot: 10,
+                    taken_at: 1_000,
+                    vault_type: empty_type,
+                    vault_count: 0,
+                    collateral_amount: 0,
+                    debt_amount: 0,
+                    collateral_value: Some(0),
+                    max_debt: Some(0),
+                    unhealthy_count: 0,
+                },
+                VaultTypeStats {
+                    slot: 10,
+                    taken_at: 1_000,
+                    vault_type,
+                    vault_count: 2,
+                    collateral_amount: 2_000,
+                    debt_amount: 2_500,
+                    collateral_value: Some(4_000),
+                    max_debt: Some(2_666),
+                    unhealthy_count: 1,
+                },
+            ]
+        );
+
+        let unhealthy_vaults = store.unhealthy_vaults().unwrap();
+        assert_eq!(unhealthy_vaults.len(), 1);
+        assert_eq!(unhealthy_vaults[0].address, unhealthy.0);
+        assert_eq!(unhealthy_vaults[0].collateral_ratio, Some(13_333));
+
+        // the vault repaid, and the price is gone
+        let repaid = (
+            healthy.0,
+            Vault {
+                debt_amount: 0,
+                ..healthy.1.clone()
+            },
+        );
+        let snapshot = Snapshot::new(
+            11,
+            debt_types,
+            vault_types,
+            vec![repaid, unhealthy],
+            &HashMap::new(),
+        );
+        store.insert_snapshot(&snapshot, 1_001).unwrap();
+
+        let history = store.vault_history(&healthy.0).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].debt_amount, 1_000);
+        assert_eq!(history[0].healthy, Some(true));
+        assert_eq!(history[1].debt_amount, 0);
+        assert_eq!(history[1].healthy, None);
+
+        let history = store.stats_history(&vault_type).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].slot, 11);
+        assert_eq!(history[1].debt_amount, 1_500);
+        assert_eq!(history[1].collateral_value, None);
+        assert_eq!(store.unhealthy_vaults().unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,40 @@
+//! Index a program deployed on a local validator
+//!
+//! Deploy the program and set up a vault with the parrot CLI, then:
+//!
+//!     PARROT_PROGRAM_ID=<PROGRAM_ID> cargo test -p parrot-indexer -- --ignored
+//!
+//! `PARROT_RPC_URL` points at a validator other than the default local one.
+
+use parrot_indexer::{rpc::RpcClient, scan::scan, store::Store};
+
+#[test]
+#[ignore]
+fn test_index_local_validator() {
+    let program_id = std::env::var("PARROT_PROGRAM_ID")
+        .expect("PARROT_PROGRAM_ID")
+        .parse()
+        .unwrap();
+    let url =
+        std::env::var("PARROT_RPC_URL").unwrap_or_else(|_| "http://127.0.0.1:8899".to_string());
+    let rpc = RpcClient::new(&url);
+
+    let snapshot = scan(&rpc, &program_id).unwrap();
+    assert!(!snapshot.debt_types.is_empty());
+    assert!(!snapshot.vault_types.is_empty());
+
+    let mut store = Store::open_in_memory().unwrap();
+    store.insert_snapshot(&snapshot, 0).unwrap();
+
+    // a vault type per stats row, and every vault is counted once
+    let stats = store.latest_stats().unwrap();
+    assert_eq!(stats.len(), snapshot.vault_types.len());
+    let vault_count: u64 = stats.iter().map(|stats| stats.vault_count).sum();
+    assert_eq!(vault_count, snapshot.vaults.len() as u64);
+
+    for entry in &snapshot.vaults {
+        let history = store.vault_history(&entry.address).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].debt_amount, entry.vault.debt_amount);
+    }
+}
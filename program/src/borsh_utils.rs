@@ -1,6 +1,9 @@
 //! Borsh utils
-use borsh::schema::{BorshSchema, Declaration, Definition, Fields};
-use std::collections::HashMap;
+use borsh::{
+    schema::{BorshSchema, Declaration, Definition, Fields},
+    BorshDeserialize,
+};
+use std::{collections::HashMap, convert::TryInto, io};
 
 /// Get packed length for the given BorchSchema Declaration
 fn get_declaration_packed_len(
@@ -54,3 +57,18 @@ pub fn get_packed_len<S: BorshSchema>() -> usize {
     let schema_container = S::schema_container();
     get_declaration_packed_len(&schema_container.declaration, &schema_container.definitions)
 }
+
+/// Deserialize a fixed size array. Borsh only derives that for arrays of Copy
+/// types.
+pub fn deserialize_array<T: BorshDeserialize, const N: usize>(
+    buf: &mut &[u8],
+) -> io::Result<[T; N]> {
+    let mut items = Vec::with_capacity(N);
+    for _ in 0..N {
+        items.push(T::deserialize(buf)?);
+    }
+
+    items
+        .try_into()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "array length"))
+}
@@ -0,0 +1,449 @@
+//! Cross program invocation helpers, for programs composing with this one
+//!
+//! Build with the `no-entrypoint` feature to link the program as a library. The
+//! helpers take the accounts of an instruction in a struct, and pass
+//! `signer_seeds` through to `invoke_signed` so that a calling program can act
+//! for its program accounts, e.g. a vault owned by a strategy.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{borsh_state::InitBorshState, error::Error, instruction};
+
+/// Load a state account of the program, after checking that the program owns
+/// it. A state account owned by another program could hold anything.
+pub fn load<T: InitBorshState>(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+) -> Result<T, ProgramError> {
+    if account.owner.ne(program_id) {
+        return Err(Error::UnexpectedProgramAccount.into());
+    }
+
+    T::load_initialized(account)
+}
+
+fn metas(accounts: &[AccountInfo]) -> Vec<AccountMeta> {
+    accounts
+        .iter()
+        .map(|account| AccountMeta {
+            pubkey: *account.key,
+            is_signer: account.is_signer,
+            is_writable: account.is_writable,
+        })
+        .collect()
+}
+
+fn invoke<'a>(
+    instruction: &Instruction,
+    program: &AccountInfo<'a>,
+    accounts: &[&AccountInfo<'a>],
+    extra_accounts: &[AccountInfo<'a>],
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let mut account_infos = Vec::with_capacity(1 + accounts.len() + extra_accounts.len());
+    account_infos.push(program.clone());
+    account_infos.extend(accounts.iter().map(|account| (*account).clone()));
+    account_infos.extend_from_slice(extra_accounts);
+
+    invoke_signed(instruction, &account_infos, signer_seeds)
+}
+
+pub struct InitVault<'a> {
+    pub vault: &'a AccountInfo<'a>, // writable, allocated to the program
+    pub vault_type: &'a AccountInfo<'a>,
+    pub rent: &'a AccountInfo<'a>,
+
+    pub position_token: Option<PositionToken<'a>>,
+    pub allowlist: Option<&'a AccountInfo<'a>>,
+}
+
+/// The new position token of a tokenized vault.
+pub struct PositionToken<'a> {
+    pub token_program: &'a AccountInfo<'a>,
+
+    pub mint: &'a AccountInfo<'a>, // writable, allocated to spl-token
+    pub account: &'a AccountInfo<'a>, // writable, allocated to spl-token
+    pub mint_authority: &'a AccountInfo<'a>,
+}
+
+pub fn init_vault<'a>(
+    program: &AccountInfo<'a>,
+    accounts: InitVault<'a>,
+    owner: &Pubkey,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let inx = instruction::init_vault(
+        program.key,
+        accounts.vault.key,
+        accounts.vault_type.key,
+        owner,
+        accounts
+            .position_token
+            .as_ref()
+            .map(|position| (position.mint.key, position.account.key)),
+        accounts.allowlist.map(|account| account.key),
+    )?;
+
+    let mut account_infos = vec![accounts.rent, accounts.vault];
+    if let Some(position) = &accounts.position_token {
+        account_infos.extend(vec![
+            position.mint,
+            position.account,
+            position.mint_authority,
+            position.token_program,
+        ]);
+    }
+    account_infos.push(accounts.vault_type);
+    account_infos.extend(accounts.allowlist);
+
+    invoke(&inx, program, &account_infos, &[], signer_seeds)
+}
+
+pub struct Stake<'a> {
+    pub token_program: &'a AccountInfo<'a>,
+
+    pub collateral_from: &'a AccountInfo<'a>, // writable
+    pub collateral_from_authority: &'a AccountInfo<'a>, // signed
+    pub collateral_holder: &'a AccountInfo<'a>, // writable
+
+    pub vault_type: &'a AccountInfo<'a>, // writable
+    pub vault: &'a AccountInfo<'a>,      // writable
+
+    // native accounts, for a native vault type
+    pub native_accounts: &'a [AccountInfo<'a>],
+}
+
+pub fn stake<'a>(
+    program: &AccountInfo<'a>,
+    accounts: Stake<'a>,
+    amount: u64,
+    collateral_holder_nonce: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let inx = instruction::stake(
+        program.key,
+        accounts.collateral_from.key,
+        accounts.collateral_from_authority.key,
+        accounts.collateral_holder.key,
+        accounts.vault_type.key,
+        accounts.vault.key,
+        &metas(accounts.native_accounts),
+        amount,
+        collateral_holder_nonce,
+    )?;
+
+    invoke(
+        &inx,
+        program,
+        &[
+            accounts.token_program,
+            accounts.collateral_from,
+            accounts.collateral_from_authority,
+            accounts.collateral_holder,
+            accounts.vault_type,
+            accounts.vault,
+        ],
+        accounts.native_accounts,
+        signer_seeds,
+    )
+}
+
+pub struct Unstake<'a> {
+    pub token_program: &'a AccountInfo<'a>,
+
+    pub collateral_holder: &'a AccountInfo<'a>, // writable
+    pub collateral_holder_authority: &'a AccountInfo<'a>,
+    pub collateral_to: &'a AccountInfo<'a>, // writable
+
+    pub vault_type: &'a AccountInfo<'a>,  // writable
+    pub vault: &'a AccountInfo<'a>,       // writable
+    pub vault_owner: &'a AccountInfo<'a>, // signed, owner or delegate
+
+    pub price_oracle: &'a AccountInfo<'a>,
+
+    // native accounts for a native vault type
+    pub native_accounts: &'a [AccountInfo<'a>],
+    // required if the vault is tokenized
+    pub position_account: Option<&'a AccountInfo<'a>>,
+    // required if the owner isn't signing
+    pub vault_delegate: Option<&'a AccountInfo<'a>>,
+}
+
+pub fn unstake<'a>(
+    program: &AccountInfo<'a>,
+    accounts: Unstake<'a>,
+    amount: u64,
+    collateral_holder_nonce: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let inx = instruction::unstake(
+        program.key,
+        accounts.collateral_holder.key,
+        accounts.collateral_holder_authority.key,
+        accounts.collateral_to.key,
+        accounts.vault_type.key,
+        accounts.vault.key,
+        accounts.vault_owner.key,
+        accounts.price_oracle.key,
+        &metas(accounts.native_accounts),
+        accounts.position_account.map(|account| account.key),
+        accounts.vault_delegate.map(|account| account.key),
+        amount,
+        collateral_holder_nonce,
+    )?;
+
+    let mut extra_accounts = accounts.native_accounts.to_vec();
+    extra_accounts.extend(accounts.position_account.cloned());
+    extra_accounts.extend(accounts.vault_delegate.cloned());
+
+    invoke(
+        &inx,
+        program,
+        &[
+            accounts.token_program,
+            accounts.collateral_holder,
+            accounts.collateral_holder_authority,
+            accounts.collateral_to,
+            accounts.vault_type,
+            accounts.vault,
+            accounts.vault_owner,
+            accounts.price_oracle,
+        ],
+        &extra_accounts,
+        signer_seeds,
+    )
+}
+
+pub struct Borrow<'a> {
+    pub token_program: &'a AccountInfo<'a>,
+
+    pub debt_token: &'a AccountInfo<'a>, // writable
+    pub debt_minter: &'a AccountInfo<'a>,
+    pub debt_receiver: &'a AccountInfo<'a>, // writable
+
+    pub debt_type: &'a AccountInfo<'a>,
+    pub vault_type: &'a AccountInfo<'a>,  // writable
+    pub vault: &'a AccountInfo<'a>,       // writable
+    pub vault_owner: &'a AccountInfo<'a>, // signed, owner or delegate
+
+    pub price_oracle: &'a AccountInfo<'a>,
+    // writable, required if the vault type charges an origination fee
+    pub treasury: Option<&'a AccountInfo<'a>>,
+    // required if the vault type is permissioned
+    pub allowlist: Option<&'a AccountInfo<'a>>,
+    // required if the vault is tokenized
+    pub position_account: Option<&'a AccountInfo<'a>>,
+    // writable, required if the owner isn't signing
+    pub vault_delegate: Option<&'a AccountInfo<'a>>,
+}
+
+pub fn borrow<'a>(
+    program: &AccountInfo<'a>,
+    accounts: Borrow<'a>,
+    amount: u64,
+    debt_minter_nonce: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let inx = instruction::borrow(
+        program.key,
+        accounts.debt_token.key,
+        accounts.debt_minter.key,
+        accounts.debt_receiver.key,
+        accounts.debt_type.key,
+        accounts.vault_type.key,
+        accounts.vault.key,
+        accounts.vault_owner.key,
+        accounts.price_oracle.key,
+        accounts.treasury.map(|account| account.key),
+        accounts.allowlist.map(|account| account.key),
+        accounts.position_account.map(|account| account.key),
+        accounts.vault_delegate.map(|account| account.key),
+        amount,
+        debt_minter_nonce,
+    )?;
+
+    let mut extra_accounts: Vec<AccountInfo> =
+        accounts.position_account.into_iter().cloned().collect();
+    extra_accounts.extend(accounts.vault_delegate.cloned());
+    extra_accounts.extend(accounts.treasury.cloned());
+    extra_accounts.extend(accounts.allowlist.cloned());
+
+    invoke(
+        &inx,
+        program,
+        &[
+            accounts.token_program,
+            accounts.debt_token,
+            accounts.debt_minter,
+            accounts.debt_receiver,
+            accounts.debt_type,
+            accounts.vault_type,
+            accounts.vault,
+            accounts.vault_owner,
+            accounts.price_oracle,
+        ],
+        &extra_accounts,
+        signer_seeds,
+    )
+}
+
+pub struct Repay<'a> {
+    pub token_program: &'a AccountInfo<'a>,
+
+    pub debt_token: &'a AccountInfo<'a>,          // writable
+    pub debt_from: &'a AccountInfo<'a>,           // writable
+    pub debt_from_authority: &'a AccountInfo<'a>, // signed
+
+    pub debt_type: &'a AccountInfo<'a>,
+    pub vault_type: &'a AccountInfo<'a>, // writable
+    pub vault: &'a AccountInfo<'a>,      // writable
+}
+
+pub fn repay<'a>(
+    program: &AccountInfo<'a>,
+    accounts: Repay<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let inx = instruction::repay(
+        program.key,
+        accounts.debt_token.key,
+        accounts.debt_from.key,
+        accounts.debt_from_authority.key,
+        accounts.debt_type.key,
+        accounts.vault_type.key,
+        accounts.vault.key,
+        amount,
+    )?;
+
+    invoke(
+        &inx,
+        program,
+        &[
+            accounts.token_program,
+            accounts.debt_token,
+            accounts.debt_from,
+            accounts.debt_from_authority,
+            accounts.debt_type,
+            accounts.vault_type,
+            accounts.vault,
+        ],
+        &[],
+        signer_seeds,
+    )
+}
+
+pub struct JoinSavings<'a> {
+    pub token_program: &'a AccountInfo<'a>,
+    pub clock: &'a AccountInfo<'a>,
+
+    pub savings: &'a AccountInfo<'a>,
+
+    pub debt_from: &'a AccountInfo<'a>,           // writable
+    pub debt_from_authority: &'a AccountInfo<'a>, // signed
+    pub debt_holder: &'a AccountInfo<'a>,         // writable
+
+    pub share_token: &'a AccountInfo<'a>, // writable
+    pub savings_authority: &'a AccountInfo<'a>,
+    pub share_to: &'a AccountInfo<'a>, // writable
+}
+
+pub fn join_savings<'a>(
+    program: &AccountInfo<'a>,
+    accounts: JoinSavings<'a>,
+    amount: u64,
+    savings_authority_nonce: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let inx = instruction::join_savings(
+        program.key,
+        accounts.savings.key,
+        accounts.debt_from.key,
+        accounts.debt_from_authority.key,
+        accounts.debt_holder.key,
+        accounts.share_token.key,
+        accounts.savings_authority.key,
+        accounts.share_to.key,
+        amount,
+        savings_authority_nonce,
+    )?;
+
+    invoke(
+        &inx,
+        program,
+        &[
+            accounts.token_program,
+            accounts.clock,
+            accounts.savings,
+            accounts.debt_from,
+            accounts.debt_from_authority,
+            accounts.debt_holder,
+            accounts.share_token,
+            accounts.savings_authority,
+            accounts.share_to,
+        ],
+        &[],
+        signer_seeds,
+    )
+}
+
+pub struct ExitSavings<'a> {
+    pub token_program: &'a AccountInfo<'a>,
+    pub clock: &'a AccountInfo<'a>,
+
+    pub savings: &'a AccountInfo<'a>,
+
+    pub share_token: &'a AccountInfo<'a>,          // writable
+    pub share_from: &'a AccountInfo<'a>,           // writable
+    pub share_from_authority: &'a AccountInfo<'a>, // signed
+
+    pub debt_holder: &'a AccountInfo<'a>, // writable
+    pub savings_authority: &'a AccountInfo<'a>,
+    pub debt_to: &'a AccountInfo<'a>, // writable
+}
+
+pub fn exit_savings<'a>(
+    program: &AccountInfo<'a>,
+    accounts: ExitSavings<'a>,
+    shares: u64,
+    savings_authority_nonce: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let inx = instruction::exit_savings(
+        program.key,
+        accounts.savings.key,
+        accounts.share_token.key,
+        accounts.share_from.key,
+        accounts.share_from_authority.key,
+        accounts.debt_holder.key,
+        accounts.savings_authority.key,
+        accounts.debt_to.key,
+        shares,
+        savings_authority_nonce,
+    )?;
+
+    invoke(
+        &inx,
+        program,
+        &[
+            accounts.token_program,
+            accounts.clock,
+            accounts.savings,
+            accounts.share_token,
+            accounts.share_from,
+            accounts.share_from_authority,
+            accounts.debt_holder,
+            accounts.savings_authority,
+            accounts.debt_to,
+        ],
+        &[],
+        signer_seeds,
+    )
+}
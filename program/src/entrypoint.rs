@@ -1,10 +1,13 @@
 //! Program entrypoint
 
-use crate::processor::Processor;
+use crate::{error::Error, processor::Processor};
 
 use solana_program::{
-    msg,
-    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, pubkey::Pubkey,
+    account_info::AccountInfo,
+    entrypoint,
+    entrypoint::ProgramResult,
+    program_error::{PrintProgramError, ProgramError},
+    pubkey::Pubkey,
 };
 
 entrypoint!(process_instruction);
@@ -15,14 +18,15 @@ fn process_instruction<'a>(
     accounts: &'a [AccountInfo<'a>],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    // TODO: find a way to print the custom error here, but not the custom error
-    // of other sub programs like spl-token.
+    let result = Processor::process(program_id, accounts, instruction_data);
 
-    if let Err(error) = Processor::process(program_id, accounts, instruction_data) {
-        // catch the error so we can print it
-        // error.print::<Error>();
-        // msg!("{:?}", error);
-        return Err(error);
+    // name our own errors. The custom error of an invoked program, like
+    // spl-token, has a code outside of our range, and is left to the runtime.
+    if let Err(ProgramError::Custom(code)) = result {
+        if let Some(error) = Error::from_code(code) {
+            error.print::<Error>();
+        }
     }
-    Ok(())
+
+    result
 }
@@ -1,11 +1,20 @@
 //! Error types
 
 use num_derive::FromPrimitive;
-use solana_program::program_error::ProgramError;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
 
 use num_traits::FromPrimitive;
 use thiserror::Error;
 
+/// Custom error codes of the program start at the offset, so that clients can
+/// tell them from the codes of the programs it invokes, e.g. spl-token's, which
+/// count from 0. The high bytes spell "PR".
+pub const ERROR_CODE_OFFSET: u32 = 0x5052_0000;
+
 /// Errors that may be returned by the program.
 #[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
 pub enum Error {
@@ -33,6 +42,105 @@ pub enum Error {
     #[error("Invalid price oracle")] // 7
     InvalidPriceOracle,
 
+    #[error("Vault still has debt or collateral")] // 8
+    VaultNotEmpty,
+
+    #[error("Insufficient collateral")] // 9
+    InsufficientCollateral,
+
+    #[error("Vault delegate mismatch")] // 10
+    DelegateMismatch,
+
+    #[error("Vault delegate permission denied")] // 11
+    DelegatePermissionDenied,
+
+    #[error("Vault delegate borrow cap exceeded")] // 12
+    DelegateBorrowCapExceeded,
+
+    #[error("Invalid position token")] // 13
+    InvalidPositionToken,
+
+    #[error("Vault is tokenized")] // 14
+    VaultTokenized,
+
+    #[error("Invalid collateral ratio")] // 15
+    InvalidCollateralRatio,
+
+    #[error("Collateral ratio too low")] // 16
+    CollateralRatioTooLow,
+
+    #[error("Too many collateral types in vault")] // 17
+    TooManyCollaterals,
+
+    #[error("Invalid collateral token")] // 18
+    InvalidCollateralToken,
+
+    #[error("Repay amount exceeds debt")] // 19
+    RepayExceedsDebt,
+
+    #[error("Invalid swap program")] // 20
+    InvalidSwapProgram,
+
+    #[error("Swap result out of bounds")] // 21
+    SwapResultMismatch,
+
+    #[error("Vault not healthier after deleverage")] // 22
+    DeleverageNotHealthier,
+
+    #[error("Invalid share token")] // 23
+    InvalidShareToken,
+
+    #[error("Savings account mismatch")] // 24
+    SavingsMismatch,
+
+    #[error("Savings rate not dripped")] // 25
+    SavingsNotDripped,
+
+    #[error("Treasury account mismatch")] // 26
+    TreasuryMismatch,
+
+    #[error("Insufficient surplus")] // 27
+    InsufficientSurplus,
+
+    #[error("Vault is not insolvent")] // 28
+    VaultNotInsolvent,
+
+    #[error("Invalid governance token")] // 29
+    InvalidGovernanceToken,
+
+    #[error("Auction not allowed by the treasury")] // 30
+    InvalidAuction,
+
+    #[error("Auction ended")] // 31
+    AuctionEnded,
+
+    #[error("Auction not ended")] // 32
+    AuctionNotEnded,
+
+    #[error("Bid too low")] // 33
+    BidTooLow,
+
+    #[error("Invalid fee")] // 34
+    InvalidFee,
+
+    #[error("Collateral cap exceeded")] // 35
+    CollateralCapExceeded,
+
+    #[error("Allowlist account mismatch")] // 36
+    AllowlistMismatch,
+
+    #[error("Address not on the allowlist")] // 37
+    NotAllowlisted,
+
+    #[error("Allowlist is full")] // 38
+    AllowlistFull,
+
+    #[error("Lp pool not refreshed in the current slot")] // 39
+    StaleLpPool,
+
+    #[error("Vault is healthy")] // 40
+    VaultHealthy,
+
     // #[error("Insufficient withdrawable")] // 1
     // InsufficientWithdrawable,
 
@@ -62,22 +170,100 @@ pub enum Error {
 
     // #[error("Invalid faucet")]
     // InvalidFaucet,
-
-    #[error("Unknown error")]
+    /// Deprecated, kept for the `From<ProgramError>` conversion. The program
+    /// never returns it.
+    #[error("Unknown error")] // 41
     UnknownError,
+
+    #[error("Price feed token mismatch")] // 42
+    PriceFeedTokenMismatch,
+}
+
+impl Error {
+    /// The custom error code of the error.
+    pub fn code(&self) -> u32 {
+        ERROR_CODE_OFFSET + self.clone() as u32
+    }
+
+    /// The error of a custom error code, None if it isn't one of ours.
+    pub fn from_code(code: u32) -> Option<Error> {
+        code.checked_sub(ERROR_CODE_OFFSET)
+            .and_then(Error::from_u32)
+    }
 }
 
 impl From<Error> for ProgramError {
     fn from(e: Error) -> Self {
-        ProgramError::Custom(e as u32)
+        ProgramError::Custom(e.code())
     }
 }
 
+/// Deprecated, kept for compatibility: every error that isn't one of ours is an
+/// `UnknownError`. Prefer `Error::from_code`.
 impl From<ProgramError> for Error {
     fn from(err: ProgramError) -> Self {
         match err {
-            ProgramError::Custom(code) => Error::from_u32(code).unwrap_or(Error::UnknownError),
+            ProgramError::Custom(code) => Error::from_code(code).unwrap_or(Error::UnknownError),
             _ => Error::UnknownError,
         }
     }
 }
+
+impl<T> DecodeError<T> for Error {
+    fn decode_custom_error_to_enum(custom: u32) -> Option<T>
+    where
+        T: FromPrimitive,
+    {
+        custom.checked_sub(ERROR_CODE_OFFSET).and_then(T::from_u32)
+    }
+
+    fn type_of() -> &'static str {
+        "Parrot Error"
+    }
+}
+
+impl PrintProgramError for Error {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        msg!("Error: {:?} ({})", self, self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let err: ProgramError = Error::CollateralRatioTooLow.into();
+        assert_eq!(err, ProgramError::Custom(0x5052_0010));
+        assert_eq!(
+            Error::from_code(0x5052_0010),
+            Some(Error::CollateralRatioTooLow)
+        );
+        assert_eq!(
+            <Error as DecodeError<Error>>::decode_custom_error_to_enum(0x5052_0010),
+            Some(Error::CollateralRatioTooLow)
+        );
+
+        // spl-token's InsufficientFunds
+        assert_eq!(Error::from_code(1), None);
+        assert_eq!(
+            <Error as DecodeError<Error>>::decode_custom_error_to_enum(1),
+            None
+        );
+        assert_eq!(Error::from_code(ERROR_CODE_OFFSET + 0xffff), None);
+
+        assert_eq!(
+            Error::from(ProgramError::Custom(0x5052_0010)),
+            Error::CollateralRatioTooLow
+        );
+        assert_eq!(Error::from(ProgramError::Custom(1)), Error::UnknownError);
+        assert_eq!(
+            Error::from(ProgramError::InvalidArgument),
+            Error::UnknownError
+        );
+    }
+}
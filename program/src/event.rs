@@ -0,0 +1,379 @@
+//! Events logged by the program, for indexers
+//!
+//! Every instruction that changes state logs one `Event`, Borsh serialized and
+//! base64 encoded, on a log line of its own that starts with `EVENT_PREFIX`.
+//! The events carry the amounts an instruction moved, and the balances it left,
+//! so an indexer can follow the vaults without loading any account.
+//!
+//! Any program can log a line that looks like an event, so `parse_logs` only
+//! decodes the lines logged while this program was the one running.
+//!
+//! New variants are only ever appended, so the encoding of the existing ones
+//! doesn't change.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{entrypoint::ProgramResult, msg, pubkey::Pubkey};
+use std::io;
+
+use crate::state::{AuctionKind, PublicKey, VaultTypeParams, MAX_MULTI_VAULT_COLLATERALS};
+
+/// Prefix of the log message of an event, followed by the encoded event.
+pub const EVENT_PREFIX: &str = "parrot-event:";
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub enum Event {
+    DebtTypeInitialized {
+        debt_type: PublicKey,
+        debt_token: PublicKey,
+        owner: PublicKey,
+    },
+    VaultTypeInitialized {
+        vault_type: PublicKey,
+        debt_type: PublicKey,
+        collateral_token: PublicKey,
+        price_oracle: PublicKey,
+        params: VaultTypeParams,
+    },
+    VaultInitialized {
+        vault: PublicKey,
+        vault_type: PublicKey,
+        owner: PublicKey,
+    },
+    VaultStaked {
+        vault: PublicKey,
+        vault_type: PublicKey,
+        amount: u64,
+        // of the vault, after the stake
+        collateral_amount: u64,
+    },
+    VaultUnstaked {
+        vault: PublicKey,
+        vault_type: PublicKey,
+        amount: u64,
+        collateral_amount: u64,
+    },
+    DebtBorrowed {
+        vault: PublicKey,
+        vault_type: PublicKey,
+        amount: u64,
+        // origination fee added to the debt
+        fee: u64,
+        debt_amount: u64,
+    },
+    DebtRepaid {
+        vault: PublicKey,
+        vault_type: PublicKey,
+        amount: u64,
+        debt_amount: u64,
+    },
+    VaultClosed {
+        vault: PublicKey,
+    },
+    /// The owner changed, or a new owner is pending until accepted.
+    VaultTransferred {
+        vault: PublicKey,
+        owner: PublicKey,
+        pending_owner: PublicKey,
+    },
+    VaultDelegateSet {
+        vault_delegate: PublicKey,
+        vault: PublicKey,
+        delegate: PublicKey,
+        permissions: u8,
+        borrow_cap: u64,
+    },
+    VaultDelegateRevoked {
+        vault_delegate: PublicKey,
+        vault: PublicKey,
+    },
+    PriceFeedInitialized {
+        price_feed: PublicKey,
+        owner: PublicKey,
+        decimals: u8,
+    },
+    PriceUpdated {
+        price_feed: PublicKey,
+        price: u64,
+    },
+    LpPoolInitialized {
+        lp_pool: PublicKey,
+        price_feed_a: PublicKey,
+        price_feed_b: PublicKey,
+    },
+    /// Logged by both UpdateLpPool and RefreshLpPool.
+    LpPoolUpdated {
+        lp_pool: PublicKey,
+        reserve_a: u64,
+        reserve_b: u64,
+        lp_supply: u64,
+        pool_value: u128,
+    },
+    MultiVaultInitialized {
+        multi_vault: PublicKey,
+        debt_type: PublicKey,
+        owner: PublicKey,
+    },
+    MultiVaultStaked {
+        multi_vault: PublicKey,
+        vault_type: PublicKey,
+        amount: u64,
+        // of the vault type's collateral in the vault, after the stake
+        collateral_amount: u64,
+    },
+    MultiVaultUnstaked {
+        multi_vault: PublicKey,
+        vault_type: PublicKey,
+        amount: u64,
+        collateral_amount: u64,
+    },
+    MultiVaultBorrowed {
+        multi_vault: PublicKey,
+        amount: u64,
+        fee: u64,
+        debt_amount: u64,
+    },
+    ParamsUpdated {
+        vault_type: PublicKey,
+        params: VaultTypeParams,
+    },
+    VaultDeleveraged {
+        vault: PublicKey,
+        vault_type: PublicKey,
+        collateral_sold: u64,
+        debt_repaid: u64,
+        collateral_amount: u64,
+        debt_amount: u64,
+    },
+    VaultLeveraged {
+        vault: PublicKey,
+        vault_type: PublicKey,
+        amount: u64,
+        fee: u64,
+        collateral_bought: u64,
+        collateral_amount: u64,
+        debt_amount: u64,
+    },
+    SavingsInitialized {
+        savings: PublicKey,
+        debt_type: PublicKey,
+        share_token: PublicKey,
+        rate: u64,
+    },
+    SavingsRateSet {
+        savings: PublicKey,
+        rate: u64,
+    },
+    SavingsDripped {
+        savings: PublicKey,
+        accumulator: u128,
+        // debt tokens minted to back the shares
+        interest: u64,
+    },
+    SavingsJoined {
+        savings: PublicKey,
+        amount: u64,
+        shares: u64,
+    },
+    SavingsExited {
+        savings: PublicKey,
+        shares: u64,
+        amount: u64,
+    },
+    TreasuryInitialized {
+        treasury: PublicKey,
+        debt_type: PublicKey,
+        governance_token: PublicKey,
+    },
+    SurplusWithdrawn {
+        treasury: PublicKey,
+        amount: u64,
+        surplus: u64,
+    },
+    /// The debt of an insolvent vault was written off as bad debt.
+    VaultWrittenOff {
+        vault: PublicKey,
+        vault_type: PublicKey,
+        treasury: PublicKey,
+        debt_amount: u64,
+    },
+    AuctionStarted {
+        auction: PublicKey,
+        treasury: PublicKey,
+        kind: AuctionKind,
+        debt_amount: u64,
+        gov_amount: u64,
+        ends_at: i64,
+    },
+    AuctionBid {
+        auction: PublicKey,
+        bidder: PublicKey,
+        gov_amount: u64,
+    },
+    /// The bidder is the default key if no one bid.
+    AuctionSettled {
+        auction: PublicKey,
+        treasury: PublicKey,
+        bidder: PublicKey,
+        debt_amount: u64,
+        gov_amount: u64,
+    },
+    AllowlistInitialized {
+        allowlist: PublicKey,
+        debt_type: PublicKey,
+    },
+    AllowlistUpdated {
+        allowlist: PublicKey,
+        address: PublicKey,
+        // removed if false
+        added: bool,
+    },
+    /// The liquidator repaid the debt, penalty included, up to the value of
+    /// the collateral, and took the collateral worth what it repaid. The
+    /// collateral left over stays in the vault, and the debt left over can be
+    /// written off.
+    VaultLiquidated {
+        vault: PublicKey,
+        vault_type: PublicKey,
+        treasury: PublicKey,
+        collateral_seized: u64,
+        debt_repaid: u64,
+        penalty: u64,
+        debt_amount: u64,
+    },
+    MultiVaultRepaid {
+        multi_vault: PublicKey,
+        amount: u64,
+        debt_amount: u64,
+    },
+    /// Like VaultLiquidated, for the collaterals of a multi vault together.
+    MultiVaultLiquidated {
+        multi_vault: PublicKey,
+        treasury: PublicKey,
+        // of each collateral, by slot of the vault
+        collateral_seized: [u64; MAX_MULTI_VAULT_COLLATERALS],
+        debt_repaid: u64,
+        penalty: u64,
+        debt_amount: u64,
+    },
+    AuctionRefundClaimed {
+        auction: PublicKey,
+        refund: PublicKey,
+        bidder: PublicKey,
+        amount: u64,
+    },
+}
+
+impl Event {
+    /// Log the event with msg!.
+    pub fn log(&self) -> ProgramResult {
+        msg!("{}{}", EVENT_PREFIX, self.encode()?);
+
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Result<String, io::Error> {
+        Ok(base64::encode(self.try_to_vec()?))
+    }
+
+    /// Decode the part of a log message after EVENT_PREFIX.
+    pub fn decode(data: &str) -> Result<Event, io::Error> {
+        let data =
+            base64::decode(data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Event::try_from_slice(&data)
+    }
+}
+
+/// Decode the events that the program logged in the log messages of a
+/// transaction, in order. Fails on an event that doesn't decode, e.g. one
+/// logged by a newer version of the program.
+///
+/// The runtime truncates the logs of a transaction that logs too much, which
+/// may drop events.
+pub fn parse_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Result<Vec<Event>, io::Error> {
+    let program_id = program_id.to_string();
+    // the programs running, innermost last
+    let mut invoked: Vec<&str> = vec![];
+    let mut events = vec![];
+
+    for line in logs {
+        let line = match line.as_ref().strip_prefix("Program ") {
+            Some(line) => line,
+            None => continue,
+        };
+
+        if let Some(message) = line.strip_prefix("log: ") {
+            if invoked.last() != Some(&program_id.as_str()) {
+                continue;
+            }
+
+            if let Some(data) = message.strip_prefix(EVENT_PREFIX) {
+                events.push(Event::decode(data)?);
+            }
+            continue;
+        }
+
+        // Program <id> invoke [<depth>], Program <id> success or failed: <err>
+        let mut words = line.split(' ');
+        match (words.next(), words.next()) {
+            (Some(id), Some("invoke")) => invoked.push(id),
+            (Some(_), Some("success")) | (Some(_), Some("failed:")) => {
+                invoked.pop();
+            }
+            _ => (),
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_logs() {
+        let program_id = Pubkey::new_unique();
+        let other_id = Pubkey::new_unique();
+
+        let staked = Event::VaultStaked {
+            vault: PublicKey([1; 32]),
+            vault_type: PublicKey([2; 32]),
+            amount: 100,
+            collateral_amount: 300,
+        };
+        let borrowed = Event::DebtBorrowed {
+            vault: PublicKey([1; 32]),
+            vault_type: PublicKey([2; 32]),
+            amount: 50,
+            fee: 1,
+            debt_amount: 51,
+        };
+        let event_log =
+            |event: &Event| format!("Program log: {}{}", EVENT_PREFIX, event.encode().unwrap());
+
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            event_log(&staked),
+            format!("Program {} invoke [2]", other_id),
+            // a look alike from another program
+            event_log(&borrowed),
+            format!("Program {} consumed 100 of 200000 compute units", other_id),
+            format!("Program {} success", other_id),
+            "Program log: not an event".to_string(),
+            event_log(&borrowed),
+            format!("Program {} success", program_id),
+            event_log(&staked),
+        ];
+
+        assert_eq!(
+            parse_logs(&program_id, &logs).unwrap(),
+            vec![staked, borrowed]
+        );
+
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            format!("Program log: {}AAAA", EVENT_PREFIX),
+        ];
+        assert!(parse_logs(&program_id, &logs).is_err());
+    }
+}
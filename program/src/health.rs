@@ -0,0 +1,563 @@
+//! Vault health math, on the plain state structs
+//!
+//! Nothing here touches an `AccountInfo`, so clients can compute exactly what
+//! the processor checks: load the accounts, take the `Price` of the vault
+//! type's oracle, and call `vault_health`.
+
+use crate::{
+    error::Error,
+    math::{Decimal, WAD_DECIMALS},
+    state::{LpPool, PriceFeed, Vault, VaultType, RATIO_BASE},
+};
+
+/// Price of the collateral of a vault type in its debt token: `amount` base
+/// units of collateral are worth `value` base units of debt.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Price {
+    pub value: u128,
+    pub amount: u128,
+}
+
+impl Price {
+    pub fn from_price_feed(feed: &PriceFeed, vault_type: &VaultType) -> Result<Price, Error> {
+        // price / 10^decimals whole debt tokens per whole collateral token
+        Price::scaled(
+            feed.price as u128,
+            1,
+            vault_type.debt_decimals as i32
+                - feed.decimals as i32
+                - vault_type.collateral_decimals as i32,
+        )
+    }
+
+    pub fn from_lp_pool(pool: &LpPool, vault_type: &VaultType) -> Result<Price, Error> {
+        // the pool value is in whole debt tokens, scaled by WAD
+        Price::scaled(
+            pool.pool_value,
+            pool.lp_supply as u128,
+            vault_type.debt_decimals as i32 - WAD_DECIMALS as i32,
+        )
+    }
+
+    /// value / amount * 10^exponent, putting the power of ten on one side.
+    fn scaled(value: u128, amount: u128, exponent: i32) -> Result<Price, Error> {
+        let pow = 10u128
+            .checked_pow(exponent.unsigned_abs())
+            .ok_or(Error::Overflow)?;
+
+        let price = if exponent >= 0 {
+            Price {
+                value: value.checked_mul(pow).ok_or(Error::Overflow)?,
+                amount,
+            }
+        } else {
+            Price {
+                value,
+                amount: amount.checked_mul(pow).ok_or(Error::Overflow)?,
+            }
+        };
+
+        Ok(price)
+    }
+
+    /// Value of the collateral amount in debt tokens, rounded down.
+    pub fn value(&self, collateral_amount: u64) -> Result<Decimal, Error> {
+        Decimal::from_u64(collateral_amount).try_mul_ratio_floor(self.value, self.amount)
+    }
+
+    /// Least collateral amount worth at least the value. Worthless collateral
+    /// is worth nothing at any amount.
+    fn collateral_for(&self, value: Decimal) -> Result<u64, Error> {
+        value
+            .try_mul_ratio_ceil(self.amount, self.value)?
+            .try_ceil_u64()
+    }
+}
+
+/// Collateral and debt values are in base units of the debt token.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Health {
+    pub collateral_value: u64,
+    pub debt_value: u64,
+
+    // collateral value to debt, in basis points. None without debt.
+    pub collateral_ratio: Option<u64>,
+
+    // most debt the collateral can back
+    pub max_debt: u64,
+    // most the vault can still borrow, origination fee included
+    pub max_borrowable: u64,
+    // most collateral the vault can unstake
+    pub max_withdrawable: u64,
+
+    // price of a whole collateral token in whole debt tokens, at which the
+    // vault becomes unhealthy. None without debt.
+    pub liquidation_price: Option<Decimal>,
+}
+
+impl Health {
+    pub fn is_healthy(&self) -> bool {
+        self.debt_value <= self.max_debt
+    }
+}
+
+/// Max debt that the collateral amount of a vault type can back at the price.
+pub fn max_debt(
+    vault_type: &VaultType,
+    price: &Price,
+    collateral_amount: u64,
+) -> Result<u64, Error> {
+    vault_type.max_debt(price.value(collateral_amount)?)
+}
+
+/// Collateral value to debt of a collateral and debt amount. None without
+/// debt.
+pub fn collateral_ratio(
+    price: &Price,
+    collateral_amount: u64,
+    debt_amount: u64,
+) -> Result<Option<Decimal>, Error> {
+    if debt_amount == 0 {
+        return Ok(None);
+    }
+
+    let ratio = price
+        .value(collateral_amount)?
+        .try_div_floor(Decimal::from_u64(debt_amount))?;
+    Ok(Some(ratio))
+}
+
+pub fn vault_health(vault: &Vault, vault_type: &VaultType, price: &Price) -> Result<Health, Error> {
+    let collateral_value = price.value(vault.collateral_amount)?;
+    let max_debt = vault_type.max_debt(collateral_value)?;
+    let debt_value = vault.debt_amount;
+
+    let mut health = Health {
+        collateral_value: collateral_value.try_floor_u64()?,
+        debt_value,
+        max_debt,
+        max_borrowable: max_borrowable(vault_type, max_debt.saturating_sub(debt_value))?,
+        max_withdrawable: vault.collateral_amount,
+        ..Health::default()
+    };
+    if debt_value == 0 {
+        return Ok(health);
+    }
+
+    let debt = Decimal::from_u64(debt_value);
+    health.collateral_ratio = Some(
+        collateral_value
+            .try_mul_ratio_floor(RATIO_BASE as u128, 1)?
+            .try_div_floor(debt)?
+            .try_floor_u64()?,
+    );
+
+    // least collateral value that backs the debt, the exact bound of
+    // VaultType::max_debt
+    let min_value = debt.try_mul_ceil(Decimal::from_bps(vault_type.params.collateral_ratio))?;
+
+    health.max_withdrawable = match price.collateral_for(min_value) {
+        Ok(min_collateral) => vault.collateral_amount.saturating_sub(min_collateral),
+        // worthless collateral can't back any debt
+        Err(_) if price.value == 0 => 0,
+        Err(err) => return Err(err),
+    };
+
+    if vault.collateral_amount > 0 {
+        // min_value / collateral_amount per base unit, scaled to whole tokens
+        let exponent = vault_type.collateral_decimals as i32 - vault_type.debt_decimals as i32;
+        let unit = Price::scaled(1, vault.collateral_amount as u128, exponent)?;
+        health.liquidation_price = Some(min_value.try_mul_ratio_ceil(unit.value, unit.amount)?);
+    }
+
+    Ok(health)
+}
+
+/// Outcome of liquidating a vault. The penalty is added to the debt, and the
+/// liquidator repays as much of it as the collateral is worth, for the
+/// collateral worth the repaid debt. The collateral left over stays in the
+/// vault for its owner, and any debt left over has no collateral backing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Liquidation {
+    pub penalty: u64,
+    pub debt_repaid: u64,
+    pub debt_left: u64,
+    pub collateral_seized: u64,
+}
+
+pub fn liquidation(
+    vault: &Vault,
+    vault_type: &VaultType,
+    price: &Price,
+) -> Result<Liquidation, Error> {
+    let penalty = vault_type.params.liquidation_penalty(vault.debt_amount)?;
+    let debt_amount = vault
+        .debt_amount
+        .checked_add(penalty)
+        .ok_or(Error::Overflow)?;
+
+    let collateral_value = price.value(vault.collateral_amount)?.try_floor_u64()?;
+    let debt_repaid = debt_amount.min(collateral_value);
+    let debt_left = debt_amount - debt_repaid;
+
+    // collateral short of the debt all goes to the liquidator
+    let collateral_seized = if debt_left > 0 || price.value == 0 {
+        vault.collateral_amount
+    } else {
+        price
+            .collateral_for(Decimal::from_u64(debt_repaid))?
+            .min(vault.collateral_amount)
+    };
+
+    Ok(Liquidation {
+        penalty,
+        debt_repaid,
+        debt_left,
+        collateral_seized,
+    })
+}
+
+/// A collateral of a multi vault, priced by its vault type's oracle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collateral<'a> {
+    pub vault_type: &'a VaultType,
+    pub price: Price,
+    pub amount: u64,
+}
+
+/// Outcome of liquidating a multi vault, with the collateral seized of each
+/// of the collaterals, in their order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MultiLiquidation {
+    pub penalty: u64,
+    pub debt_repaid: u64,
+    pub debt_left: u64,
+    pub collateral_seized: Vec<u64>,
+}
+
+/// Like `liquidation`, for the collaterals of a multi vault together. The
+/// highest penalty of their vault types applies, and the liquidator takes the
+/// collaterals in order until they are worth the repaid debt.
+pub fn multi_vault_liquidation(
+    debt_amount: u64,
+    collaterals: &[Collateral],
+) -> Result<MultiLiquidation, Error> {
+    let mut penalty = 0;
+    let mut values = Vec::with_capacity(collaterals.len());
+    for collateral in collaterals {
+        penalty = penalty.max(
+            collateral
+                .vault_type
+                .params
+                .liquidation_penalty(debt_amount)?,
+        );
+        values.push(collateral.price.value(collateral.amount)?.try_floor_u64()?);
+    }
+
+    let debt_amount = debt_amount.checked_add(penalty).ok_or(Error::Overflow)?;
+    let collateral_value = values
+        .iter()
+        .try_fold(0u64, |sum, value| sum.checked_add(*value))
+        .ok_or(Error::Overflow)?;
+    let debt_repaid = debt_amount.min(collateral_value);
+    let debt_left = debt_amount - debt_repaid;
+
+    let mut debt_to_cover = debt_repaid;
+    let mut collateral_seized = Vec::with_capacity(collaterals.len());
+    for (collateral, value) in collaterals.iter().zip(values) {
+        let covered = debt_to_cover.min(value);
+        debt_to_cover -= covered;
+
+        // collateral short of the debt all goes to the liquidator
+        let seized = if debt_left > 0 {
+            collateral.amount
+        } else if covered == 0 {
+            0
+        } else {
+            collateral
+                .price
+                .collateral_for(Decimal::from_u64(covered))?
+                .min(collateral.amount)
+        };
+        collateral_seized.push(seized);
+    }
+
+    Ok(MultiLiquidation {
+        penalty,
+        debt_repaid,
+        debt_left,
+        collateral_seized,
+    })
+}
+
+/// Most that can be borrowed while the debt it adds, origination fee
+/// included, stays within the headroom.
+fn max_borrowable(vault_type: &VaultType, headroom: u64) -> Result<u64, Error> {
+    let fee_base = RATIO_BASE + vault_type.params.origination_fee;
+    let mut amount = Decimal::from_u64(headroom)
+        .try_mul_ratio_floor(RATIO_BASE as u128, fee_base as u128)?
+        .try_floor_u64()?;
+
+    // the fee rounds up, which can push the estimate over by a token
+    loop {
+        let fee = vault_type.params.origination_fee(amount)?;
+        if amount == 0 || amount as u128 + fee as u128 <= headroom as u128 {
+            return Ok(amount);
+        }
+        amount -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::VaultTypeParams;
+
+    fn new_vault_type(collateral_ratio: u64, origination_fee: u64) -> VaultType {
+        VaultType {
+            params: VaultTypeParams {
+                collateral_ratio,
+                origination_fee,
+                ..VaultTypeParams::default()
+            },
+            ..VaultType::default()
+        }
+    }
+
+    fn vault(collateral_amount: u64, debt_amount: u64) -> Vault {
+        Vault {
+            collateral_amount,
+            debt_amount,
+            ..Vault::default()
+        }
+    }
+
+    #[test]
+    fn test_vault_health() {
+        let vault_type = new_vault_type(15_000, 0);
+        // 2 debt tokens per collateral token
+        let price = Price::from_price_feed(
+            &PriceFeed {
+                price: 200,
+                decimals: 2,
+                ..PriceFeed::default()
+            },
+            &vault_type,
+        )
+        .unwrap();
+
+        let health = vault_health(&vault(10_000, 1_000), &vault_type, &price).unwrap();
+        assert_eq!(
+            health,
+            Health {
+                collateral_value: 20_000,
+                debt_value: 1_000,
+                collateral_ratio: Some(200_000),
+                max_debt: 13_333,
+                max_borrowable: 12_333,
+                // 750 collateral is worth 1500, backing 1000 of debt
+                max_withdrawable: 9_250,
+                // 10000 collateral at 0.15 is worth 1500
+                liquidation_price: Some(Decimal::from_bps(1_500)),
+            }
+        );
+        assert!(health.is_healthy());
+
+        // the limits are exact
+        let at_limit = vault(10_000, 13_333);
+        assert!(vault_health(&at_limit, &vault_type, &price)
+            .unwrap()
+            .is_healthy());
+        let over_limit = vault(10_000, 13_334);
+        assert!(!vault_health(&over_limit, &vault_type, &price)
+            .unwrap()
+            .is_healthy());
+
+        let withdrawn = vault(750, 1_000);
+        assert_eq!(
+            vault_health(&withdrawn, &vault_type, &price)
+                .unwrap()
+                .max_withdrawable,
+            0
+        );
+        assert!(vault_health(&withdrawn, &vault_type, &price)
+            .unwrap()
+            .is_healthy());
+        assert!(!vault_health(&vault(749, 1_000), &vault_type, &price)
+            .unwrap()
+            .is_healthy());
+    }
+
+    #[test]
+    fn test_vault_health_with_decimals() {
+        // a 9 decimals collateral at 45000, backing a 6 decimals debt token
+        let vault_type = VaultType {
+            debt_decimals: 6,
+            collateral_decimals: 9,
+            ..new_vault_type(15_000, 0)
+        };
+        let feed = PriceFeed {
+            price: 45_000,
+            ..PriceFeed::default()
+        };
+        let price = Price::from_price_feed(&feed, &vault_type).unwrap();
+
+        let health =
+            vault_health(&vault(1_000_000_000, 15_000_000_000), &vault_type, &price).unwrap();
+        assert_eq!(health.collateral_value, 45_000_000_000);
+        assert_eq!(health.max_debt, 30_000_000_000);
+        assert_eq!(health.max_withdrawable, 500_000_000);
+        assert_eq!(health.liquidation_price, Some(Decimal::from_u64(22_500)));
+    }
+
+    #[test]
+    fn test_vault_health_without_debt() {
+        let price = Price {
+            value: 0,
+            amount: 1,
+        };
+        let health = vault_health(&vault(100, 0), &new_vault_type(15_000, 0), &price).unwrap();
+
+        assert!(health.is_healthy());
+        assert_eq!(health.collateral_ratio, None);
+        assert_eq!(health.liquidation_price, None);
+        assert_eq!(health.max_withdrawable, 100);
+
+        // worthless collateral backs nothing
+        let health = vault_health(&vault(100, 1), &new_vault_type(15_000, 0), &price).unwrap();
+        assert!(!health.is_healthy());
+        assert_eq!(health.max_withdrawable, 0);
+    }
+
+    #[test]
+    fn test_collateral_ratio() {
+        let price = Price {
+            value: 2,
+            amount: 1,
+        };
+        assert_eq!(collateral_ratio(&price, 100, 0).unwrap(), None);
+        assert_eq!(
+            collateral_ratio(&price, 100, 150).unwrap(),
+            Some(Decimal::from_ratio_floor(4, 3).unwrap())
+        );
+
+        // a ratio too fine for basis points still orders
+        let before = collateral_ratio(&price, 1_000_000, 300_000).unwrap();
+        let after = collateral_ratio(&price, 999_999, 300_000).unwrap();
+        assert!(after < before);
+    }
+
+    #[test]
+    fn test_liquidation() {
+        let vault_type = VaultType {
+            params: VaultTypeParams {
+                collateral_ratio: 15_000,
+                liquidation_penalty: 1_000,
+                ..VaultTypeParams::default()
+            },
+            ..VaultType::default()
+        };
+        let price = Price {
+            value: 2,
+            amount: 1,
+        };
+
+        // the collateral covers the debt and the penalty, and the owner keeps
+        // the rest
+        assert_eq!(
+            liquidation(&vault(1_000, 1_500), &vault_type, &price),
+            Ok(Liquidation {
+                penalty: 150,
+                debt_repaid: 1_650,
+                debt_left: 0,
+                collateral_seized: 825,
+            })
+        );
+        // the seized collateral rounds up
+        assert_eq!(
+            liquidation(&vault(1_000, 1_501), &vault_type, &price).map(|l| l.collateral_seized),
+            Ok(826)
+        );
+
+        // the shortfall is left in the vault
+        assert_eq!(
+            liquidation(&vault(500, 1_000), &vault_type, &price),
+            Ok(Liquidation {
+                penalty: 100,
+                debt_repaid: 1_000,
+                debt_left: 100,
+                collateral_seized: 500,
+            })
+        );
+    }
+
+    #[test]
+    fn test_multi_vault_liquidation() {
+        let vault_type = |liquidation_penalty| VaultType {
+            params: VaultTypeParams {
+                collateral_ratio: 15_000,
+                liquidation_penalty,
+                ..VaultTypeParams::default()
+            },
+            ..VaultType::default()
+        };
+        let (vault_type_a, vault_type_b) = (vault_type(500), vault_type(1_000));
+        let collaterals = [
+            Collateral {
+                vault_type: &vault_type_a,
+                price: Price {
+                    value: 2,
+                    amount: 1,
+                },
+                amount: 500,
+            },
+            Collateral {
+                vault_type: &vault_type_b,
+                price: Price {
+                    value: 1,
+                    amount: 1,
+                },
+                amount: 500,
+            },
+        ];
+
+        // the higher penalty applies, and the first collateral is taken first
+        assert_eq!(
+            multi_vault_liquidation(1_000, &collaterals),
+            Ok(MultiLiquidation {
+                penalty: 100,
+                debt_repaid: 1_100,
+                debt_left: 0,
+                collateral_seized: vec![500, 100],
+            })
+        );
+
+        // all of the collaterals go for a shortfall
+        assert_eq!(
+            multi_vault_liquidation(1_500, &collaterals),
+            Ok(MultiLiquidation {
+                penalty: 150,
+                debt_repaid: 1_500,
+                debt_left: 150,
+                collateral_seized: vec![500, 500],
+            })
+        );
+    }
+
+    #[test]
+    fn test_max_borrowable_with_fee() {
+        // 1% origination fee
+        let vault_type = new_vault_type(10_000, 100);
+        let price = Price {
+            value: 1,
+            amount: 1,
+        };
+
+        let health = vault_health(&vault(1_000, 0), &vault_type, &price).unwrap();
+        // borrowing 990 adds 990 + 10 of debt
+        assert_eq!(health.max_borrowable, 990);
+
+        let health = vault_health(&vault(1_000, 0), &new_vault_type(10_000, 0), &price).unwrap();
+        assert_eq!(health.max_borrowable, 1_000);
+    }
+}
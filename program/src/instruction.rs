@@ -1,8 +1,36 @@
 #![allow(dead_code)]
 
 use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction as SolanaInstruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar,
+};
+
+use crate::{
+    processor::{LEVERAGE_ROLE, POSITION_ROLE},
+    state::{
+        AuctionKind, CollateralPricing, PublicKey, VaultTypeParams, MAX_MULTI_VAULT_COLLATERALS,
+    },
+};
+
+/// Bits of the optional accounts passed to Unstake, Borrow, Deleverage and
+/// Leverage. The accounts of the bits set follow the fixed accounts, in the
+/// order of the bits.
+pub mod optional_accounts {
+    // token account holding the position token, if the vault is tokenized
+    pub const POSITION: u8 = 1 << 0;
+    // vault delegate, if the signer is a delegate
+    pub const DELEGATE: u8 = 1 << 1;
+    // treasury, if the vault type charges an origination fee
+    pub const TREASURY: u8 = 1 << 2;
+    // allowlist, if the vault type is permissioned
+    pub const ALLOWLIST: u8 = 1 << 3;
+
+    pub const ALL: u8 = POSITION | DELEGATE | TREASURY | ALLOWLIST;
+}
 
-use crate::state::PublicKey;
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
 
 pub enum Instruction {
@@ -15,10 +43,16 @@ pub enum Instruction {
         collateral_token: PublicKey,
         collateral_token_holder: PublicKey,
         price_oracle: PublicKey,
+        pricing: CollateralPricing,
+        params: VaultTypeParams,
+        // collateral is native SOL, staked and unstaked as lamports
+        native: bool,
     },
     InitVault {
         vault_type: PublicKey,
         owner: PublicKey,
+        // represent the vault by a position token. Whoever holds it owns the vault.
+        tokenize: bool,
     },
     Stake {
         amount: u64,
@@ -26,6 +60,9 @@ pub enum Instruction {
     },
     Unstake {
         amount: u64,
+        collateral_holder_nonce: u8,
+        // bit set of optional_accounts
+        optional_accounts: u8,
     },
     Repay {
         amount: u64,
@@ -33,5 +70,1578 @@ pub enum Instruction {
     Borrow {
         amount: u64,
         debt_minter_nonce: u8,
+        optional_accounts: u8,
+    },
+    CloseVault,
+    TransferVault {
+        new_owner: PublicKey,
+        // if set, the new owner has to sign an AcceptVault to take over
+        require_accept: bool,
+    },
+    AcceptVault,
+    SetVaultDelegate {
+        delegate: PublicKey,
+        permissions: u8,
+        borrow_cap: u64,
+    },
+    RevokeVaultDelegate,
+    InitPriceFeed {
+        owner: PublicKey,
+        decimals: u8,
+    },
+    UpdatePrice {
+        price: u64,
+    },
+    InitLpPool {
+        owner: PublicKey,
+        price_feed_a: PublicKey,
+        price_feed_b: PublicKey,
+    },
+    // update the reserves reported by the keeper, and revalue the pool
+    UpdateLpPool {
+        reserve_a: u64,
+        reserve_b: u64,
+        lp_supply: u64,
+    },
+    // revalue the pool at the current prices. Vaults priced by the pool need
+    // it refreshed in the same slot.
+    RefreshLpPool,
+    InitMultiVault {
+        debt_type: PublicKey,
+        owner: PublicKey,
+    },
+    StakeMulti {
+        amount: u64,
+        collateral_holder_nonce: u8,
+    },
+    UnstakeMulti {
+        amount: u64,
+        collateral_holder_nonce: u8,
+    },
+    BorrowMulti {
+        amount: u64,
+        debt_minter_nonce: u8,
+    },
+    UpdateVaultTypeParams {
+        params: VaultTypeParams,
+    },
+    // sell collateral through the swap program, and repay debt with the proceeds
+    Deleverage {
+        collateral_amount: u64,
+        minimum_debt_amount: u64,
+        collateral_holder_nonce: u8,
+        optional_accounts: u8,
+    },
+    // borrow, buy collateral with the debt tokens through the swap program, and
+    // stake it into the same vault
+    Leverage {
+        amount: u64,
+        minimum_collateral_amount: u64,
+        debt_minter_nonce: u8,
+        collateral_holder_nonce: u8,
+        optional_accounts: u8,
+    },
+    InitSavings {
+        // yearly rate in basis points
+        rate: u64,
+        savings_authority_nonce: u8,
+    },
+    SetSavingsRate {
+        rate: u64,
+    },
+    // grow the savings accumulator, and mint the interest to the debt holder
+    // out of the stability fees collected from the vault types
+    Drip {
+        debt_minter_nonce: u8,
+    },
+    // lock debt tokens into the savings for share tokens
+    JoinSavings {
+        amount: u64,
+        savings_authority_nonce: u8,
+    },
+    // burn share tokens for the locked debt tokens and interest
+    ExitSavings {
+        shares: u64,
+        savings_authority_nonce: u8,
+    },
+    InitTreasury {
+        governance_token: PublicKey,
+    },
+    // mint debt tokens out of the surplus
+    WithdrawSurplus {
+        amount: u64,
+        debt_minter_nonce: u8,
     },
+    // record the debt of a vault without collateral as bad debt
+    WriteOffVault,
+    StartAuction {
+        kind: AuctionKind,
+        debt_amount: u64,
+        gov_amount: u64,
+        auction_authority_nonce: u8,
+    },
+    Bid {
+        gov_amount: u64,
+        auction_authority_nonce: u8,
+    },
+    SettleAuction {
+        auction_authority_nonce: u8,
+        debt_minter_nonce: u8,
+    },
+    InitAllowlist,
+    AddToAllowlist {
+        address: PublicKey,
+    },
+    RemoveFromAllowlist {
+        address: PublicKey,
+    },
+    // repay the debt of an unhealthy vault, penalty included, for the collateral
+    // it is worth. The owner keeps the rest of the collateral.
+    Liquidate {
+        collateral_holder_nonce: u8,
+    },
+    RepayMulti {
+        amount: u64,
+    },
+    // repay the debt of a multi vault that is unhealthy by the value of all
+    // of its collaterals, penalty included, for the collaterals it is worth
+    LiquidateMulti {
+        // of the collateral holders, by slot of the vault
+        collateral_holder_nonces: [u8; MAX_MULTI_VAULT_COLLATERALS],
+    },
+    // pay an outbid bid back out of the auction escrow, even after the auction
+    // is settled
+    ClaimRefund {
+        auction_authority_nonce: u8,
+    },
+}
+
+// Builders of the program instructions, with the accounts in the order
+// Processor::process expects them. State accounts passed to an Init
+// instruction must be allocated and assigned to the program beforehand, in the
+// same transaction.
+
+fn encode(
+    program_id: &Pubkey,
+    instruction: Instruction,
+    accounts: Vec<AccountMeta>,
+) -> Result<SolanaInstruction, ProgramError> {
+    let data = instruction
+        .try_to_vec()
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    Ok(SolanaInstruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+fn with_extra(mut accounts: Vec<AccountMeta>, extra_accounts: &[AccountMeta]) -> Vec<AccountMeta> {
+    accounts.extend_from_slice(extra_accounts);
+    accounts
+}
+
+/// Append the optional accounts that are passed, returning their bit set of
+/// optional_accounts.
+fn push_optional_accounts(
+    accounts: &mut Vec<AccountMeta>,
+    position_account: Option<&Pubkey>,
+    vault_delegate: Option<&Pubkey>,
+    treasury: Option<&Pubkey>,
+    allowlist: Option<&Pubkey>,
+) -> u8 {
+    let optional = [
+        (
+            optional_accounts::POSITION,
+            position_account.map(|key| AccountMeta::new_readonly(*key, false)),
+        ),
+        (
+            optional_accounts::DELEGATE,
+            vault_delegate.map(|key| AccountMeta::new(*key, false)),
+        ),
+        (
+            optional_accounts::TREASURY,
+            treasury.map(|key| AccountMeta::new(*key, false)),
+        ),
+        (
+            optional_accounts::ALLOWLIST,
+            allowlist.map(|key| AccountMeta::new_readonly(*key, false)),
+        ),
+    ];
+
+    let mut bits = 0;
+    for (bit, account) in optional.iter().cloned() {
+        if let Some(account) = account {
+            accounts.push(account);
+            bits |= bit;
+        }
+    }
+    bits
+}
+
+pub fn init_debt_type(
+    program_id: &Pubkey,
+    debt_type: &Pubkey,
+    debt_token: &Pubkey,
+    owner: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::InitDebtType {
+            debt_token: debt_token.into(),
+            owner: owner.into(),
+        },
+        vec![
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new(*debt_type, false),
+            AccountMeta::new_readonly(*debt_token, false),
+        ],
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn init_vault_type(
+    program_id: &Pubkey,
+    vault_type: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+    collateral_token: &Pubkey,
+    collateral_token_holder: &Pubkey,
+    price_oracle: &Pubkey,
+    pricing: CollateralPricing,
+    params: VaultTypeParams,
+    native: bool,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::InitVaultType {
+            debt_type: debt_type.into(),
+            collateral_token: collateral_token.into(),
+            collateral_token_holder: collateral_token_holder.into(),
+            price_oracle: price_oracle.into(),
+            pricing,
+            params,
+            native,
+        },
+        vec![
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new(*vault_type, false),
+            AccountMeta::new_readonly(*collateral_token, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new_readonly(*debt_type_owner, true),
+        ],
+    )
+}
+
+/// The vault is tokenized if a position token is given: its mint and token
+/// account, both allocated to spl-token but not initialized. The allowlist is
+/// required by a permissioned vault type.
+pub fn init_vault(
+    program_id: &Pubkey,
+    vault: &Pubkey,
+    vault_type: &Pubkey,
+    owner: &Pubkey,
+    position_token: Option<(&Pubkey, &Pubkey)>,
+    allowlist: Option<&Pubkey>,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new(*vault, false),
+    ];
+    if let Some((mint, account)) = position_token {
+        let (authority, _) = Pubkey::find_program_address(
+            &[&vault.to_bytes()[..], POSITION_ROLE.as_bytes()],
+            program_id,
+        );
+        accounts.extend(vec![
+            AccountMeta::new(*mint, false),
+            AccountMeta::new(*account, false),
+            AccountMeta::new_readonly(authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ]);
+    }
+    accounts.push(AccountMeta::new_readonly(*vault_type, false));
+    if let Some(allowlist) = allowlist {
+        accounts.push(AccountMeta::new_readonly(*allowlist, false));
+    }
+
+    encode(
+        program_id,
+        Instruction::InitVault {
+            vault_type: vault_type.into(),
+            owner: owner.into(),
+            tokenize: position_token.is_some(),
+        },
+        accounts,
+    )
+}
+
+/// `native_accounts` are the system program, the rent sysvar, the native mint
+/// and the wrap account, for a native vault type, whose collateral the
+/// authority pays in lamports.
+#[allow(clippy::too_many_arguments)]
+pub fn stake(
+    program_id: &Pubkey,
+    collateral_from: &Pubkey,
+    collateral_from_authority: &Pubkey,
+    collateral_holder: &Pubkey,
+    vault_type: &Pubkey,
+    vault: &Pubkey,
+    native_accounts: &[AccountMeta],
+    amount: u64,
+    collateral_holder_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::Stake {
+            amount,
+            collateral_holder_nonce,
+        },
+        with_extra(
+            vec![
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(*collateral_from, false),
+                AccountMeta::new(*collateral_from_authority, true),
+                AccountMeta::new(*collateral_holder, false),
+                AccountMeta::new(*vault_type, false),
+                AccountMeta::new(*vault, false),
+            ],
+            native_accounts,
+        ),
+    )
+}
+
+/// `native_accounts` are the native accounts for a native vault type. The
+/// position token account is required if the vault is tokenized, and the vault
+/// delegate if the owner isn't signing. The vault owner pays the rent of the
+/// wrap account of a native vault type.
+#[allow(clippy::too_many_arguments)]
+pub fn unstake(
+    program_id: &Pubkey,
+    collateral_holder: &Pubkey,
+    collateral_holder_authority: &Pubkey,
+    collateral_to: &Pubkey,
+    vault_type: &Pubkey,
+    vault: &Pubkey,
+    vault_owner: &Pubkey,
+    price_oracle: &Pubkey,
+    native_accounts: &[AccountMeta],
+    position_account: Option<&Pubkey>,
+    vault_delegate: Option<&Pubkey>,
+    amount: u64,
+    collateral_holder_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = with_extra(
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*collateral_holder, false),
+            AccountMeta::new_readonly(*collateral_holder_authority, false),
+            AccountMeta::new(*collateral_to, false),
+            AccountMeta::new(*vault_type, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new(*vault_owner, true),
+            AccountMeta::new_readonly(*price_oracle, false),
+        ],
+        native_accounts,
+    );
+    let optional_accounts =
+        push_optional_accounts(&mut accounts, position_account, vault_delegate, None, None);
+
+    encode(
+        program_id,
+        Instruction::Unstake {
+            amount,
+            collateral_holder_nonce,
+            optional_accounts,
+        },
+        accounts,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn repay(
+    program_id: &Pubkey,
+    debt_token: &Pubkey,
+    debt_from: &Pubkey,
+    debt_from_authority: &Pubkey,
+    debt_type: &Pubkey,
+    vault_type: &Pubkey,
+    vault: &Pubkey,
+    amount: u64,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::Repay { amount },
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*debt_token, false),
+            AccountMeta::new(*debt_from, false),
+            AccountMeta::new_readonly(*debt_from_authority, true),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new(*vault_type, false),
+            AccountMeta::new(*vault, false),
+        ],
+    )
+}
+
+/// The treasury is required if the vault type charges an origination fee, the
+/// allowlist if the vault type is permissioned, the position token account if
+/// the vault is tokenized, and the vault delegate if the owner isn't signing.
+#[allow(clippy::too_many_arguments)]
+pub fn borrow(
+    program_id: &Pubkey,
+    debt_token: &Pubkey,
+    debt_minter: &Pubkey,
+    debt_receiver: &Pubkey,
+    debt_type: &Pubkey,
+    vault_type: &Pubkey,
+    vault: &Pubkey,
+    vault_owner: &Pubkey,
+    price_oracle: &Pubkey,
+    treasury: Option<&Pubkey>,
+    allowlist: Option<&Pubkey>,
+    position_account: Option<&Pubkey>,
+    vault_delegate: Option<&Pubkey>,
+    amount: u64,
+    debt_minter_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(*debt_token, false),
+        AccountMeta::new_readonly(*debt_minter, false),
+        AccountMeta::new(*debt_receiver, false),
+        AccountMeta::new_readonly(*debt_type, false),
+        AccountMeta::new(*vault_type, false),
+        AccountMeta::new(*vault, false),
+        AccountMeta::new_readonly(*vault_owner, true),
+        AccountMeta::new_readonly(*price_oracle, false),
+    ];
+    let optional_accounts = push_optional_accounts(
+        &mut accounts,
+        position_account,
+        vault_delegate,
+        treasury,
+        allowlist,
+    );
+
+    encode(
+        program_id,
+        Instruction::Borrow {
+            amount,
+            debt_minter_nonce,
+            optional_accounts,
+        },
+        accounts,
+    )
+}
+
+pub fn close_vault(
+    program_id: &Pubkey,
+    vault: &Pubkey,
+    vault_owner: &Pubkey,
+    receiver: &Pubkey,
+    position_account: Option<&Pubkey>,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new(*vault, false),
+        AccountMeta::new_readonly(*vault_owner, true),
+        AccountMeta::new(*receiver, false),
+    ];
+    if let Some(position_account) = position_account {
+        accounts.push(AccountMeta::new_readonly(*position_account, false));
+    }
+
+    encode(program_id, Instruction::CloseVault, accounts)
+}
+
+pub fn transfer_vault(
+    program_id: &Pubkey,
+    vault: &Pubkey,
+    vault_owner: &Pubkey,
+    new_owner: &Pubkey,
+    require_accept: bool,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::TransferVault {
+            new_owner: new_owner.into(),
+            require_accept,
+        },
+        vec![
+            AccountMeta::new(*vault, false),
+            AccountMeta::new_readonly(*vault_owner, true),
+        ],
+    )
+}
+
+pub fn accept_vault(
+    program_id: &Pubkey,
+    vault: &Pubkey,
+    new_owner: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::AcceptVault,
+        vec![
+            AccountMeta::new(*vault, false),
+            AccountMeta::new_readonly(*new_owner, true),
+        ],
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn set_vault_delegate(
+    program_id: &Pubkey,
+    vault_delegate: &Pubkey,
+    vault: &Pubkey,
+    vault_owner: &Pubkey,
+    position_account: Option<&Pubkey>,
+    delegate: &Pubkey,
+    permissions: u8,
+    borrow_cap: u64,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new(*vault_delegate, false),
+        AccountMeta::new_readonly(*vault, false),
+        AccountMeta::new_readonly(*vault_owner, true),
+    ];
+    if let Some(position_account) = position_account {
+        accounts.push(AccountMeta::new_readonly(*position_account, false));
+    }
+
+    encode(
+        program_id,
+        Instruction::SetVaultDelegate {
+            delegate: delegate.into(),
+            permissions,
+            borrow_cap,
+        },
+        accounts,
+    )
+}
+
+pub fn revoke_vault_delegate(
+    program_id: &Pubkey,
+    vault_delegate: &Pubkey,
+    vault: &Pubkey,
+    vault_owner: &Pubkey,
+    receiver: &Pubkey,
+    position_account: Option<&Pubkey>,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new(*vault_delegate, false),
+        AccountMeta::new_readonly(*vault, false),
+        AccountMeta::new_readonly(*vault_owner, true),
+        AccountMeta::new(*receiver, false),
+    ];
+    if let Some(position_account) = position_account {
+        accounts.push(AccountMeta::new_readonly(*position_account, false));
+    }
+
+    encode(program_id, Instruction::RevokeVaultDelegate, accounts)
+}
+
+/// The price feed prices the token of `token_mint`.
+pub fn init_price_feed(
+    program_id: &Pubkey,
+    price_feed: &Pubkey,
+    token_mint: &Pubkey,
+    owner: &Pubkey,
+    decimals: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::InitPriceFeed {
+            owner: owner.into(),
+            decimals,
+        },
+        vec![
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new(*price_feed, false),
+            AccountMeta::new_readonly(*token_mint, false),
+        ],
+    )
+}
+
+pub fn update_price(
+    program_id: &Pubkey,
+    price_feed: &Pubkey,
+    owner: &Pubkey,
+    price: u64,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::UpdatePrice { price },
+        vec![
+            AccountMeta::new(*price_feed, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+    )
+}
+
+/// The pool tokens are the mints priced by the two price feeds.
+#[allow(clippy::too_many_arguments)]
+pub fn init_lp_pool(
+    program_id: &Pubkey,
+    lp_pool: &Pubkey,
+    owner: &Pubkey,
+    price_feed_a: &Pubkey,
+    price_feed_b: &Pubkey,
+    token_a: &Pubkey,
+    token_b: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::InitLpPool {
+            owner: owner.into(),
+            price_feed_a: price_feed_a.into(),
+            price_feed_b: price_feed_b.into(),
+        },
+        vec![
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new(*lp_pool, false),
+            AccountMeta::new_readonly(*token_a, false),
+            AccountMeta::new_readonly(*token_b, false),
+            AccountMeta::new_readonly(*price_feed_a, false),
+            AccountMeta::new_readonly(*price_feed_b, false),
+        ],
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_lp_pool(
+    program_id: &Pubkey,
+    lp_pool: &Pubkey,
+    price_feed_a: &Pubkey,
+    price_feed_b: &Pubkey,
+    owner: &Pubkey,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::UpdateLpPool {
+            reserve_a,
+            reserve_b,
+            lp_supply,
+        },
+        vec![
+            AccountMeta::new(*lp_pool, false),
+            AccountMeta::new_readonly(*price_feed_a, false),
+            AccountMeta::new_readonly(*price_feed_b, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+    )
+}
+
+pub fn refresh_lp_pool(
+    program_id: &Pubkey,
+    lp_pool: &Pubkey,
+    price_feed_a: &Pubkey,
+    price_feed_b: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::RefreshLpPool,
+        vec![
+            AccountMeta::new(*lp_pool, false),
+            AccountMeta::new_readonly(*price_feed_a, false),
+            AccountMeta::new_readonly(*price_feed_b, false),
+        ],
+    )
+}
+
+/// The vault type, if given, is one the owner is going to stake into. Its
+/// allowlist is required if it's permissioned.
+pub fn init_multi_vault(
+    program_id: &Pubkey,
+    multi_vault: &Pubkey,
+    debt_type: &Pubkey,
+    owner: &Pubkey,
+    vault_type: Option<&Pubkey>,
+    allowlist: Option<&Pubkey>,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new(*multi_vault, false),
+        AccountMeta::new_readonly(*debt_type, false),
+    ];
+    if let Some(vault_type) = vault_type {
+        accounts.push(AccountMeta::new_readonly(*vault_type, false));
+    }
+    if let Some(allowlist) = allowlist {
+        accounts.push(AccountMeta::new_readonly(*allowlist, false));
+    }
+
+    encode(
+        program_id,
+        Instruction::InitMultiVault {
+            debt_type: debt_type.into(),
+            owner: owner.into(),
+        },
+        accounts,
+    )
+}
+
+/// The allowlist is required if the vault type is permissioned.
+#[allow(clippy::too_many_arguments)]
+pub fn stake_multi(
+    program_id: &Pubkey,
+    collateral_from: &Pubkey,
+    collateral_from_authority: &Pubkey,
+    collateral_holder: &Pubkey,
+    vault_type: &Pubkey,
+    multi_vault: &Pubkey,
+    allowlist: Option<&Pubkey>,
+    amount: u64,
+    collateral_holder_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(*collateral_from, false),
+        AccountMeta::new_readonly(*collateral_from_authority, true),
+        AccountMeta::new(*collateral_holder, false),
+        AccountMeta::new(*vault_type, false),
+        AccountMeta::new(*multi_vault, false),
+    ];
+    if let Some(allowlist) = allowlist {
+        accounts.push(AccountMeta::new_readonly(*allowlist, false));
+    }
+
+    encode(
+        program_id,
+        Instruction::StakeMulti {
+            amount,
+            collateral_holder_nonce,
+        },
+        accounts,
+    )
+}
+
+/// `valuation_accounts` are the (vault type, price oracle) pairs of the
+/// collaterals left in the vault.
+#[allow(clippy::too_many_arguments)]
+pub fn unstake_multi(
+    program_id: &Pubkey,
+    collateral_holder: &Pubkey,
+    collateral_holder_authority: &Pubkey,
+    collateral_to: &Pubkey,
+    vault_type: &Pubkey,
+    multi_vault: &Pubkey,
+    vault_owner: &Pubkey,
+    valuation_accounts: &[AccountMeta],
+    amount: u64,
+    collateral_holder_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::UnstakeMulti {
+            amount,
+            collateral_holder_nonce,
+        },
+        with_extra(
+            vec![
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(*collateral_holder, false),
+                AccountMeta::new_readonly(*collateral_holder_authority, false),
+                AccountMeta::new(*collateral_to, false),
+                AccountMeta::new(*vault_type, false),
+                AccountMeta::new(*multi_vault, false),
+                AccountMeta::new_readonly(*vault_owner, true),
+            ],
+            valuation_accounts,
+        ),
+    )
+}
+
+/// `valuation_accounts` are the (vault type, price oracle) pairs of the
+/// collaterals in the vault. The treasury is required if any of their vault
+/// types charges an origination fee, and `allowlists` are those of the
+/// permissioned ones, in the same order.
+#[allow(clippy::too_many_arguments)]
+pub fn borrow_multi(
+    program_id: &Pubkey,
+    debt_token: &Pubkey,
+    debt_minter: &Pubkey,
+    debt_receiver: &Pubkey,
+    debt_type: &Pubkey,
+    multi_vault: &Pubkey,
+    vault_owner: &Pubkey,
+    valuation_accounts: &[AccountMeta],
+    treasury: Option<&Pubkey>,
+    allowlists: &[AccountMeta],
+    amount: u64,
+    debt_minter_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = with_extra(
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*debt_token, false),
+            AccountMeta::new_readonly(*debt_minter, false),
+            AccountMeta::new(*debt_receiver, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new(*multi_vault, false),
+            AccountMeta::new_readonly(*vault_owner, true),
+        ],
+        valuation_accounts,
+    );
+    if let Some(treasury) = treasury {
+        accounts.push(AccountMeta::new(*treasury, false));
+    }
+
+    encode(
+        program_id,
+        Instruction::BorrowMulti {
+            amount,
+            debt_minter_nonce,
+        },
+        with_extra(accounts, allowlists),
+    )
+}
+
+pub fn repay_multi(
+    program_id: &Pubkey,
+    debt_token: &Pubkey,
+    debt_from: &Pubkey,
+    debt_from_authority: &Pubkey,
+    debt_type: &Pubkey,
+    multi_vault: &Pubkey,
+    amount: u64,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::RepayMulti { amount },
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*debt_token, false),
+            AccountMeta::new(*debt_from, false),
+            AccountMeta::new_readonly(*debt_from_authority, true),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new(*multi_vault, false),
+        ],
+    )
+}
+
+pub fn update_vault_type_params(
+    program_id: &Pubkey,
+    vault_type: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+    params: VaultTypeParams,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::UpdateVaultTypeParams { params },
+        vec![
+            AccountMeta::new(*vault_type, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new_readonly(*debt_type_owner, true),
+        ],
+    )
+}
+
+/// The position token account and vault delegate are as in unstake.
+/// `pool_accounts` are passed to the swap program.
+#[allow(clippy::too_many_arguments)]
+pub fn deleverage(
+    program_id: &Pubkey,
+    swap_program: &Pubkey,
+    collateral_holder: &Pubkey,
+    collateral_holder_authority: &Pubkey,
+    debt_token: &Pubkey,
+    debt_account: &Pubkey,
+    debt_type: &Pubkey,
+    vault_type: &Pubkey,
+    vault: &Pubkey,
+    vault_owner: &Pubkey,
+    price_oracle: &Pubkey,
+    position_account: Option<&Pubkey>,
+    vault_delegate: Option<&Pubkey>,
+    pool_accounts: &[AccountMeta],
+    collateral_amount: u64,
+    minimum_debt_amount: u64,
+    collateral_holder_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*swap_program, false),
+        AccountMeta::new(*collateral_holder, false),
+        AccountMeta::new_readonly(*collateral_holder_authority, false),
+        AccountMeta::new(*debt_token, false),
+        AccountMeta::new(*debt_account, false),
+        AccountMeta::new_readonly(*debt_type, false),
+        AccountMeta::new(*vault_type, false),
+        AccountMeta::new(*vault, false),
+        AccountMeta::new_readonly(*vault_owner, true),
+        AccountMeta::new_readonly(*price_oracle, false),
+    ];
+    let optional_accounts =
+        push_optional_accounts(&mut accounts, position_account, vault_delegate, None, None);
+
+    encode(
+        program_id,
+        Instruction::Deleverage {
+            collateral_amount,
+            minimum_debt_amount,
+            collateral_holder_nonce,
+            optional_accounts,
+        },
+        with_extra(accounts, pool_accounts),
+    )
+}
+
+/// The program account that owns the debt token account leverage borrows to.
+pub fn leverage_authority(program_id: &Pubkey, vault_type: &Pubkey) -> Pubkey {
+    let (authority, _) = Pubkey::find_program_address(
+        &[&vault_type.to_bytes()[..], LEVERAGE_ROLE.as_bytes()],
+        program_id,
+    );
+    authority
+}
+
+/// `leverage_account` is a debt token account owned by the leverage authority.
+/// The treasury, allowlist, position token account and vault delegate are as in
+/// borrow. `pool_accounts` are passed to the swap program.
+#[allow(clippy::too_many_arguments)]
+pub fn leverage(
+    program_id: &Pubkey,
+    swap_program: &Pubkey,
+    debt_token: &Pubkey,
+    debt_minter: &Pubkey,
+    leverage_account: &Pubkey,
+    collateral_holder: &Pubkey,
+    debt_type: &Pubkey,
+    vault_type: &Pubkey,
+    vault: &Pubkey,
+    vault_owner: &Pubkey,
+    price_oracle: &Pubkey,
+    treasury: Option<&Pubkey>,
+    allowlist: Option<&Pubkey>,
+    position_account: Option<&Pubkey>,
+    vault_delegate: Option<&Pubkey>,
+    pool_accounts: &[AccountMeta],
+    amount: u64,
+    minimum_collateral_amount: u64,
+    debt_minter_nonce: u8,
+    collateral_holder_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*swap_program, false),
+        AccountMeta::new(*debt_token, false),
+        AccountMeta::new_readonly(*debt_minter, false),
+        AccountMeta::new(*leverage_account, false),
+        AccountMeta::new_readonly(leverage_authority(program_id, vault_type), false),
+        AccountMeta::new(*collateral_holder, false),
+        AccountMeta::new_readonly(*debt_type, false),
+        AccountMeta::new(*vault_type, false),
+        AccountMeta::new(*vault, false),
+        AccountMeta::new_readonly(*vault_owner, true),
+        AccountMeta::new_readonly(*price_oracle, false),
+    ];
+    let optional_accounts = push_optional_accounts(
+        &mut accounts,
+        position_account,
+        vault_delegate,
+        treasury,
+        allowlist,
+    );
+
+    encode(
+        program_id,
+        Instruction::Leverage {
+            amount,
+            minimum_collateral_amount,
+            debt_minter_nonce,
+            collateral_holder_nonce,
+            optional_accounts,
+        },
+        with_extra(accounts, pool_accounts),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn init_savings(
+    program_id: &Pubkey,
+    savings: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+    share_token: &Pubkey,
+    debt_holder: &Pubkey,
+    rate: u64,
+    savings_authority_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::InitSavings {
+            rate,
+            savings_authority_nonce,
+        },
+        vec![
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new(*savings, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new_readonly(*debt_type_owner, true),
+            AccountMeta::new_readonly(*share_token, false),
+            AccountMeta::new_readonly(*debt_holder, false),
+        ],
+    )
+}
+
+pub fn set_savings_rate(
+    program_id: &Pubkey,
+    savings: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+    rate: u64,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::SetSavingsRate { rate },
+        vec![
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new(*savings, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new_readonly(*debt_type_owner, true),
+        ],
+    )
+}
+
+/// `vault_types` are the vault types of the debt type whose stability fees
+/// the drip collects.
+#[allow(clippy::too_many_arguments)]
+pub fn drip(
+    program_id: &Pubkey,
+    savings: &Pubkey,
+    share_token: &Pubkey,
+    debt_holder: &Pubkey,
+    debt_type: &Pubkey,
+    debt_token: &Pubkey,
+    debt_minter: &Pubkey,
+    treasury: &Pubkey,
+    vault_types: &[Pubkey],
+    debt_minter_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new(*savings, false),
+        AccountMeta::new_readonly(*share_token, false),
+        AccountMeta::new(*debt_holder, false),
+        AccountMeta::new_readonly(*debt_type, false),
+        AccountMeta::new(*debt_token, false),
+        AccountMeta::new_readonly(*debt_minter, false),
+        AccountMeta::new(*treasury, false),
+    ];
+    accounts.extend(
+        vault_types
+            .iter()
+            .map(|vault_type| AccountMeta::new(*vault_type, false)),
+    );
+
+    encode(
+        program_id,
+        Instruction::Drip { debt_minter_nonce },
+        accounts,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn join_savings(
+    program_id: &Pubkey,
+    savings: &Pubkey,
+    debt_from: &Pubkey,
+    debt_from_authority: &Pubkey,
+    debt_holder: &Pubkey,
+    share_token: &Pubkey,
+    savings_authority: &Pubkey,
+    share_to: &Pubkey,
+    amount: u64,
+    savings_authority_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::JoinSavings {
+            amount,
+            savings_authority_nonce,
+        },
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(*savings, false),
+            AccountMeta::new(*debt_from, false),
+            AccountMeta::new_readonly(*debt_from_authority, true),
+            AccountMeta::new(*debt_holder, false),
+            AccountMeta::new(*share_token, false),
+            AccountMeta::new_readonly(*savings_authority, false),
+            AccountMeta::new(*share_to, false),
+        ],
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn exit_savings(
+    program_id: &Pubkey,
+    savings: &Pubkey,
+    share_token: &Pubkey,
+    share_from: &Pubkey,
+    share_from_authority: &Pubkey,
+    debt_holder: &Pubkey,
+    savings_authority: &Pubkey,
+    debt_to: &Pubkey,
+    shares: u64,
+    savings_authority_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::ExitSavings {
+            shares,
+            savings_authority_nonce,
+        },
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(*savings, false),
+            AccountMeta::new(*share_token, false),
+            AccountMeta::new(*share_from, false),
+            AccountMeta::new_readonly(*share_from_authority, true),
+            AccountMeta::new(*debt_holder, false),
+            AccountMeta::new_readonly(*savings_authority, false),
+            AccountMeta::new(*debt_to, false),
+        ],
+    )
+}
+
+pub fn init_treasury(
+    program_id: &Pubkey,
+    treasury: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+    governance_token: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::InitTreasury {
+            governance_token: governance_token.into(),
+        },
+        vec![
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new(*treasury, false),
+            AccountMeta::new(*debt_type, false),
+            AccountMeta::new_readonly(*debt_type_owner, true),
+        ],
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw_surplus(
+    program_id: &Pubkey,
+    treasury: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+    debt_token: &Pubkey,
+    debt_minter: &Pubkey,
+    debt_receiver: &Pubkey,
+    amount: u64,
+    debt_minter_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::WithdrawSurplus {
+            amount,
+            debt_minter_nonce,
+        },
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*treasury, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new_readonly(*debt_type_owner, true),
+            AccountMeta::new(*debt_token, false),
+            AccountMeta::new_readonly(*debt_minter, false),
+            AccountMeta::new(*debt_receiver, false),
+        ],
+    )
+}
+
+pub fn write_off_vault(
+    program_id: &Pubkey,
+    treasury: &Pubkey,
+    debt_type: &Pubkey,
+    vault_type: &Pubkey,
+    vault: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::WriteOffVault,
+        vec![
+            AccountMeta::new(*treasury, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new_readonly(*vault_type, false),
+            AccountMeta::new(*vault, false),
+        ],
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn start_auction(
+    program_id: &Pubkey,
+    auction: &Pubkey,
+    treasury: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+    escrow: &Pubkey,
+    kind: AuctionKind,
+    debt_amount: u64,
+    gov_amount: u64,
+    auction_authority_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::StartAuction {
+            kind,
+            debt_amount,
+            gov_amount,
+            auction_authority_nonce,
+        },
+        vec![
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new(*auction, false),
+            AccountMeta::new(*treasury, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new_readonly(*debt_type_owner, true),
+            AccountMeta::new_readonly(*escrow, false),
+        ],
+    )
+}
+
+/// Once the auction has a bid, `refund` is required to record the outbid bid.
+/// It must be allocated and assigned to the program beforehand, like the state
+/// accounts of an Init instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn bid(
+    program_id: &Pubkey,
+    auction: &Pubkey,
+    escrow: &Pubkey,
+    auction_authority: &Pubkey,
+    bid_from: &Pubkey,
+    bid_from_authority: &Pubkey,
+    payout: &Pubkey,
+    refund: Option<&Pubkey>,
+    gov_amount: u64,
+    auction_authority_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new(*auction, false),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new_readonly(*auction_authority, false),
+        AccountMeta::new(*bid_from, false),
+        AccountMeta::new_readonly(*bid_from_authority, true),
+        AccountMeta::new_readonly(*payout, false),
+    ];
+    if let Some(refund) = refund {
+        accounts.push(AccountMeta::new(*refund, false));
+    }
+
+    encode(
+        program_id,
+        Instruction::Bid {
+            gov_amount,
+            auction_authority_nonce,
+        },
+        accounts,
+    )
+}
+
+/// `refund_to` is the token account that paid the bid, and `receiver` its
+/// owner, which gets the rent of the refund account.
+pub fn claim_refund(
+    program_id: &Pubkey,
+    refund: &Pubkey,
+    escrow: &Pubkey,
+    auction_authority: &Pubkey,
+    refund_to: &Pubkey,
+    receiver: &Pubkey,
+    auction_authority_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::ClaimRefund {
+            auction_authority_nonce,
+        },
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*refund, false),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new_readonly(*auction_authority, false),
+            AccountMeta::new(*refund_to, false),
+            AccountMeta::new(*receiver, false),
+        ],
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn settle_auction(
+    program_id: &Pubkey,
+    auction: &Pubkey,
+    treasury: &Pubkey,
+    debt_type: &Pubkey,
+    escrow: &Pubkey,
+    auction_authority: &Pubkey,
+    debt_token: &Pubkey,
+    governance_token: &Pubkey,
+    debt_minter: &Pubkey,
+    payout: &Pubkey,
+    receiver: &Pubkey,
+    auction_authority_nonce: u8,
+    debt_minter_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::SettleAuction {
+            auction_authority_nonce,
+            debt_minter_nonce,
+        },
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new(*auction, false),
+            AccountMeta::new(*treasury, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new_readonly(*auction_authority, false),
+            AccountMeta::new(*debt_token, false),
+            AccountMeta::new(*governance_token, false),
+            AccountMeta::new_readonly(*debt_minter, false),
+            AccountMeta::new(*payout, false),
+            AccountMeta::new(*receiver, false),
+        ],
+    )
+}
+
+pub fn init_allowlist(
+    program_id: &Pubkey,
+    allowlist: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::InitAllowlist,
+        vec![
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new(*allowlist, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new_readonly(*debt_type_owner, true),
+        ],
+    )
+}
+
+fn update_allowlist(
+    program_id: &Pubkey,
+    instruction: Instruction,
+    allowlist: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        instruction,
+        vec![
+            AccountMeta::new(*allowlist, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new_readonly(*debt_type_owner, true),
+        ],
+    )
+}
+
+pub fn add_to_allowlist(
+    program_id: &Pubkey,
+    allowlist: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+    address: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    update_allowlist(
+        program_id,
+        Instruction::AddToAllowlist {
+            address: address.into(),
+        },
+        allowlist,
+        debt_type,
+        debt_type_owner,
+    )
+}
+
+pub fn remove_from_allowlist(
+    program_id: &Pubkey,
+    allowlist: &Pubkey,
+    debt_type: &Pubkey,
+    debt_type_owner: &Pubkey,
+    address: &Pubkey,
+) -> Result<SolanaInstruction, ProgramError> {
+    update_allowlist(
+        program_id,
+        Instruction::RemoveFromAllowlist {
+            address: address.into(),
+        },
+        allowlist,
+        debt_type,
+        debt_type_owner,
+    )
+}
+
+/// `debt_from` pays the debt, and `collateral_to` receives the collateral
+/// worth it.
+#[allow(clippy::too_many_arguments)]
+pub fn liquidate(
+    program_id: &Pubkey,
+    debt_token: &Pubkey,
+    debt_from: &Pubkey,
+    debt_from_authority: &Pubkey,
+    collateral_holder: &Pubkey,
+    collateral_holder_authority: &Pubkey,
+    collateral_to: &Pubkey,
+    treasury: &Pubkey,
+    debt_type: &Pubkey,
+    vault_type: &Pubkey,
+    vault: &Pubkey,
+    price_oracle: &Pubkey,
+    collateral_holder_nonce: u8,
+) -> Result<SolanaInstruction, ProgramError> {
+    encode(
+        program_id,
+        Instruction::Liquidate {
+            collateral_holder_nonce,
+        },
+        vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*debt_token, false),
+            AccountMeta::new(*debt_from, false),
+            AccountMeta::new_readonly(*debt_from_authority, true),
+            AccountMeta::new(*collateral_holder, false),
+            AccountMeta::new_readonly(*collateral_holder_authority, false),
+            AccountMeta::new(*collateral_to, false),
+            AccountMeta::new(*treasury, false),
+            AccountMeta::new_readonly(*debt_type, false),
+            AccountMeta::new(*vault_type, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new_readonly(*price_oracle, false),
+        ],
+    )
+}
+
+/// Accounts of a collateral of the multi vault to liquidate.
+/// `collateral_to` receives the seized collateral.
+pub struct LiquidateMultiCollateral<'a> {
+    pub vault_type: &'a Pubkey,
+    pub price_oracle: &'a Pubkey,
+    pub collateral_holder: &'a Pubkey,
+    pub collateral_holder_authority: &'a Pubkey,
+    pub collateral_to: &'a Pubkey,
+}
+
+/// `collaterals` are those in the vault, in the order of its slots, and the
+/// nonces are those of their holders, by slot.
+#[allow(clippy::too_many_arguments)]
+pub fn liquidate_multi(
+    program_id: &Pubkey,
+    debt_token: &Pubkey,
+    debt_from: &Pubkey,
+    debt_from_authority: &Pubkey,
+    treasury: &Pubkey,
+    debt_type: &Pubkey,
+    multi_vault: &Pubkey,
+    collaterals: &[LiquidateMultiCollateral],
+    collateral_holder_nonces: [u8; MAX_MULTI_VAULT_COLLATERALS],
+) -> Result<SolanaInstruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(*debt_token, false),
+        AccountMeta::new(*debt_from, false),
+        AccountMeta::new_readonly(*debt_from_authority, true),
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new_readonly(*debt_type, false),
+        AccountMeta::new(*multi_vault, false),
+    ];
+    for collateral in collaterals {
+        accounts.extend_from_slice(&[
+            AccountMeta::new(*collateral.vault_type, false),
+            AccountMeta::new_readonly(*collateral.price_oracle, false),
+            AccountMeta::new(*collateral.collateral_holder, false),
+            AccountMeta::new_readonly(*collateral.collateral_holder_authority, false),
+            AccountMeta::new(*collateral.collateral_to, false),
+        ]);
+    }
+
+    encode(
+        program_id,
+        Instruction::LiquidateMulti {
+            collateral_holder_nonces,
+        },
+        accounts,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_borrow_instruction() {
+        let program_id = Pubkey::new_unique();
+        let keys: Vec<Pubkey> = (0..11).map(|_| Pubkey::new_unique()).collect();
+
+        let instruction = borrow(
+            &program_id,
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            Some(&keys[8]),
+            Some(&keys[9]),
+            None,
+            Some(&keys[10]),
+            100,
+            254,
+        )
+        .unwrap();
+
+        assert_eq!(instruction.program_id, program_id);
+        assert_eq!(
+            Instruction::try_from_slice(&instruction.data).unwrap(),
+            Instruction::Borrow {
+                amount: 100,
+                debt_minter_nonce: 254,
+                optional_accounts: optional_accounts::DELEGATE
+                    | optional_accounts::TREASURY
+                    | optional_accounts::ALLOWLIST,
+            }
+        );
+
+        assert_eq!(instruction.accounts.len(), 12);
+        assert_eq!(instruction.accounts[0].pubkey, spl_token::id());
+        assert_eq!(instruction.accounts[6], AccountMeta::new(keys[5], false));
+        assert_eq!(
+            instruction.accounts[7],
+            AccountMeta::new_readonly(keys[6], true)
+        );
+        // the optional accounts are pushed in the order of their bits
+        assert_eq!(instruction.accounts[9], AccountMeta::new(keys[10], false));
+        assert_eq!(instruction.accounts[10], AccountMeta::new(keys[8], false));
+        assert_eq!(
+            instruction.accounts[11],
+            AccountMeta::new_readonly(keys[9], false)
+        );
+    }
 }
@@ -2,18 +2,17 @@
 
 pub mod borsh_state;
 pub mod borsh_utils;
+pub mod cpi;
 pub mod error;
+pub mod event;
+pub mod health;
 pub mod instruction;
+pub mod math;
 pub mod processor;
 pub mod state;
+pub mod swap;
 mod utils;
 
-use crate::error::Error;
-use borsh_state::InitBorshState;
-use solana_program::{
-    account_info::AccountInfo, program_error::ProgramError, program_pack::IsInitialized,
-};
-
 #[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
 
@@ -0,0 +1,321 @@
+//! Fixed point decimal math
+//!
+//! A `Decimal` is a u128 scaled by WAD, 18 decimal places. Products and
+//! quotients are computed on 256 bits, so only a result that doesn't fit
+//! overflows. Every operation that can lose precision says how it rounds: the
+//! `_floor` variants round down, the `_ceil` variants up. Rounding should
+//! always favour the protocol, e.g. down for what a vault can borrow, up for
+//! what it owes.
+
+use std::{convert::TryFrom, fmt};
+
+use crate::{error::Error, state::RATIO_BASE};
+
+use self::u256::U256;
+
+// the expansion trips clippy lints that aren't ours to fix
+#[allow(clippy::all)]
+mod u256 {
+    uint::construct_uint! {
+        pub struct U256(4);
+    }
+}
+
+/// Scale of a `Decimal`, 1.0.
+pub const WAD: u128 = 1_000_000_000_000_000_000;
+pub const WAD_DECIMALS: u8 = 18;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Decimal(u128);
+
+impl Decimal {
+    pub fn zero() -> Self {
+        Decimal(0)
+    }
+
+    pub fn one() -> Self {
+        Decimal(WAD)
+    }
+
+    /// The decimal of a raw value scaled by WAD.
+    pub fn from_raw(raw: u128) -> Self {
+        Decimal(raw)
+    }
+
+    /// The raw value, scaled by WAD.
+    pub fn raw(self) -> u128 {
+        self.0
+    }
+
+    pub fn from_u64(n: u64) -> Self {
+        // u64::MAX * WAD < u128::MAX
+        Decimal(n as u128 * WAD)
+    }
+
+    /// A ratio in basis points of RATIO_BASE, e.g. 15000 is 1.5.
+    pub fn from_bps(bps: u64) -> Self {
+        Decimal(bps as u128 * (WAD / RATIO_BASE as u128))
+    }
+
+    pub fn from_ratio_floor(numerator: u128, denominator: u128) -> Result<Self, Error> {
+        mul_div(numerator, WAD, denominator, Rounding::Floor).map(Decimal)
+    }
+
+    pub fn from_ratio_ceil(numerator: u128, denominator: u128) -> Result<Self, Error> {
+        mul_div(numerator, WAD, denominator, Rounding::Ceil).map(Decimal)
+    }
+
+    /// Whole tokens of an amount in base units of a mint with the decimals.
+    /// Exact up to 18 decimals, rounded down beyond.
+    pub fn from_amount(amount: u64, decimals: u8) -> Result<Self, Error> {
+        Decimal::from_ratio_floor(amount as u128, pow10(decimals)?)
+    }
+
+    /// Amount in base units of a mint with the decimals, rounded down.
+    pub fn try_to_amount_floor(self, decimals: u8) -> Result<u64, Error> {
+        to_u64(mul_div(self.0, pow10(decimals)?, WAD, Rounding::Floor)?)
+    }
+
+    /// Amount in base units of a mint with the decimals, rounded up.
+    pub fn try_to_amount_ceil(self, decimals: u8) -> Result<u64, Error> {
+        to_u64(mul_div(self.0, pow10(decimals)?, WAD, Rounding::Ceil)?)
+    }
+
+    pub fn try_add(self, rhs: Decimal) -> Result<Self, Error> {
+        self.0
+            .checked_add(rhs.0)
+            .map(Decimal)
+            .ok_or(Error::Overflow)
+    }
+
+    pub fn try_sub(self, rhs: Decimal) -> Result<Self, Error> {
+        self.0
+            .checked_sub(rhs.0)
+            .map(Decimal)
+            .ok_or(Error::Overflow)
+    }
+
+    pub fn try_mul_floor(self, rhs: Decimal) -> Result<Self, Error> {
+        mul_div(self.0, rhs.0, WAD, Rounding::Floor).map(Decimal)
+    }
+
+    pub fn try_mul_ceil(self, rhs: Decimal) -> Result<Self, Error> {
+        mul_div(self.0, rhs.0, WAD, Rounding::Ceil).map(Decimal)
+    }
+
+    /// Scale by numerator / denominator. Exact but for the final rounding, as
+    /// the ratio isn't rounded first.
+    pub fn try_mul_ratio_floor(self, numerator: u128, denominator: u128) -> Result<Self, Error> {
+        mul_div(self.0, numerator, denominator, Rounding::Floor).map(Decimal)
+    }
+
+    pub fn try_mul_ratio_ceil(self, numerator: u128, denominator: u128) -> Result<Self, Error> {
+        mul_div(self.0, numerator, denominator, Rounding::Ceil).map(Decimal)
+    }
+
+    /// Division by zero is an overflow.
+    pub fn try_div_floor(self, rhs: Decimal) -> Result<Self, Error> {
+        mul_div(self.0, WAD, rhs.0, Rounding::Floor).map(Decimal)
+    }
+
+    /// Division by zero is an overflow.
+    pub fn try_div_ceil(self, rhs: Decimal) -> Result<Self, Error> {
+        mul_div(self.0, WAD, rhs.0, Rounding::Ceil).map(Decimal)
+    }
+
+    /// sqrt(self * rhs), rounded down.
+    pub fn try_geometric_mean(self, rhs: Decimal) -> Result<Self, Error> {
+        // the square root of a product of two u128 fits a u128
+        let root = sqrt(U256::from(self.0) * U256::from(rhs.0));
+        Ok(Decimal(root.as_u128()))
+    }
+
+    pub fn try_floor_u64(self) -> Result<u64, Error> {
+        to_u64(self.0 / WAD)
+    }
+
+    pub fn try_ceil_u64(self) -> Result<u64, Error> {
+        to_u64(self.0.div_ceil(WAD))
+    }
+
+    /// Rounded to the nearest integer, halves up.
+    pub fn try_round_u64(self) -> Result<u64, Error> {
+        let rounded = self.0.checked_add(WAD / 2).ok_or(Error::Overflow)? / WAD;
+        to_u64(rounded)
+    }
+}
+
+impl fmt::Display for Decimal {
+    /// In full precision, without trailing zeros, e.g. 1.5
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let integer = self.0 / WAD;
+        let fraction = self.0 % WAD;
+        if fraction == 0 {
+            return write!(f, "{}", integer);
+        }
+
+        let digits = format!("{:018}", fraction);
+        write!(f, "{}.{}", integer, digits.trim_end_matches('0'))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Rounding {
+    Floor,
+    Ceil,
+}
+
+/// a * b / d on 256 bits.
+fn mul_div(a: u128, b: u128, d: u128, rounding: Rounding) -> Result<u128, Error> {
+    if d == 0 {
+        return Err(Error::Overflow);
+    }
+
+    let product = U256::from(a) * U256::from(b);
+    let d = U256::from(d);
+    let mut quotient = product / d;
+    if let Rounding::Ceil = rounding {
+        if !(product % d).is_zero() {
+            quotient += U256::one();
+        }
+    }
+
+    if quotient > U256::from(u128::MAX) {
+        return Err(Error::Overflow);
+    }
+
+    Ok(quotient.as_u128())
+}
+
+/// Integer square root, rounded down.
+fn sqrt(n: U256) -> U256 {
+    if n < U256::from(2) {
+        return n;
+    }
+
+    // newton's method, starting from a guess that is never too small
+    let mut x = n / 2 + 1;
+    let mut y = (x + n / x) / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+
+    x
+}
+
+fn pow10(decimals: u8) -> Result<u128, Error> {
+    10u128.checked_pow(decimals as u32).ok_or(Error::Overflow)
+}
+
+fn to_u64(n: u128) -> Result<u64, Error> {
+    u64::try_from(n).map_err(|_| Error::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rounding() {
+        let third = Decimal::from_ratio_floor(1, 3).unwrap();
+        assert_eq!(third.raw(), 333_333_333_333_333_333);
+        assert_eq!(
+            Decimal::from_ratio_ceil(1, 3).unwrap().raw(),
+            333_333_333_333_333_334
+        );
+
+        let three = Decimal::from_u64(3);
+        assert_eq!(third.try_mul_floor(three).unwrap().try_floor_u64(), Ok(0));
+        assert_eq!(
+            Decimal::from_ratio_ceil(1, 3)
+                .unwrap()
+                .try_mul_floor(three)
+                .unwrap()
+                .try_floor_u64(),
+            Ok(1)
+        );
+
+        let ten = Decimal::from_u64(10);
+        assert_eq!(ten.try_div_floor(three).unwrap().try_floor_u64(), Ok(3));
+        assert_eq!(ten.try_div_floor(three).unwrap().try_ceil_u64(), Ok(4));
+        assert_eq!(
+            ten.try_div_ceil(three).unwrap().raw(),
+            3_333_333_333_333_333_334
+        );
+
+        let half = Decimal::from_bps(5_000);
+        assert_eq!(half.try_round_u64(), Ok(1));
+        assert_eq!(half.try_floor_u64(), Ok(0));
+        assert_eq!(Decimal::from_bps(4_999).try_round_u64(), Ok(0));
+        assert_eq!(Decimal::from_bps(15_000).to_string(), "1.5");
+        assert_eq!(Decimal::from_u64(3).to_string(), "3");
+
+        assert_eq!(
+            Decimal::from_u64(2).try_geometric_mean(Decimal::from_u64(8)),
+            Ok(Decimal::from_u64(4))
+        );
+        assert_eq!(
+            Decimal::from_u64(2)
+                .try_geometric_mean(Decimal::one())
+                .unwrap()
+                .raw(),
+            1_414_213_562_373_095_048
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let max = Decimal::from_raw(u128::MAX);
+
+        assert_eq!(max.try_add(Decimal::from_raw(1)), Err(Error::Overflow));
+        assert_eq!(
+            Decimal::zero().try_sub(Decimal::from_raw(1)),
+            Err(Error::Overflow)
+        );
+        assert_eq!(
+            max.try_mul_floor(Decimal::from_u64(2)),
+            Err(Error::Overflow)
+        );
+        assert_eq!(
+            Decimal::one().try_div_floor(Decimal::zero()),
+            Err(Error::Overflow)
+        );
+        assert_eq!(max.try_floor_u64(), Err(Error::Overflow));
+
+        // 256 bit intermediates don't overflow
+        let big = Decimal::from_u64(u64::MAX);
+        assert_eq!(
+            big.try_mul_floor(Decimal::from_u64(2))
+                .unwrap()
+                .try_div_floor(Decimal::from_u64(2))
+                .unwrap(),
+            big
+        );
+    }
+
+    #[test]
+    fn test_amounts() {
+        // 1.5 of a 9 decimals token
+        let amount = Decimal::from_amount(1_500_000_000, 9).unwrap();
+        assert_eq!(amount, Decimal::from_bps(15_000));
+
+        // to a 6 decimals token
+        assert_eq!(amount.try_to_amount_floor(6), Ok(1_500_000));
+
+        // precision below the base unit rounds
+        let amount = Decimal::from_amount(1_234_567, 9).unwrap();
+        assert_eq!(amount.try_to_amount_floor(6), Ok(1_234));
+        assert_eq!(amount.try_to_amount_ceil(6), Ok(1_235));
+
+        assert_eq!(Decimal::from_amount(1, 39), Err(Error::Overflow));
+
+        // a ratio scales exactly, where a rounded ratio loses the last digit
+        let three = Decimal::from_u64(3);
+        assert_eq!(three.try_mul_ratio_floor(1, 3), Ok(Decimal::one()));
+        assert_eq!(
+            three.try_mul_floor(Decimal::from_ratio_floor(1, 3).unwrap()),
+            Ok(Decimal::from_raw(WAD - 1))
+        );
+    }
+}
@@ -1,26 +1,38 @@
 use solana_program::{
     account_info::AccountInfo,
-    clock::Clock,
     entrypoint::ProgramResult,
-    msg,
+    instruction::AccountMeta,
     program::invoke_signed,
     program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar},
+    system_instruction,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
 use crate::{
     borsh_state::{BorshState, InitBorshState},
     error::Error,
-    instruction::Instruction,
-    state::{DebtType, PublicKey, Vault, VaultType},
-    utils::Accounts,
+    event::Event,
+    health::{self, Collateral, Price},
+    instruction::{optional_accounts, Instruction},
+    state::{
+        delegate_permission, Allowlist, Auction, AuctionKind, AuctionRefund, CollateralBalance,
+        CollateralPricing, DebtType, LpPool, MultiVault, PriceFeed, PublicKey, Savings, Treasury,
+        Vault, VaultDelegate, VaultType, VaultTypeParams, ACCUMULATOR_BASE, AUCTION_DURATION,
+        MAX_MULTI_VAULT_COLLATERALS,
+    },
+    swap,
+    utils::{close_account, Accounts},
 };
 use borsh::BorshDeserialize;
 
 struct InitDebtTypeContext<'a> {
     rent: Rent,
     debt_type: &'a AccountInfo<'a>, // writable
+    debt_token_mint: &'a AccountInfo<'a>,
+
     debt_token: PublicKey,
     owner: PublicKey,
 }
@@ -29,62 +41,257 @@ impl<'a> InitDebtTypeContext<'a> {
     fn process(&self) -> ProgramResult {
         let mut debt_type = DebtType::init_uninitialized(self.debt_type)?;
 
+        if !self.debt_token.is_account(self.debt_token_mint) {
+            return Err(Error::InvalidDebtToken)?;
+        }
+        let mint = unpack_mint(self.debt_token_mint)?;
+
         debt_type.is_initialized = true;
         debt_type.debt_token = self.debt_token.clone();
+        debt_type.decimals = mint.decimals;
         debt_type.owner = self.owner.clone();
 
         debt_type.save_exempt(self.debt_type, &self.rent)?;
 
-        Ok(())
+        Event::DebtTypeInitialized {
+            debt_type: self.debt_type.into(),
+            debt_token: self.debt_token.clone(),
+            owner: self.owner.clone(),
+        }
+        .log()
     }
 }
 
 struct InitVaultTypeContext<'a> {
+    program_id: &'a Pubkey,
+
     rent: Rent,
     vault_type: &'a AccountInfo<'a>, // writable
+    collateral_token_mint: &'a AccountInfo<'a>,
+    debt_type_account: &'a AccountInfo<'a>,
+    debt_type_owner: &'a AccountInfo<'a>, // signed
 
     debt_type: PublicKey,
     collateral_token: PublicKey,
     collateral_token_holder: PublicKey,
     price_oracle: PublicKey,
+    pricing: CollateralPricing,
+    params: VaultTypeParams,
+    native: bool,
 }
 
 impl<'a> InitVaultTypeContext<'a> {
     fn process(&self) -> ProgramResult {
         let mut vtype = VaultType::init_uninitialized(self.vault_type)?;
-        // TODO: check ownership over debt type
+
+        self.params.validate()?;
+
+        if self.native
+            && self
+                .collateral_token
+                .ne(&PublicKey(spl_token::native_mint::id().to_bytes()))
+        {
+            return Err(Error::InvalidCollateralToken)?;
+        }
+
+        if !self.collateral_token.is_account(self.collateral_token_mint) {
+            return Err(Error::InvalidCollateralToken)?;
+        }
+        let mint = unpack_mint(self.collateral_token_mint)?;
+
+        check_program_owned(self.program_id, self.debt_type_account)?;
+        if !self.debt_type.is_account(self.debt_type_account) {
+            return Err(Error::DebtTypeMismatch)?;
+        }
+        let debt_type = DebtType::load_initialized(self.debt_type_account)?;
+        check_signed_by(&debt_type.owner, self.debt_type_owner)?;
 
         vtype.is_initialized = true;
         vtype.debt_type = self.debt_type.clone();
+        vtype.debt_decimals = debt_type.decimals;
+        vtype.collateral_decimals = mint.decimals;
         vtype.price_oracle = self.price_oracle.clone();
+        vtype.pricing = self.pricing;
         vtype.collateral_token = self.collateral_token.clone();
         vtype.collateral_token_holder = self.collateral_token_holder.clone();
+        vtype.params = self.params.clone();
+        vtype.native = self.native;
+        vtype.debt_accumulator = ACCUMULATOR_BASE;
+        vtype.last_accrual = Clock::get()?.unix_timestamp;
 
         vtype.save_exempt(self.vault_type, &self.rent)?;
 
-        Ok(())
+        Event::VaultTypeInitialized {
+            vault_type: self.vault_type.into(),
+            debt_type: self.debt_type.clone(),
+            collateral_token: self.collateral_token.clone(),
+            price_oracle: self.price_oracle.clone(),
+            params: self.params.clone(),
+        }
+        .log()
     }
 }
 
 struct InitVaultContext<'a> {
+    program_id: &'a Pubkey,
+
     rent: Rent,
     vault: &'a AccountInfo<'a>, // writable
 
+    position_token: Option<PositionTokenAccounts<'a>>,
+
+    vault_type_account: &'a AccountInfo<'a>,
+    // for a permissioned vault type
+    allowlist: Option<&'a AccountInfo<'a>>,
+
     vault_type: PublicKey,
     owner: PublicKey,
 }
 
+struct PositionTokenAccounts<'a> {
+    token_program: &'a AccountInfo<'a>,
+    rent: &'a AccountInfo<'a>,
+
+    mint: &'a AccountInfo<'a>,      // writable, uninitialized
+    account: &'a AccountInfo<'a>,   // writable, uninitialized
+    authority: &'a AccountInfo<'a>, // [vault, POSITION_ROLE]
+}
+
 impl<'a> InitVaultContext<'a> {
     fn process(&self) -> ProgramResult {
         let mut vault = Vault::init_uninitialized(self.vault)?;
 
+        if !self.vault_type.is_account(self.vault_type_account) {
+            return Err(Error::VaultTypeMismatch)?;
+        }
+
+        check_program_owned(self.program_id, self.vault_type_account)?;
+        let vault_type = VaultType::load_initialized(self.vault_type_account)?;
+        check_allowlisted(self.program_id, &vault_type, self.allowlist, &self.owner)?;
+
         vault.is_initialized = true;
         vault.vault_type = self.vault_type.clone();
         vault.owner = self.owner.clone();
+
+        if let Some(position_token) = &self.position_token {
+            self.create_position_token(position_token)?;
+            vault.position_token = position_token.mint.into();
+        }
+
         vault.save_exempt(self.vault, &self.rent)?;
 
-        Ok(())
+        Event::VaultInitialized {
+            vault: self.vault.into(),
+            vault_type: self.vault_type.clone(),
+            owner: self.owner.clone(),
+        }
+        .log()
+    }
+
+    /// Mint the one position token of the vault to the owner, and revoke the
+    /// mint authority. The mint has to be new, so that a position token can't
+    /// back more than one vault.
+    fn create_position_token(&self, accounts: &PositionTokenAccounts<'a>) -> ProgramResult {
+        let (authority, nonce) = Pubkey::find_program_address(
+            &[&self.vault.key.to_bytes()[..], POSITION_ROLE.as_bytes()],
+            self.program_id,
+        );
+        if authority.ne(accounts.authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+        let seeds: &[&[u8]] = &[
+            &self.vault.key.to_bytes()[..],
+            POSITION_ROLE.as_bytes(),
+            &[nonce],
+        ];
+
+        // initializing fails if the mint is already in use
+        invoke_signed(
+            &spl_token::instruction::initialize_mint(
+                accounts.token_program.key,
+                accounts.mint.key,
+                accounts.authority.key,
+                None,
+                0,
+            )?,
+            &[
+                accounts.mint.clone(),
+                accounts.rent.clone(),
+                accounts.token_program.clone(),
+            ],
+            &[],
+        )?;
+        invoke_signed(
+            &spl_token::instruction::initialize_account2(
+                accounts.token_program.key,
+                accounts.account.key,
+                accounts.mint.key,
+                &self.owner.clone().into(),
+            )?,
+            &[
+                accounts.account.clone(),
+                accounts.mint.clone(),
+                accounts.rent.clone(),
+                accounts.token_program.clone(),
+            ],
+            &[],
+        )?;
+
+        mint_tokens(
+            accounts.token_program,
+            accounts.mint,
+            accounts.account,
+            accounts.authority,
+            &[seeds],
+            1,
+        )?;
+
+        invoke_signed(
+            &spl_token::instruction::set_authority(
+                accounts.token_program.key,
+                accounts.mint.key,
+                None,
+                spl_token::instruction::AuthorityType::MintTokens,
+                accounts.authority.key,
+                &[],
+            )?,
+            &[
+                accounts.mint.clone(),
+                accounts.authority.clone(),
+                accounts.token_program.clone(),
+            ],
+            &[seeds],
+        )
+    }
+}
+
+/// Check that the address is on the allowlist of a permissioned vault type.
+fn check_allowlisted(
+    program_id: &Pubkey,
+    vault_type: &VaultType,
+    allowlist: Option<&AccountInfo>,
+    address: &PublicKey,
+) -> ProgramResult {
+    if !vault_type.params.is_permissioned() {
+        return Ok(());
+    }
+
+    let allowlist_account = allowlist.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if !vault_type.params.allowlist.is_account(allowlist_account) {
+        return Err(Error::AllowlistMismatch.into());
+    }
+
+    check_program_owned(program_id, allowlist_account)?;
+    let allowlist = Allowlist::load_initialized(allowlist_account)?;
+    if allowlist.debt_type.ne(&vault_type.debt_type) {
+        return Err(Error::AllowlistMismatch.into());
+    }
+
+    if !allowlist.contains(address) {
+        return Err(Error::NotAllowlisted.into());
     }
+
+    Ok(())
 }
 
 fn expected_program_account_pubkey(
@@ -96,241 +303,3825 @@ fn expected_program_account_pubkey(
     // https://docs.rs/solana-sdk/1.5.10/solana_sdk/pubkey/struct.Pubkey.html#method.create_program_address
     Pubkey::create_program_address(seeds, program_id).map_err(|_err| ProgramError::InvalidSeeds)
 }
-struct StakeContext<'a> {
-    program_id: &'a Pubkey,
-
-    token_program: &'a AccountInfo<'a>,
 
-    collateral_from: &'a AccountInfo<'a>,           // writable
-    collateral_from_authority: &'a AccountInfo<'a>, // signed
-    collateral_to: &'a AccountInfo<'a>,             // writable
+fn check_signed_by(owner: &PublicKey, signer: &AccountInfo) -> ProgramResult {
+    if owner.ne(&PublicKey(signer.key.to_bytes())) {
+        return Err(Error::OwnerMismatch.into());
+    }
 
-    vault_type: &'a AccountInfo<'a>,
-    vault: &'a AccountInfo<'a>, // writable
+    if !signer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
 
-    amount: u64,
-    collateral_holder_nonce: u8,
+    Ok(())
 }
 
-static COLLATERAL_HOLDER_ROLE: &str = "holder";
-static MINTER_ROLE: &str = "minter";
+/// Resolve the owner of the vault. The owner of a tokenized vault is whoever
+/// holds the position token, proven by the token account holding it.
+fn vault_owner(
+    vault: &Vault,
+    position_account: Option<&AccountInfo>,
+) -> Result<PublicKey, ProgramError> {
+    if !vault.is_tokenized() {
+        return Ok(vault.owner.clone());
+    }
 
-impl<'a> StakeContext<'a> {
-    fn process(&self) -> ProgramResult {
-        let mut vault_type = VaultType::load_initialized(self.vault_type)?;
-        let mut vault = Vault::load_initialized(self.vault)?;
+    let position_account = position_account.ok_or(Error::InvalidPositionToken)?;
+    let position = unpack_token_account(position_account)?;
+    if vault
+        .position_token
+        .ne(&PublicKey(position.mint.to_bytes()))
+        || position.amount != 1
+    {
+        return Err(Error::InvalidPositionToken.into());
+    }
 
-        if vault.vault_type.ne(&self.vault_type.into()) {
-            return Err(Error::VaultTypeMismatch)?;
-        }
+    Ok(PublicKey(position.owner.to_bytes()))
+}
 
-        if vault_type
-            .collateral_token_holder
-            .ne(&self.collateral_to.into())
-        {
-            return Err(Error::CollateralHolderAccountMismatch)?;
+/// Optional accounts that follow the fixed accounts of an instruction acting on
+/// behalf of the vault owner, in the order of the optional_accounts bits set in
+/// the instruction data.
+struct OptionalAccounts<'a> {
+    // token account holding the position token, if the vault is tokenized
+    position_account: Option<&'a AccountInfo<'a>>,
+    // writable, if the signer is a delegate
+    vault_delegate: Option<&'a AccountInfo<'a>>,
+    // writable, if the vault type charges an origination fee
+    treasury: Option<&'a AccountInfo<'a>>,
+    // if the vault type is permissioned
+    allowlist: Option<&'a AccountInfo<'a>>,
+}
+
+impl<'a> OptionalAccounts<'a> {
+    /// Take the accounts of the bits, returning the accounts left after them.
+    fn take(
+        bits: u8,
+        accounts: &'a [AccountInfo<'a>],
+    ) -> Result<(Self, &'a [AccountInfo<'a>]), ProgramError> {
+        if bits & !optional_accounts::ALL != 0 {
+            return Err(ProgramError::InvalidInstructionData);
         }
 
-        // transfer from user token account to collateral holding account
-        self.stake_collateral()?;
+        let mut rest = accounts;
+        let mut take = |bit: u8| -> Result<Option<&'a AccountInfo<'a>>, ProgramError> {
+            if bits & bit == 0 {
+                return Ok(None);
+            }
+            let (account, tail) = rest
+                .split_first()
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            rest = tail;
+            Ok(Some(account))
+        };
 
-        vault.collateral_amount = vault
-            .collateral_amount
-            .checked_add(self.amount)
-            .ok_or(Error::Overflow)?;
+        let optional = OptionalAccounts {
+            position_account: take(optional_accounts::POSITION)?,
+            vault_delegate: take(optional_accounts::DELEGATE)?,
+            treasury: take(optional_accounts::TREASURY)?,
+            allowlist: take(optional_accounts::ALLOWLIST)?,
+        };
+        Ok((optional, rest))
+    }
+}
 
-        vault.save(self.vault)?;
+/// The signer must be either the vault owner, or a delegate that the current
+/// owner granted the permission to. Returns the vault owner, and the delegate
+/// state if the signer is a delegate.
+fn check_vault_authority(
+    program_id: &Pubkey,
+    vault_account: &AccountInfo,
+    vault: &Vault,
+    signer: &AccountInfo,
+    position_account: Option<&AccountInfo>,
+    vault_delegate: Option<&AccountInfo>,
+    permission: u8,
+) -> Result<(PublicKey, Option<VaultDelegate>), ProgramError> {
+    if !signer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
 
-        Ok(())
+    let owner = vault_owner(vault, position_account)?;
+    if owner.is_account(signer) {
+        return Ok((owner, None));
     }
 
-    fn stake_collateral(&self) -> ProgramResult {
-        let inx = spl_token::instruction::transfer(
-            self.token_program.key,
-            self.collateral_from.key,
-            self.collateral_to.key,
-            self.collateral_from_authority.key,
-            &[],
-            self.amount,
-        )?;
+    let vault_delegate = vault_delegate.ok_or(Error::OwnerMismatch)?;
+    check_program_owned(program_id, vault_delegate)?;
+    let delegate = VaultDelegate::load_initialized(vault_delegate)?;
 
-        invoke_signed(
-            &inx,
-            &[
-                self.token_program.clone(),
-                self.collateral_from.clone(),
-                self.collateral_to.clone(),
-                self.collateral_from_authority.clone(),
-            ],
-            &[],
-        )
+    if !delegate.vault.is_account(vault_account)
+        || delegate.vault_owner.ne(&owner)
+        || !delegate.delegate.is_account(signer)
+    {
+        return Err(Error::DelegateMismatch.into());
     }
+
+    if delegate.permissions & permission == 0 {
+        return Err(Error::DelegatePermissionDenied.into());
+    }
+
+    Ok((owner, Some(delegate)))
 }
 
-struct BorrowContext<'a> {
-    program_id: &'a Pubkey,
+fn unpack_token_account(account: &AccountInfo) -> Result<spl_token::state::Account, ProgramError> {
+    if account.owner.ne(&spl_token::id()) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
-    token_program: &'a AccountInfo<'a>,
+    spl_token::state::Account::unpack(&account.data.borrow())
+}
 
-    debt_token: &'a AccountInfo<'a>,    // writable
-    debt_minter: &'a AccountInfo<'a>,   // Program pubkey, writable
-    debt_receiver: &'a AccountInfo<'a>, // writable
+fn unpack_mint(account: &AccountInfo) -> Result<spl_token::state::Mint, ProgramError> {
+    if account.owner.ne(&spl_token::id()) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
-    debt_type: &'a AccountInfo<'a>,
-    vault_type: &'a AccountInfo<'a>,
-    vault: &'a AccountInfo<'a>,       // writable
-    vault_owner: &'a AccountInfo<'a>, // signed
+    spl_token::state::Mint::unpack(&account.data.borrow())
+}
 
-    price_oracle: &'a AccountInfo<'a>,
+fn check_token_account_owner(account: &AccountInfo, owner: &PublicKey) -> ProgramResult {
+    let token_account = unpack_token_account(account)?;
+    if owner.ne(&PublicKey(token_account.owner.to_bytes())) {
+        return Err(Error::OwnerMismatch.into());
+    }
 
-    amount: u64,
-    debt_minter_nonce: u8,
+    Ok(())
 }
 
-impl<'a> BorrowContext<'a> {
-    fn process(&self) -> ProgramResult {
-        let (_debt_type, _vault_type, mut vault) = self.load_state_checked()?;
+/// State that isn't saved by an instruction has to be checked to be owned by
+/// the program, otherwise anyone could fake it.
+fn check_program_owned(program_id: &Pubkey, account: &AccountInfo) -> ProgramResult {
+    if account.owner.ne(program_id) {
+        return Err(Error::UnexpectedProgramAccount.into());
+    }
 
-        let debt_minter_seeds = &[
-            &self.debt_type.key.to_bytes()[..],
-            MINTER_ROLE.as_bytes(),
-            &[self.debt_minter_nonce],
-        ];
+    Ok(())
+}
 
-        // NOTE: probably not necessary to check the debt_minter. If the client
-        // passes invalid nonce or debt_minter, it will fail to mint anyway.
-        let debt_minter = self.program_pubkey(debt_minter_seeds)?;
-        if debt_minter.ne(self.debt_minter.key) {
-            return Err(Error::UnexpectedProgramAccount)?;
+/// The holder token account must be controlled by the program, otherwise the
+/// collateral could never be unstaked.
+fn check_collateral_holder(
+    program_id: &Pubkey,
+    vault_type_account: &AccountInfo,
+    vault_type: &VaultType,
+    holder: &AccountInfo,
+    collateral_holder_nonce: u8,
+) -> ProgramResult {
+    if !vault_type.collateral_token_holder.is_account(holder) {
+        return Err(Error::CollateralHolderAccountMismatch.into());
+    }
+
+    let holder = unpack_token_account(holder)?;
+    let holder_authority = expected_program_account_pubkey(
+        program_id,
+        &[
+            &vault_type_account.key.to_bytes()[..],
+            COLLATERAL_HOLDER_ROLE.as_bytes(),
+            &[collateral_holder_nonce],
+        ],
+    )?;
+    if holder.owner.ne(&holder_authority) {
+        return Err(Error::CollateralHolderAccountMismatch.into());
+    }
+
+    Ok(())
+}
+
+/// Price of the collateral of a vault type, from its oracle.
+fn collateral_price(
+    program_id: &Pubkey,
+    vault_type_account: &AccountInfo,
+    vault_type: &VaultType,
+    price_oracle: &AccountInfo,
+) -> Result<Price, ProgramError> {
+    check_program_owned(program_id, vault_type_account)?;
+    check_program_owned(program_id, price_oracle)?;
+
+    if !vault_type.price_oracle.is_account(price_oracle) {
+        return Err(Error::InvalidPriceOracle.into());
+    }
+
+    let price = match vault_type.pricing {
+        CollateralPricing::PriceFeed => {
+            Price::from_price_feed(&PriceFeed::load_initialized(price_oracle)?, vault_type)?
+        }
+        CollateralPricing::LpPool => {
+            // the pool value is cached, so it has to be refreshed at the current
+            // prices earlier in the transaction
+            let lp_pool = LpPool::load_initialized(price_oracle)?;
+            if lp_pool.last_update != Clock::get()?.slot {
+                return Err(Error::StaleLpPool.into());
+            }
+            Price::from_lp_pool(&lp_pool, vault_type)?
         }
+    };
 
-        // TODO: check debt ceiling against the price feed
+    Ok(price)
+}
 
-        self.mint_debt_to_receiver(debt_minter_seeds, self.amount)?;
+/// Max debt that the collateral of a vault type can back at the oracle price.
+fn max_debt_for_collateral(
+    program_id: &Pubkey,
+    vault_type_account: &AccountInfo,
+    vault_type: &VaultType,
+    price_oracle: &AccountInfo,
+    collateral_amount: u64,
+) -> Result<u64, ProgramError> {
+    let price = collateral_price(program_id, vault_type_account, vault_type, price_oracle)?;
 
-        vault.debt_amount = vault
-            .debt_amount
-            .checked_add(self.amount)
-            .ok_or(Error::Overflow)?;
-        vault.save(self.vault)?;
+    Ok(health::max_debt(vault_type, &price, collateral_amount)?)
+}
 
-        Ok(())
+fn check_vault_health(
+    program_id: &Pubkey,
+    vault_type_account: &AccountInfo,
+    vault_type: &VaultType,
+    price_oracle: &AccountInfo,
+    vault: &Vault,
+) -> ProgramResult {
+    // a vault without debt is always healthy, even without a price
+    if vault.debt_amount == 0 {
+        return Ok(());
     }
 
-    fn load_state_checked(&self) -> Result<(DebtType, VaultType, Vault), ProgramError> {
-        let debt_type = DebtType::load_initialized(self.debt_type)?;
-        let vault_type = VaultType::load_initialized(self.vault_type)?;
-        let vault = Vault::load_initialized(self.vault)?;
+    let price = collateral_price(program_id, vault_type_account, vault_type, price_oracle)?;
+    if !health::vault_health(vault, vault_type, &price)?.is_healthy() {
+        return Err(Error::CollateralRatioTooLow.into());
+    }
 
-        if debt_type.debt_token.ne(&self.debt_token.into()) {
-            return Err(Error::InvalidDebtToken)?;
-        }
+    Ok(())
+}
 
-        if vault_type.debt_type.ne(&self.debt_type.into()) {
-            return Err(Error::DebtTypeMismatch)?;
+fn transfer_tokens<'a>(
+    token_program: &AccountInfo<'a>,
+    from: &AccountInfo<'a>,
+    to: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> ProgramResult {
+    let inx = spl_token::instruction::transfer(
+        token_program.key,
+        from.key,
+        to.key,
+        authority.key,
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &inx,
+        &[
+            token_program.clone(),
+            from.clone(),
+            to.clone(),
+            authority.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+fn mint_tokens<'a>(
+    token_program: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    to: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> ProgramResult {
+    let inx = spl_token::instruction::mint_to(
+        token_program.key,
+        mint.key,
+        to.key,
+        authority.key,
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &inx,
+        &[
+            mint.clone(),
+            to.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+fn burn_tokens<'a>(
+    token_program: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    from: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> ProgramResult {
+    let inx = spl_token::instruction::burn(
+        token_program.key,
+        from.key,
+        mint.key,
+        authority.key,
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &inx,
+        &[
+            from.clone(),
+            mint.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+/// Check the chain of vault -> vault type -> debt type -> debt token.
+fn check_debt_token(
+    program_id: &Pubkey,
+    debt_token: &AccountInfo,
+    debt_type_account: &AccountInfo,
+    vault_type_account: &AccountInfo,
+    vault_type: &VaultType,
+    vault: &Vault,
+) -> ProgramResult {
+    check_program_owned(program_id, debt_type_account)?;
+    check_program_owned(program_id, vault_type_account)?;
+
+    if !vault.vault_type.is_account(vault_type_account) {
+        return Err(Error::VaultTypeMismatch.into());
+    }
+
+    if !vault_type.debt_type.is_account(debt_type_account) {
+        return Err(Error::DebtTypeMismatch.into());
+    }
+
+    let debt_type = DebtType::load_initialized(debt_type_account)?;
+    if !debt_type.debt_token.is_account(debt_token) {
+        return Err(Error::InvalidDebtToken.into());
+    }
+
+    Ok(())
+}
+
+/// Accounts needed to wrap and unwrap native SOL, following the fixed accounts
+/// of Stake and Unstake for a native vault type.
+struct NativeAccounts<'a> {
+    system_program: &'a AccountInfo<'a>,
+    rent: &'a AccountInfo<'a>,
+    native_mint: &'a AccountInfo<'a>,
+
+    // program account used as a temporary wrapped SOL account
+    wrap_account: &'a AccountInfo<'a>, // writable
+}
+
+impl<'a> NativeAccounts<'a> {
+    /// Split off the native accounts if the vault type takes native SOL.
+    fn split(
+        vault_type: &VaultType,
+        accounts: &'a [AccountInfo<'a>],
+    ) -> Result<(Option<Self>, &'a [AccountInfo<'a>]), ProgramError> {
+        if !vault_type.native {
+            return Ok((None, accounts));
         }
 
-        if vault_type.price_oracle.ne(&self.price_oracle.into()) {
-            return Err(Error::InvalidPriceOracle)?;
+        match accounts {
+            [system_program, rent, native_mint, wrap_account, rest @ ..] => Ok((
+                Some(NativeAccounts {
+                    system_program,
+                    rent,
+                    native_mint,
+                    wrap_account,
+                }),
+                rest,
+            )),
+            _ => Err(ProgramError::NotEnoughAccountKeys),
         }
+    }
 
-        if vault.vault_type.ne(&self.vault_type.into()) {
-            return Err(Error::VaultTypeMismatch)?;
+    /// Create the temporary wrapped SOL account, funded by the payer with the
+    /// lamports to wrap. The account is its own token owner, so that only the
+    /// program can move the wrapped SOL.
+    fn wrap(
+        &self,
+        program_id: &Pubkey,
+        token_program: &AccountInfo<'a>,
+        vault_type: &AccountInfo,
+        payer: &AccountInfo<'a>,
+        lamports: u64,
+    ) -> Result<u8, ProgramError> {
+        if self.native_mint.key.ne(&spl_token::native_mint::id()) {
+            return Err(Error::InvalidCollateralToken.into());
         }
 
-        if vault.owner.ne(&self.vault_owner.into()) {
-            return Err(Error::OwnerMismatch)?;
+        let (wrap_account, nonce) = Pubkey::find_program_address(
+            &[&vault_type.key.to_bytes()[..], WRAP_ROLE.as_bytes()],
+            program_id,
+        );
+        if wrap_account.ne(self.wrap_account.key) {
+            return Err(Error::UnexpectedProgramAccount.into());
         }
+        let seeds: &[&[u8]] = &[
+            &vault_type.key.to_bytes()[..],
+            WRAP_ROLE.as_bytes(),
+            &[nonce],
+        ];
 
-        Ok((debt_type, vault_type, vault))
-    }
+        let rent = Rent::from_account_info(self.rent)?;
+        let len = spl_token::state::Account::LEN;
+        let lamports = lamports
+            .checked_add(rent.minimum_balance(len))
+            .ok_or(Error::Overflow)?;
 
-    fn mint_debt_to_receiver(&self, seeds: &[&[u8]], amount: u64) -> ProgramResult {
-        let mint = spl_token::instruction::mint_to(
-            self.token_program.key,
-            self.debt_token.key,
-            self.debt_receiver.key,
-            self.debt_minter.key,
+        // transfer, allocate and assign rather than create_account, which would
+        // fail if someone sent lamports to the address beforehand.
+        invoke_signed(
+            &system_instruction::transfer(payer.key, self.wrap_account.key, lamports),
+            &[
+                payer.clone(),
+                self.wrap_account.clone(),
+                self.system_program.clone(),
+            ],
             &[],
-            amount,
+        )?;
+        invoke_signed(
+            &system_instruction::allocate(self.wrap_account.key, len as u64),
+            &[self.wrap_account.clone(), self.system_program.clone()],
+            &[seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(self.wrap_account.key, token_program.key),
+            &[self.wrap_account.clone(), self.system_program.clone()],
+            &[seeds],
         )?;
 
         invoke_signed(
-            &mint,
+            &spl_token::instruction::initialize_account(
+                token_program.key,
+                self.wrap_account.key,
+                self.native_mint.key,
+                self.wrap_account.key,
+            )?,
             &[
-                self.debt_token.clone(),
-                self.debt_receiver.clone(),
-                self.debt_minter.clone(),
-                self.token_program.clone(),
+                self.wrap_account.clone(),
+                self.native_mint.clone(),
+                self.rent.clone(),
+                token_program.clone(),
             ],
-            &[seeds],
+            &[],
         )?;
 
-        Ok(())
+        Ok(nonce)
     }
 
-    fn program_pubkey(&self, seeds: &[&[u8]]) -> Result<Pubkey, ProgramError> {
-        // is it ok to trust the nonce passed by the client?, i think so.
-        //
-        // https://docs.rs/solana-sdk/1.5.10/solana_sdk/pubkey/struct.Pubkey.html#method.create_program_address
-        Pubkey::create_program_address(seeds, self.program_id)
-            .map_err(|_err| ProgramError::InvalidSeeds)
-    }
-}
+    /// Close the temporary wrapped SOL account, unwrapping all its lamports to
+    /// the receiver.
+    fn unwrap(
+        &self,
+        token_program: &AccountInfo<'a>,
+        vault_type: &AccountInfo,
+        receiver: &AccountInfo<'a>,
+        nonce: u8,
+    ) -> ProgramResult {
+        let seeds: &[&[u8]] = &[
+            &vault_type.key.to_bytes()[..],
+            WRAP_ROLE.as_bytes(),
+            &[nonce],
+        ];
+
+        invoke_signed(
+            &spl_token::instruction::close_account(
+                token_program.key,
+                self.wrap_account.key,
+                receiver.key,
+                self.wrap_account.key,
+                &[],
+            )?,
+            &[
+                self.wrap_account.clone(),
+                receiver.clone(),
+                token_program.clone(),
+            ],
+            &[seeds],
+        )
+    }
+}
+
+struct StakeContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    collateral_from: &'a AccountInfo<'a>,           // writable
+    collateral_from_authority: &'a AccountInfo<'a>, // signed
+    collateral_to: &'a AccountInfo<'a>,             // writable
+
+    vault_type: &'a AccountInfo<'a>, // writable
+    vault: &'a AccountInfo<'a>,      // writable
+
+    // native accounts, for a native vault type
+    extra_accounts: &'a [AccountInfo<'a>],
+
+    amount: u64,
+    collateral_holder_nonce: u8,
+}
+
+pub static COLLATERAL_HOLDER_ROLE: &str = "holder";
+pub static MINTER_ROLE: &str = "minter";
+pub static WRAP_ROLE: &str = "wrap";
+pub static SAVINGS_ROLE: &str = "savings";
+pub static AUCTION_ROLE: &str = "auction";
+pub static POSITION_ROLE: &str = "position";
+pub static LEVERAGE_ROLE: &str = "leverage";
+
+impl<'a> StakeContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault_type = VaultType::load_initialized(self.vault_type)?;
+        let mut vault = Vault::load_initialized(self.vault)?;
+
+        if vault.vault_type.ne(&self.vault_type.into()) {
+            return Err(Error::VaultTypeMismatch)?;
+        }
+
+        check_collateral_holder(
+            self.program_id,
+            self.vault_type,
+            &vault_type,
+            self.collateral_to,
+            self.collateral_holder_nonce,
+        )?;
+
+        vault_type.add_collateral(self.amount)?;
+
+        // transfer from user token account to collateral holding account
+        match NativeAccounts::split(&vault_type, self.extra_accounts)? {
+            (Some(native), _) => self.stake_native(&native)?,
+            (None, _) => self.stake_collateral()?,
+        }
+
+        vault.collateral_amount = vault
+            .collateral_amount
+            .checked_add(self.amount)
+            .ok_or(Error::Overflow)?;
+
+        vault_type.save(self.vault_type)?;
+        vault.save(self.vault)?;
+
+        Event::VaultStaked {
+            vault: self.vault.into(),
+            vault_type: self.vault_type.into(),
+            amount: self.amount,
+            collateral_amount: vault.collateral_amount,
+        }
+        .log()
+    }
+
+    fn stake_collateral(&self) -> ProgramResult {
+        transfer_tokens(
+            self.token_program,
+            self.collateral_from,
+            self.collateral_to,
+            self.collateral_from_authority,
+            &[],
+            self.amount,
+        )
+    }
+
+    /// Wrap lamports of the signer, and move the wrapped SOL to the holder. The
+    /// rent of the temporary account goes back to the signer.
+    fn stake_native(&self, native: &NativeAccounts<'a>) -> ProgramResult {
+        let nonce = native.wrap(
+            self.program_id,
+            self.token_program,
+            self.vault_type,
+            self.collateral_from_authority,
+            self.amount,
+        )?;
+
+        transfer_tokens(
+            self.token_program,
+            native.wrap_account,
+            self.collateral_to,
+            native.wrap_account,
+            &[&[
+                &self.vault_type.key.to_bytes()[..],
+                WRAP_ROLE.as_bytes(),
+                &[nonce],
+            ]],
+            self.amount,
+        )?;
+
+        native.unwrap(
+            self.token_program,
+            self.vault_type,
+            self.collateral_from_authority,
+            nonce,
+        )
+    }
+}
+
+struct UnstakeContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    collateral_from: &'a AccountInfo<'a>,           // writable
+    collateral_from_authority: &'a AccountInfo<'a>, // program account
+    collateral_to: &'a AccountInfo<'a>,             // writable
+
+    vault_type: &'a AccountInfo<'a>,  // writable
+    vault: &'a AccountInfo<'a>,       // writable
+    vault_owner: &'a AccountInfo<'a>, // signed, owner or delegate
+
+    price_oracle: &'a AccountInfo<'a>,
+
+    // native accounts for a native vault type, then the optional accounts
+    extra_accounts: &'a [AccountInfo<'a>],
+
+    amount: u64,
+    collateral_holder_nonce: u8,
+    optional_accounts: u8,
+}
+
+impl<'a> UnstakeContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault_type = VaultType::load_initialized(self.vault_type)?;
+        let mut vault = Vault::load_initialized(self.vault)?;
+
+        if vault.vault_type.ne(&self.vault_type.into()) {
+            return Err(Error::VaultTypeMismatch)?;
+        }
+
+        if vault_type
+            .collateral_token_holder
+            .ne(&self.collateral_from.into())
+        {
+            return Err(Error::CollateralHolderAccountMismatch)?;
+        }
+
+        let (native, optional_accounts) = NativeAccounts::split(&vault_type, self.extra_accounts)?;
+        let (optional, _) = OptionalAccounts::take(self.optional_accounts, optional_accounts)?;
+        let (owner, delegate) = check_vault_authority(
+            self.program_id,
+            self.vault,
+            &vault,
+            self.vault_owner,
+            optional.position_account,
+            optional.vault_delegate,
+            delegate_permission::UNSTAKE,
+        )?;
+        if delegate.is_some() {
+            // a delegate can only unstake back to the owner
+            match native {
+                Some(_) if !owner.is_account(self.collateral_to) => {
+                    return Err(Error::OwnerMismatch)?;
+                }
+                Some(_) => (),
+                None => check_token_account_owner(self.collateral_to, &owner)?,
+            }
+        }
+
+        vault_type.accrue_vault(&mut vault, Clock::get()?.unix_timestamp)?;
+
+        vault.collateral_amount = vault
+            .collateral_amount
+            .checked_sub(self.amount)
+            .ok_or(Error::InsufficientCollateral)?;
+        vault_type.remove_collateral(self.amount)?;
+
+        check_vault_health(
+            self.program_id,
+            self.vault_type,
+            &vault_type,
+            self.price_oracle,
+            &vault,
+        )?;
+
+        let holder_seeds = &[
+            &self.vault_type.key.to_bytes()[..],
+            COLLATERAL_HOLDER_ROLE.as_bytes(),
+            &[self.collateral_holder_nonce],
+        ];
+
+        let holder_authority = expected_program_account_pubkey(self.program_id, holder_seeds)?;
+        if holder_authority.ne(self.collateral_from_authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        match native {
+            Some(native) => self.unstake_native(&native, holder_seeds)?,
+            None => self.unstake_collateral(holder_seeds)?,
+        }
+
+        vault_type.save(self.vault_type)?;
+        vault.save(self.vault)?;
+
+        Event::VaultUnstaked {
+            vault: self.vault.into(),
+            vault_type: self.vault_type.into(),
+            amount: self.amount,
+            collateral_amount: vault.collateral_amount,
+        }
+        .log()
+    }
+
+    fn unstake_collateral(&self, seeds: &[&[u8]]) -> ProgramResult {
+        transfer_tokens(
+            self.token_program,
+            self.collateral_from,
+            self.collateral_to,
+            self.collateral_from_authority,
+            &[seeds],
+            self.amount,
+        )
+    }
+
+    /// Move the wrapped SOL to a temporary account and unwrap it to
+    /// collateral_to, a system account. The signer pays the rent of the
+    /// temporary account, which also goes to collateral_to.
+    fn unstake_native(&self, native: &NativeAccounts<'a>, seeds: &[&[u8]]) -> ProgramResult {
+        let nonce = native.wrap(
+            self.program_id,
+            self.token_program,
+            self.vault_type,
+            self.vault_owner,
+            0,
+        )?;
+
+        transfer_tokens(
+            self.token_program,
+            self.collateral_from,
+            native.wrap_account,
+            self.collateral_from_authority,
+            &[seeds],
+            self.amount,
+        )?;
+
+        native.unwrap(
+            self.token_program,
+            self.vault_type,
+            self.collateral_to,
+            nonce,
+        )
+    }
+}
+
+struct BorrowContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    debt_token: &'a AccountInfo<'a>,    // writable
+    debt_minter: &'a AccountInfo<'a>,   // Program pubkey, writable
+    debt_receiver: &'a AccountInfo<'a>, // writable
+
+    debt_type: &'a AccountInfo<'a>,
+    vault_type: &'a AccountInfo<'a>,  // writable
+    vault: &'a AccountInfo<'a>,       // writable
+    vault_owner: &'a AccountInfo<'a>, // signed, owner or delegate
+
+    price_oracle: &'a AccountInfo<'a>,
+
+    // the optional accounts
+    extra_accounts: &'a [AccountInfo<'a>],
+
+    amount: u64,
+    debt_minter_nonce: u8,
+    optional_accounts: u8,
+}
+
+impl<'a> BorrowContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let (debt_type, mut vault_type, mut vault) = self.load_state_checked()?;
+
+        let (accounts, _) = OptionalAccounts::take(self.optional_accounts, self.extra_accounts)?;
+        let (owner, delegated) = self.check_authority(&vault_type, &vault, &accounts)?;
+        if delegated {
+            // a delegate can only borrow to the owner
+            check_token_account_owner(self.debt_receiver, &owner)?;
+        }
+
+        vault_type.accrue_vault(&mut vault, Clock::get()?.unix_timestamp)?;
+        let fee = self.mint_debt(&debt_type, &vault_type, &mut vault, accounts.treasury)?;
+
+        check_vault_health(
+            self.program_id,
+            self.vault_type,
+            &vault_type,
+            self.price_oracle,
+            &vault,
+        )?;
+
+        vault_type.save(self.vault_type)?;
+        vault.save(self.vault)?;
+
+        Event::DebtBorrowed {
+            vault: self.vault.into(),
+            vault_type: self.vault_type.into(),
+            amount: self.amount,
+            fee,
+            debt_amount: vault.debt_amount,
+        }
+        .log()
+    }
+
+    /// Returns the vault owner, and whether the signer is a delegate.
+    fn check_authority(
+        &self,
+        vault_type: &VaultType,
+        vault: &Vault,
+        accounts: &OptionalAccounts,
+    ) -> Result<(PublicKey, bool), ProgramError> {
+        let vault_delegate = accounts.vault_delegate;
+        let (owner, delegate) = check_vault_authority(
+            self.program_id,
+            self.vault,
+            vault,
+            self.vault_owner,
+            accounts.position_account,
+            vault_delegate,
+            delegate_permission::BORROW,
+        )?;
+        check_allowlisted(self.program_id, vault_type, accounts.allowlist, &owner)?;
+
+        let delegated = delegate.is_some();
+        if let (Some(mut delegate), Some(vault_delegate)) = (delegate, vault_delegate) {
+            // a delegate can only borrow up to the granted cap
+            delegate.borrowed_amount = delegate
+                .borrowed_amount
+                .checked_add(self.amount)
+                .ok_or(Error::Overflow)?;
+            if delegate.borrowed_amount > delegate.borrow_cap {
+                return Err(Error::DelegateBorrowCapExceeded)?;
+            }
+
+            delegate.save(vault_delegate)?;
+        }
+
+        Ok((owner, delegated))
+    }
+
+    /// Add the borrowed amount and the origination fee to the vault debt, and
+    /// mint the borrowed amount to the receiver. Returns the fee.
+    fn mint_debt(
+        &self,
+        debt_type: &DebtType,
+        vault_type: &VaultType,
+        vault: &mut Vault,
+        treasury_account: Option<&AccountInfo>,
+    ) -> Result<u64, ProgramError> {
+        let debt_minter_seeds = &[
+            &self.debt_type.key.to_bytes()[..],
+            MINTER_ROLE.as_bytes(),
+            &[self.debt_minter_nonce],
+        ];
+
+        // NOTE: probably not necessary to check the debt_minter. If the client
+        // passes invalid nonce or debt_minter, it will fail to mint anyway.
+        let debt_minter = self.program_pubkey(debt_minter_seeds)?;
+        if debt_minter.ne(self.debt_minter.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        // TODO: check debt ceiling
+
+        let fee = vault_type.params.origination_fee(self.amount)?;
+        if fee > 0 {
+            let treasury_account = treasury_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let mut treasury = load_treasury(debt_type, treasury_account)?;
+            treasury.earn(fee).ok_or(Error::Overflow)?;
+            treasury.save(treasury_account)?;
+        }
+
+        vault.debt_amount = vault
+            .debt_amount
+            .checked_add(self.amount)
+            .and_then(|debt_amount| debt_amount.checked_add(fee))
+            .ok_or(Error::Overflow)?;
+
+        self.mint_debt_to_receiver(debt_minter_seeds, self.amount)?;
+
+        Ok(fee)
+    }
+
+    fn load_state_checked(&self) -> Result<(DebtType, VaultType, Vault), ProgramError> {
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        let vault_type = VaultType::load_initialized(self.vault_type)?;
+        let vault = Vault::load_initialized(self.vault)?;
+
+        if debt_type.debt_token.ne(&self.debt_token.into()) {
+            return Err(Error::InvalidDebtToken)?;
+        }
+
+        if vault_type.debt_type.ne(&self.debt_type.into()) {
+            return Err(Error::DebtTypeMismatch)?;
+        }
+
+        if vault_type.price_oracle.ne(&self.price_oracle.into()) {
+            return Err(Error::InvalidPriceOracle)?;
+        }
+
+        if vault.vault_type.ne(&self.vault_type.into()) {
+            return Err(Error::VaultTypeMismatch)?;
+        }
+
+        Ok((debt_type, vault_type, vault))
+    }
+
+    fn mint_debt_to_receiver(&self, seeds: &[&[u8]], amount: u64) -> ProgramResult {
+        mint_tokens(
+            self.token_program,
+            self.debt_token,
+            self.debt_receiver,
+            self.debt_minter,
+            &[seeds],
+            amount,
+        )
+    }
+
+    fn program_pubkey(&self, seeds: &[&[u8]]) -> Result<Pubkey, ProgramError> {
+        // is it ok to trust the nonce passed by the client?, i think so.
+        //
+        // https://docs.rs/solana-sdk/1.5.10/solana_sdk/pubkey/struct.Pubkey.html#method.create_program_address
+        Pubkey::create_program_address(seeds, self.program_id)
+            .map_err(|_err| ProgramError::InvalidSeeds)
+    }
+}
+
+struct LeverageContext<'a> {
+    // the borrowed debt tokens are minted to the leverage account, which is
+    // owned by the program, so that a delegate can leverage too
+    borrow: BorrowContext<'a>,
+
+    swap_program: &'a AccountInfo<'a>,
+
+    leverage_authority: &'a AccountInfo<'a>, // [vault_type, LEVERAGE_ROLE]
+    collateral_holder: &'a AccountInfo<'a>,  // writable
+
+    // the pool accounts passed to the swap program follow the optional accounts
+    // of the borrow
+    minimum_collateral_amount: u64,
+    collateral_holder_nonce: u8,
+}
+
+impl<'a> LeverageContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let borrow = &self.borrow;
+        let (debt_type, mut vault_type, mut vault) = borrow.load_state_checked()?;
+
+        check_swap_program(&vault_type, self.swap_program)?;
+
+        let (accounts, pool_accounts) =
+            OptionalAccounts::take(borrow.optional_accounts, borrow.extra_accounts)?;
+        borrow.check_authority(&vault_type, &vault, &accounts)?;
+
+        let nonce = self.check_leverage_account()?;
+        let leverage_seeds: &[&[u8]] = &[
+            &borrow.vault_type.key.to_bytes()[..],
+            LEVERAGE_ROLE.as_bytes(),
+            &[nonce],
+        ];
+
+        check_collateral_holder(
+            borrow.program_id,
+            borrow.vault_type,
+            &vault_type,
+            self.collateral_holder,
+            self.collateral_holder_nonce,
+        )?;
+
+        vault_type.accrue_vault(&mut vault, Clock::get()?.unix_timestamp)?;
+        let fee = borrow.mint_debt(&debt_type, &vault_type, &mut vault, accounts.treasury)?;
+
+        // the bought collateral goes straight to the collateral holder
+        let received = swap_tokens(
+            borrow.token_program,
+            self.swap_program,
+            borrow.debt_receiver,
+            self.collateral_holder,
+            self.leverage_authority,
+            &[leverage_seeds],
+            pool_accounts,
+            borrow.amount,
+            self.minimum_collateral_amount,
+        )?;
+
+        vault_type.add_collateral(received)?;
+        vault.collateral_amount = vault
+            .collateral_amount
+            .checked_add(received)
+            .ok_or(Error::Overflow)?;
+
+        check_vault_health(
+            borrow.program_id,
+            borrow.vault_type,
+            &vault_type,
+            borrow.price_oracle,
+            &vault,
+        )?;
+
+        vault_type.save(borrow.vault_type)?;
+        vault.save(borrow.vault)?;
+
+        Event::VaultLeveraged {
+            vault: borrow.vault.into(),
+            vault_type: borrow.vault_type.into(),
+            amount: borrow.amount,
+            fee,
+            collateral_bought: received,
+            collateral_amount: vault.collateral_amount,
+            debt_amount: vault.debt_amount,
+        }
+        .log()
+    }
+
+    /// The leverage account has to be a debt token account owned by the
+    /// leverage authority. Returns the nonce of the authority.
+    fn check_leverage_account(&self) -> Result<u8, ProgramError> {
+        let borrow = &self.borrow;
+
+        let (authority, nonce) = Pubkey::find_program_address(
+            &[
+                &borrow.vault_type.key.to_bytes()[..],
+                LEVERAGE_ROLE.as_bytes(),
+            ],
+            borrow.program_id,
+        );
+        if authority.ne(self.leverage_authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        let leverage_account = unpack_token_account(borrow.debt_receiver)?;
+        if leverage_account.owner.ne(&authority) {
+            return Err(Error::OwnerMismatch)?;
+        }
+        if leverage_account.mint.ne(borrow.debt_token.key) {
+            return Err(Error::InvalidDebtToken)?;
+        }
+
+        Ok(nonce)
+    }
+}
+
+struct CloseVaultContext<'a> {
+    vault: &'a AccountInfo<'a>,       // writable
+    vault_owner: &'a AccountInfo<'a>, // signed
+    receiver: &'a AccountInfo<'a>,    // writable
+
+    position_account: Option<&'a AccountInfo<'a>>,
+}
+
+impl<'a> CloseVaultContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let vault = Vault::load_initialized(self.vault)?;
+
+        check_signed_by(
+            &vault_owner(&vault, self.position_account)?,
+            self.vault_owner,
+        )?;
+
+        if vault.debt_amount != 0 || vault.collateral_amount != 0 {
+            return Err(Error::VaultNotEmpty)?;
+        }
+
+        close_account(self.vault, self.receiver)?;
+
+        Event::VaultClosed {
+            vault: self.vault.into(),
+        }
+        .log()
+    }
+}
+
+struct TransferVaultContext<'a> {
+    vault: &'a AccountInfo<'a>,       // writable
+    vault_owner: &'a AccountInfo<'a>, // signed
+
+    new_owner: PublicKey,
+    require_accept: bool,
+}
+
+impl<'a> TransferVaultContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault = Vault::load_initialized(self.vault)?;
+
+        // a tokenized vault changes hands by transferring the position token
+        if vault.is_tokenized() {
+            return Err(Error::VaultTokenized)?;
+        }
+
+        check_signed_by(&vault.owner, self.vault_owner)?;
+
+        if self.require_accept {
+            // ownership changes only after the new owner accepts
+            vault.pending_owner = self.new_owner.clone();
+        } else {
+            vault.owner = self.new_owner.clone();
+            vault.pending_owner = PublicKey::default();
+        }
+
+        vault.save(self.vault)?;
+
+        Event::VaultTransferred {
+            vault: self.vault.into(),
+            owner: vault.owner,
+            pending_owner: vault.pending_owner,
+        }
+        .log()
+    }
+}
+
+struct AcceptVaultContext<'a> {
+    vault: &'a AccountInfo<'a>,     // writable
+    new_owner: &'a AccountInfo<'a>, // signed
+}
+
+impl<'a> AcceptVaultContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault = Vault::load_initialized(self.vault)?;
+
+        if vault.pending_owner.eq(&PublicKey::default()) {
+            return Err(Error::OwnerMismatch)?;
+        }
+
+        check_signed_by(&vault.pending_owner, self.new_owner)?;
+
+        vault.owner = vault.pending_owner;
+        vault.pending_owner = PublicKey::default();
+
+        vault.save(self.vault)?;
+
+        Event::VaultTransferred {
+            vault: self.vault.into(),
+            owner: vault.owner,
+            pending_owner: vault.pending_owner,
+        }
+        .log()
+    }
+}
+
+struct SetVaultDelegateContext<'a> {
+    program_id: &'a Pubkey,
+    rent: Rent,
+    vault_delegate: &'a AccountInfo<'a>, // writable
+    vault: &'a AccountInfo<'a>,
+    vault_owner: &'a AccountInfo<'a>, // signed
+
+    position_account: Option<&'a AccountInfo<'a>>,
+
+    delegate: PublicKey,
+    permissions: u8,
+    borrow_cap: u64,
+}
+
+impl<'a> SetVaultDelegateContext<'a> {
+    fn process(&self) -> ProgramResult {
+        check_program_owned(self.program_id, self.vault)?;
+        let vault = Vault::load_initialized(self.vault)?;
+        let owner = vault_owner(&vault, self.position_account)?;
+        check_signed_by(&owner, self.vault_owner)?;
+
+        // initialize a new delegate, or update the permissions of an existing one
+        check_program_owned(self.program_id, self.vault_delegate)?;
+        let mut delegate = VaultDelegate::load(self.vault_delegate)?;
+        if delegate.is_initialized {
+            if !delegate.vault.is_account(self.vault) {
+                return Err(Error::DelegateMismatch)?;
+            }
+        } else {
+            delegate.is_initialized = true;
+            delegate.vault = self.vault.into();
+        }
+
+        delegate.vault_owner = owner;
+        delegate.delegate = self.delegate.clone();
+        delegate.permissions = self.permissions;
+        delegate.borrow_cap = self.borrow_cap;
+
+        delegate.save_exempt(self.vault_delegate, &self.rent)?;
+
+        Event::VaultDelegateSet {
+            vault_delegate: self.vault_delegate.into(),
+            vault: self.vault.into(),
+            delegate: self.delegate.clone(),
+            permissions: self.permissions,
+            borrow_cap: self.borrow_cap,
+        }
+        .log()
+    }
+}
+
+struct RevokeVaultDelegateContext<'a> {
+    program_id: &'a Pubkey,
+    vault_delegate: &'a AccountInfo<'a>, // writable
+    vault: &'a AccountInfo<'a>,
+    vault_owner: &'a AccountInfo<'a>, // signed
+    receiver: &'a AccountInfo<'a>,    // writable
+
+    position_account: Option<&'a AccountInfo<'a>>,
+}
+
+impl<'a> RevokeVaultDelegateContext<'a> {
+    fn process(&self) -> ProgramResult {
+        check_program_owned(self.program_id, self.vault)?;
+        let vault = Vault::load_initialized(self.vault)?;
+        check_signed_by(
+            &vault_owner(&vault, self.position_account)?,
+            self.vault_owner,
+        )?;
+
+        check_program_owned(self.program_id, self.vault_delegate)?;
+        let delegate = VaultDelegate::load_initialized(self.vault_delegate)?;
+        if !delegate.vault.is_account(self.vault) {
+            return Err(Error::DelegateMismatch)?;
+        }
+
+        close_account(self.vault_delegate, self.receiver)?;
+
+        Event::VaultDelegateRevoked {
+            vault_delegate: self.vault_delegate.into(),
+            vault: self.vault.into(),
+        }
+        .log()
+    }
+}
+
+struct InitPriceFeedContext<'a> {
+    rent: Rent,
+    price_feed: &'a AccountInfo<'a>, // writable
+    token_mint: &'a AccountInfo<'a>,
+
+    owner: PublicKey,
+    decimals: u8,
+}
+
+impl<'a> InitPriceFeedContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut price_feed = PriceFeed::init_uninitialized(self.price_feed)?;
+
+        price_feed.is_initialized = true;
+        price_feed.owner = self.owner.clone();
+        price_feed.decimals = self.decimals;
+        unpack_mint(self.token_mint)?;
+        price_feed.token = self.token_mint.into();
+
+        price_feed.save_exempt(self.price_feed, &self.rent)?;
+
+        Event::PriceFeedInitialized {
+            price_feed: self.price_feed.into(),
+            owner: self.owner.clone(),
+            decimals: self.decimals,
+        }
+        .log()
+    }
+}
+
+struct UpdatePriceContext<'a> {
+    price_feed: &'a AccountInfo<'a>, // writable
+    owner: &'a AccountInfo<'a>,      // signed
+
+    price: u64,
+}
+
+impl<'a> UpdatePriceContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut price_feed = PriceFeed::load_initialized(self.price_feed)?;
+        check_signed_by(&price_feed.owner, self.owner)?;
+
+        price_feed.price = self.price;
+        price_feed.save(self.price_feed)?;
+
+        Event::PriceUpdated {
+            price_feed: self.price_feed.into(),
+            price: self.price,
+        }
+        .log()
+    }
+}
+
+struct InitLpPoolContext<'a> {
+    program_id: &'a Pubkey,
+
+    rent: Rent,
+    lp_pool: &'a AccountInfo<'a>, // writable
+    token_a_mint: &'a AccountInfo<'a>,
+    token_b_mint: &'a AccountInfo<'a>,
+    price_feed_a_account: &'a AccountInfo<'a>,
+    price_feed_b_account: &'a AccountInfo<'a>,
+
+    owner: PublicKey,
+    price_feed_a: PublicKey,
+    price_feed_b: PublicKey,
+}
+
+impl<'a> InitLpPoolContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut lp_pool = LpPool::init_uninitialized(self.lp_pool)?;
+
+        self.check_price_feed(
+            &self.price_feed_a,
+            self.price_feed_a_account,
+            self.token_a_mint,
+        )?;
+        self.check_price_feed(
+            &self.price_feed_b,
+            self.price_feed_b_account,
+            self.token_b_mint,
+        )?;
+
+        lp_pool.is_initialized = true;
+        lp_pool.owner = self.owner.clone();
+        lp_pool.price_feed_a = self.price_feed_a.clone();
+        lp_pool.price_feed_b = self.price_feed_b.clone();
+        lp_pool.decimals_a = unpack_mint(self.token_a_mint)?.decimals;
+        lp_pool.decimals_b = unpack_mint(self.token_b_mint)?.decimals;
+
+        lp_pool.save_exempt(self.lp_pool, &self.rent)?;
+
+        Event::LpPoolInitialized {
+            lp_pool: self.lp_pool.into(),
+            price_feed_a: self.price_feed_a.clone(),
+            price_feed_b: self.price_feed_b.clone(),
+        }
+        .log()
+    }
+
+    /// The price feed has to price the pool token of the mint.
+    fn check_price_feed(
+        &self,
+        price_feed: &PublicKey,
+        account: &AccountInfo,
+        token_mint: &AccountInfo,
+    ) -> ProgramResult {
+        check_program_owned(self.program_id, account)?;
+        if !price_feed.is_account(account) {
+            return Err(Error::InvalidPriceOracle)?;
+        }
+
+        let price_feed = PriceFeed::load_initialized(account)?;
+        if !price_feed.token.is_account(token_mint) {
+            return Err(Error::PriceFeedTokenMismatch)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Updates the reserves if signed by the pool owner, then revalues the pool at
+/// the current prices. Anyone can refresh the value.
+struct UpdateLpPoolContext<'a> {
+    program_id: &'a Pubkey,
+
+    lp_pool: &'a AccountInfo<'a>, // writable
+    price_feed_a: &'a AccountInfo<'a>,
+    price_feed_b: &'a AccountInfo<'a>,
+    owner: Option<&'a AccountInfo<'a>>, // signed
+
+    reserves: Option<(u64, u64, u64)>,
+}
+
+impl<'a> UpdateLpPoolContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut lp_pool = LpPool::load_initialized(self.lp_pool)?;
+
+        if let Some((reserve_a, reserve_b, lp_supply)) = self.reserves {
+            let owner = self.owner.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            check_signed_by(&lp_pool.owner, owner)?;
+
+            lp_pool.reserve_a = reserve_a;
+            lp_pool.reserve_b = reserve_b;
+            lp_pool.lp_supply = lp_supply;
+        }
+
+        check_program_owned(self.program_id, self.price_feed_a)?;
+        check_program_owned(self.program_id, self.price_feed_b)?;
+        if !lp_pool.price_feed_a.is_account(self.price_feed_a)
+            || !lp_pool.price_feed_b.is_account(self.price_feed_b)
+        {
+            return Err(Error::InvalidPriceOracle)?;
+        }
+
+        let price_a = PriceFeed::load_initialized(self.price_feed_a)?;
+        let price_b = PriceFeed::load_initialized(self.price_feed_b)?;
+        lp_pool.refresh(&price_a, &price_b, Clock::get()?.slot)?;
+
+        lp_pool.save(self.lp_pool)?;
+
+        Event::LpPoolUpdated {
+            lp_pool: self.lp_pool.into(),
+            reserve_a: lp_pool.reserve_a,
+            reserve_b: lp_pool.reserve_b,
+            lp_supply: lp_pool.lp_supply,
+            pool_value: lp_pool.pool_value,
+        }
+        .log()
+    }
+}
+
+struct InitMultiVaultContext<'a> {
+    program_id: &'a Pubkey,
+
+    rent: Rent,
+    multi_vault: &'a AccountInfo<'a>, // writable
+    debt_type_account: &'a AccountInfo<'a>,
+
+    // a vault type the owner is going to stake into, and its allowlist if it's
+    // permissioned
+    vault_type: Option<&'a AccountInfo<'a>>,
+    allowlist: Option<&'a AccountInfo<'a>>,
+
+    debt_type: PublicKey,
+    owner: PublicKey,
+}
+
+impl<'a> InitMultiVaultContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault = MultiVault::init_uninitialized(self.multi_vault)?;
+
+        check_program_owned(self.program_id, self.debt_type_account)?;
+        if !self.debt_type.is_account(self.debt_type_account) {
+            return Err(Error::DebtTypeMismatch)?;
+        }
+        DebtType::load_initialized(self.debt_type_account)?;
+
+        if let Some(vault_type_account) = self.vault_type {
+            check_program_owned(self.program_id, vault_type_account)?;
+            let vault_type = VaultType::load_initialized(vault_type_account)?;
+            if vault_type.debt_type.ne(&self.debt_type) {
+                return Err(Error::DebtTypeMismatch)?;
+            }
+
+            check_allowlisted(self.program_id, &vault_type, self.allowlist, &self.owner)?;
+        }
+
+        vault.is_initialized = true;
+        vault.debt_type = self.debt_type.clone();
+        vault.owner = self.owner.clone();
+        vault.save_exempt(self.multi_vault, &self.rent)?;
+
+        Event::MultiVaultInitialized {
+            multi_vault: self.multi_vault.into(),
+            debt_type: self.debt_type.clone(),
+            owner: self.owner.clone(),
+        }
+        .log()
+    }
+}
+
+/// Max debt that all the collaterals of a multi vault can back together. For
+/// every collateral with a balance, the accounts hold its vault type followed by
+/// its price oracle, in the order of the vault's collateral slots.
+fn multi_vault_max_debt(
+    program_id: &Pubkey,
+    vault: &MultiVault,
+    valuation_accounts: &[AccountInfo],
+) -> Result<u64, ProgramError> {
+    let mut accounts = valuation_accounts.iter();
+    let mut max_debt: u64 = 0;
+
+    for balance in vault.collaterals.iter().filter(|b| b.amount > 0) {
+        let vault_type_account = accounts.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let price_oracle = accounts.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        if !balance.vault_type.is_account(vault_type_account) {
+            return Err(Error::VaultTypeMismatch.into());
+        }
+
+        let vault_type = VaultType::load_initialized(vault_type_account)?;
+        let type_max_debt = max_debt_for_collateral(
+            program_id,
+            vault_type_account,
+            &vault_type,
+            price_oracle,
+            balance.amount,
+        )?;
+
+        max_debt = max_debt.checked_add(type_max_debt).ok_or(Error::Overflow)?;
+    }
+
+    Ok(max_debt)
+}
+
+fn check_multi_vault_health(
+    program_id: &Pubkey,
+    vault: &MultiVault,
+    valuation_accounts: &[AccountInfo],
+) -> ProgramResult {
+    if vault.debt_amount == 0 {
+        return Ok(());
+    }
+
+    if vault.debt_amount > multi_vault_max_debt(program_id, vault, valuation_accounts)? {
+        return Err(Error::CollateralRatioTooLow.into());
+    }
+
+    Ok(())
+}
+
+/// Check the chain of multi vault -> debt type -> debt token, and load the
+/// debt type.
+fn load_multi_vault_debt_type(
+    program_id: &Pubkey,
+    debt_token: &AccountInfo,
+    debt_type_account: &AccountInfo,
+    vault: &MultiVault,
+) -> Result<DebtType, ProgramError> {
+    check_program_owned(program_id, debt_type_account)?;
+
+    if !vault.debt_type.is_account(debt_type_account) {
+        return Err(Error::DebtTypeMismatch.into());
+    }
+
+    let debt_type = DebtType::load_initialized(debt_type_account)?;
+    if !debt_type.debt_token.is_account(debt_token) {
+        return Err(Error::InvalidDebtToken.into());
+    }
+
+    Ok(debt_type)
+}
+
+struct StakeMultiContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    collateral_from: &'a AccountInfo<'a>,           // writable
+    collateral_from_authority: &'a AccountInfo<'a>, // signed
+    collateral_to: &'a AccountInfo<'a>,             // writable
+
+    vault_type: &'a AccountInfo<'a>,  // writable
+    multi_vault: &'a AccountInfo<'a>, // writable
+
+    // for a permissioned vault type
+    allowlist: Option<&'a AccountInfo<'a>>,
+
+    amount: u64,
+    collateral_holder_nonce: u8,
+}
+
+impl<'a> StakeMultiContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault_type = VaultType::load_initialized(self.vault_type)?;
+        let mut vault = MultiVault::load_initialized(self.multi_vault)?;
+
+        if vault_type.debt_type.ne(&vault.debt_type) {
+            return Err(Error::DebtTypeMismatch)?;
+        }
+
+        check_allowlisted(self.program_id, &vault_type, self.allowlist, &vault.owner)?;
+
+        check_collateral_holder(
+            self.program_id,
+            self.vault_type,
+            &vault_type,
+            self.collateral_to,
+            self.collateral_holder_nonce,
+        )?;
+
+        vault_type.add_collateral(self.amount)?;
+
+        transfer_tokens(
+            self.token_program,
+            self.collateral_from,
+            self.collateral_to,
+            self.collateral_from_authority,
+            &[],
+            self.amount,
+        )?;
+
+        let balance = vault.balance_for(&self.vault_type.into())?;
+        balance.amount = balance
+            .amount
+            .checked_add(self.amount)
+            .ok_or(Error::Overflow)?;
+        let collateral_amount = balance.amount;
+
+        vault_type.save(self.vault_type)?;
+        vault.save(self.multi_vault)?;
+
+        Event::MultiVaultStaked {
+            multi_vault: self.multi_vault.into(),
+            vault_type: self.vault_type.into(),
+            amount: self.amount,
+            collateral_amount,
+        }
+        .log()
+    }
+}
+
+struct UnstakeMultiContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    collateral_from: &'a AccountInfo<'a>,           // writable
+    collateral_from_authority: &'a AccountInfo<'a>, // program account
+    collateral_to: &'a AccountInfo<'a>,             // writable
+
+    vault_type: &'a AccountInfo<'a>,  // writable
+    multi_vault: &'a AccountInfo<'a>, // writable
+    vault_owner: &'a AccountInfo<'a>, // signed
+
+    // (vault type, price oracle) pairs for the collaterals left in the vault
+    valuation_accounts: &'a [AccountInfo<'a>],
+
+    amount: u64,
+    collateral_holder_nonce: u8,
+}
+
+impl<'a> UnstakeMultiContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault_type = VaultType::load_initialized(self.vault_type)?;
+        let mut vault = MultiVault::load_initialized(self.multi_vault)?;
+
+        check_signed_by(&vault.owner, self.vault_owner)?;
+
+        if !vault_type
+            .collateral_token_holder
+            .is_account(self.collateral_from)
+        {
+            return Err(Error::CollateralHolderAccountMismatch)?;
+        }
+
+        let balance = vault.balance_for(&self.vault_type.into())?;
+        balance.amount = balance
+            .amount
+            .checked_sub(self.amount)
+            .ok_or(Error::InsufficientCollateral)?;
+        let collateral_amount = balance.amount;
+        if balance.amount == 0 {
+            // free the slot for another collateral
+            *balance = CollateralBalance::default();
+        }
+        vault_type.remove_collateral(self.amount)?;
+
+        check_multi_vault_health(self.program_id, &vault, self.valuation_accounts)?;
+
+        let holder_seeds = &[
+            &self.vault_type.key.to_bytes()[..],
+            COLLATERAL_HOLDER_ROLE.as_bytes(),
+            &[self.collateral_holder_nonce],
+        ];
+
+        let holder_authority = expected_program_account_pubkey(self.program_id, holder_seeds)?;
+        if holder_authority.ne(self.collateral_from_authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        transfer_tokens(
+            self.token_program,
+            self.collateral_from,
+            self.collateral_to,
+            self.collateral_from_authority,
+            &[holder_seeds],
+            self.amount,
+        )?;
+
+        vault_type.save(self.vault_type)?;
+        vault.save(self.multi_vault)?;
+
+        Event::MultiVaultUnstaked {
+            multi_vault: self.multi_vault.into(),
+            vault_type: self.vault_type.into(),
+            amount: self.amount,
+            collateral_amount,
+        }
+        .log()
+    }
+}
+
+struct BorrowMultiContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    debt_token: &'a AccountInfo<'a>,    // writable
+    debt_minter: &'a AccountInfo<'a>,   // Program pubkey, writable
+    debt_receiver: &'a AccountInfo<'a>, // writable
+
+    debt_type: &'a AccountInfo<'a>,
+    multi_vault: &'a AccountInfo<'a>, // writable
+    vault_owner: &'a AccountInfo<'a>, // signed
+
+    // (vault type, price oracle) pairs for the collaterals in the vault, the
+    // treasury (writable) if any of their vault types charges an origination
+    // fee, then the allowlists of the permissioned ones
+    valuation_accounts: &'a [AccountInfo<'a>],
+
+    amount: u64,
+    debt_minter_nonce: u8,
+}
+
+impl<'a> BorrowMultiContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault = MultiVault::load_initialized(self.multi_vault)?;
+
+        check_signed_by(&vault.owner, self.vault_owner)?;
+
+        let debt_type =
+            load_multi_vault_debt_type(self.program_id, self.debt_token, self.debt_type, &vault)?;
+
+        let debt_minter_seeds = &[
+            &self.debt_type.key.to_bytes()[..],
+            MINTER_ROLE.as_bytes(),
+            &[self.debt_minter_nonce],
+        ];
+
+        let debt_minter = expected_program_account_pubkey(self.program_id, debt_minter_seeds)?;
+        if debt_minter.ne(self.debt_minter.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        let vault_types = self.load_vault_types(&vault)?;
+        let (fee, allowlists) = self.charge_fee(&debt_type, &vault_types)?;
+
+        let mut allowlists = allowlists.iter();
+        for vault_type in vault_types.iter().filter(|t| t.params.is_permissioned()) {
+            check_allowlisted(self.program_id, vault_type, allowlists.next(), &vault.owner)?;
+        }
+
+        vault.debt_amount = vault
+            .debt_amount
+            .checked_add(self.amount)
+            .and_then(|debt_amount| debt_amount.checked_add(fee))
+            .ok_or(Error::Overflow)?;
+
+        check_multi_vault_health(self.program_id, &vault, self.valuation_accounts)?;
+
+        mint_tokens(
+            self.token_program,
+            self.debt_token,
+            self.debt_receiver,
+            self.debt_minter,
+            &[debt_minter_seeds],
+            self.amount,
+        )?;
+
+        vault.save(self.multi_vault)?;
+
+        Event::MultiVaultBorrowed {
+            multi_vault: self.multi_vault.into(),
+            amount: self.amount,
+            fee,
+            debt_amount: vault.debt_amount,
+        }
+        .log()
+    }
+
+    /// Load the vault types of the collaterals with a balance.
+    fn load_vault_types(&self, vault: &MultiVault) -> Result<Vec<VaultType>, ProgramError> {
+        let mut vault_type_accounts = self.valuation_accounts.iter().step_by(2);
+
+        let mut vault_types = Vec::new();
+        for balance in vault.collaterals.iter().filter(|b| b.amount > 0) {
+            let vault_type_account = vault_type_accounts
+                .next()
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if !balance.vault_type.is_account(vault_type_account) {
+                return Err(Error::VaultTypeMismatch)?;
+            }
+
+            vault_types.push(VaultType::load_initialized(vault_type_account)?);
+        }
+
+        Ok(vault_types)
+    }
+
+    /// Credit the origination fee to the treasury. The highest fee of the
+    /// vault types of the collaterals applies, so that staking a little of a
+    /// cheaper collateral doesn't lower it. Returns the fee, and the accounts
+    /// left after the treasury.
+    fn charge_fee(
+        &self,
+        debt_type: &DebtType,
+        vault_types: &[VaultType],
+    ) -> Result<(u64, &'a [AccountInfo<'a>]), ProgramError> {
+        let mut fee = 0;
+        for vault_type in vault_types {
+            fee = fee.max(vault_type.params.origination_fee(self.amount)?);
+        }
+
+        let rest = self
+            .valuation_accounts
+            .get(2 * vault_types.len()..)
+            .unwrap_or(&[]);
+        if fee == 0 {
+            return Ok((fee, rest));
+        }
+
+        let (treasury_account, rest) = rest
+            .split_first()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let mut treasury = load_treasury(debt_type, treasury_account)?;
+        treasury.earn(fee).ok_or(Error::Overflow)?;
+        treasury.save(treasury_account)?;
+
+        Ok((fee, rest))
+    }
+}
+
+struct RepayMultiContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    debt_token: &'a AccountInfo<'a>,          // writable
+    debt_from: &'a AccountInfo<'a>,           // writable
+    debt_from_authority: &'a AccountInfo<'a>, // signed
+
+    debt_type: &'a AccountInfo<'a>,
+    multi_vault: &'a AccountInfo<'a>, // writable
+
+    amount: u64,
+}
+
+impl<'a> RepayMultiContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault = MultiVault::load_initialized(self.multi_vault)?;
+
+        load_multi_vault_debt_type(self.program_id, self.debt_token, self.debt_type, &vault)?;
+
+        vault.debt_amount = vault
+            .debt_amount
+            .checked_sub(self.amount)
+            .ok_or(Error::RepayExceedsDebt)?;
+
+        burn_tokens(
+            self.token_program,
+            self.debt_token,
+            self.debt_from,
+            self.debt_from_authority,
+            &[],
+            self.amount,
+        )?;
+
+        vault.save(self.multi_vault)?;
+
+        Event::MultiVaultRepaid {
+            multi_vault: self.multi_vault.into(),
+            amount: self.amount,
+            debt_amount: vault.debt_amount,
+        }
+        .log()
+    }
+}
+
+struct RepayContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    debt_token: &'a AccountInfo<'a>,          // writable
+    debt_from: &'a AccountInfo<'a>,           // writable
+    debt_from_authority: &'a AccountInfo<'a>, // signed
+
+    debt_type: &'a AccountInfo<'a>,
+    vault_type: &'a AccountInfo<'a>, // writable
+    vault: &'a AccountInfo<'a>,      // writable
+
+    amount: u64,
+}
+
+impl<'a> RepayContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault_type = VaultType::load_initialized(self.vault_type)?;
+        let mut vault = Vault::load_initialized(self.vault)?;
+
+        check_debt_token(
+            self.program_id,
+            self.debt_token,
+            self.debt_type,
+            self.vault_type,
+            &vault_type,
+            &vault,
+        )?;
+
+        vault_type.accrue_vault(&mut vault, Clock::get()?.unix_timestamp)?;
+        vault.debt_amount = vault
+            .debt_amount
+            .checked_sub(self.amount)
+            .ok_or(Error::RepayExceedsDebt)?;
+
+        burn_tokens(
+            self.token_program,
+            self.debt_token,
+            self.debt_from,
+            self.debt_from_authority,
+            &[],
+            self.amount,
+        )?;
+
+        vault_type.save(self.vault_type)?;
+        vault.save(self.vault)?;
+
+        Event::DebtRepaid {
+            vault: self.vault.into(),
+            vault_type: self.vault_type.into(),
+            amount: self.amount,
+            debt_amount: vault.debt_amount,
+        }
+        .log()
+    }
+}
+
+struct UpdateVaultTypeParamsContext<'a> {
+    program_id: &'a Pubkey,
+
+    vault_type: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    debt_type_owner: &'a AccountInfo<'a>, // signed
+
+    params: VaultTypeParams,
+}
+
+impl<'a> UpdateVaultTypeParamsContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault_type = VaultType::load_initialized(self.vault_type)?;
+
+        check_program_owned(self.program_id, self.debt_type)?;
+        if !vault_type.debt_type.is_account(self.debt_type) {
+            return Err(Error::DebtTypeMismatch)?;
+        }
+
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        check_signed_by(&debt_type.owner, self.debt_type_owner)?;
+
+        self.params.validate()?;
+        // the debt accrued so far owes the old stability fee
+        vault_type.accrue(Clock::get()?.unix_timestamp)?;
+        vault_type.params = self.params.clone();
+
+        vault_type.save(self.vault_type)?;
+
+        Event::ParamsUpdated {
+            vault_type: self.vault_type.into(),
+            params: self.params.clone(),
+        }
+        .log()
+    }
+}
+
+fn check_swap_program(vault_type: &VaultType, swap_program: &AccountInfo) -> ProgramResult {
+    if vault_type.params.swap_program.eq(&PublicKey::default())
+        || !vault_type.params.swap_program.is_account(swap_program)
+    {
+        return Err(Error::InvalidSwapProgram.into());
+    }
+
+    Ok(())
+}
+
+/// Swap exactly `amount_in` source tokens through the swap program. Returns the
+/// amount of tokens received by the destination.
+#[allow(clippy::too_many_arguments)]
+fn swap_tokens<'a>(
+    token_program: &AccountInfo<'a>,
+    swap_program: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+    pool_accounts: &[AccountInfo<'a>],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<u64, ProgramError> {
+    let source_before = unpack_token_account(source)?.amount;
+    let destination_before = unpack_token_account(destination)?.amount;
+
+    let pool_metas: Vec<AccountMeta> = pool_accounts
+        .iter()
+        .map(|account| AccountMeta {
+            pubkey: *account.key,
+            is_signer: account.is_signer,
+            is_writable: account.is_writable,
+        })
+        .collect();
+
+    let inx = swap::swap(
+        swap_program.key,
+        source.key,
+        destination.key,
+        authority.key,
+        token_program.key,
+        &pool_metas,
+        amount_in,
+        minimum_amount_out,
+    )?;
+
+    let mut swap_accounts = vec![
+        source.clone(),
+        destination.clone(),
+        authority.clone(),
+        token_program.clone(),
+        swap_program.clone(),
+    ];
+    swap_accounts.extend(pool_accounts.iter().cloned());
+
+    invoke_signed(&inx, &swap_accounts, signer_seeds)?;
+
+    // the swap program is trusted with the source authority, but only to take
+    // exactly the amount sold
+    let source_after = unpack_token_account(source)?.amount;
+    let destination_after = unpack_token_account(destination)?.amount;
+
+    if source_before.checked_sub(source_after) != Some(amount_in) {
+        return Err(Error::SwapResultMismatch.into());
+    }
+
+    let received = destination_after
+        .checked_sub(destination_before)
+        .ok_or(Error::SwapResultMismatch)?;
+    if received < minimum_amount_out {
+        return Err(Error::SwapResultMismatch.into());
+    }
+
+    Ok(received)
+}
+
+struct DeleverageContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+    swap_program: &'a AccountInfo<'a>,
+
+    collateral_holder: &'a AccountInfo<'a>, // writable
+    collateral_holder_authority: &'a AccountInfo<'a>, // program account
+
+    debt_token: &'a AccountInfo<'a>,   // writable
+    debt_account: &'a AccountInfo<'a>, // writable, owned by vault_owner
+
+    debt_type: &'a AccountInfo<'a>,
+    vault_type: &'a AccountInfo<'a>,  // writable
+    vault: &'a AccountInfo<'a>,       // writable
+    vault_owner: &'a AccountInfo<'a>, // signed, owner or delegate
+
+    price_oracle: &'a AccountInfo<'a>,
+
+    // the optional accounts, then the pool accounts passed to the swap program
+    extra_accounts: &'a [AccountInfo<'a>],
+
+    collateral_amount: u64,
+    minimum_debt_amount: u64,
+    collateral_holder_nonce: u8,
+    optional_accounts: u8,
+}
+
+impl<'a> DeleverageContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault_type = VaultType::load_initialized(self.vault_type)?;
+        let mut vault = Vault::load_initialized(self.vault)?;
+
+        check_debt_token(
+            self.program_id,
+            self.debt_token,
+            self.debt_type,
+            self.vault_type,
+            &vault_type,
+            &vault,
+        )?;
+
+        check_swap_program(&vault_type, self.swap_program)?;
+
+        if !vault_type
+            .collateral_token_holder
+            .is_account(self.collateral_holder)
+        {
+            return Err(Error::CollateralHolderAccountMismatch)?;
+        }
+
+        let (optional, pool_accounts) =
+            OptionalAccounts::take(self.optional_accounts, self.extra_accounts)?;
+        let (_owner, delegate) = check_vault_authority(
+            self.program_id,
+            self.vault,
+            &vault,
+            self.vault_owner,
+            optional.position_account,
+            optional.vault_delegate,
+            delegate_permission::DELEVERAGE,
+        )?;
+
+        vault_type.accrue_vault(&mut vault, Clock::get()?.unix_timestamp)?;
+
+        let price = collateral_price(
+            self.program_id,
+            self.vault_type,
+            &vault_type,
+            self.price_oracle,
+        )?;
+        let ratio_before =
+            health::collateral_ratio(&price, vault.collateral_amount, vault.debt_amount)?;
+
+        vault.collateral_amount = vault
+            .collateral_amount
+            .checked_sub(self.collateral_amount)
+            .ok_or(Error::InsufficientCollateral)?;
+        vault_type.remove_collateral(self.collateral_amount)?;
+
+        let received = self.swap_collateral(pool_accounts)?;
+
+        // repay as much of the debt as the swap returned. The rest stays in the
+        // debt account of the signer.
+        let repay_amount = received.min(vault.debt_amount);
+        if delegate.is_some() && repay_amount < received {
+            // a delegate may not keep the proceeds
+            return Err(Error::DelegatePermissionDenied)?;
+        }
+
+        vault.debt_amount -= repay_amount;
+        burn_tokens(
+            self.token_program,
+            self.debt_token,
+            self.debt_account,
+            self.vault_owner,
+            &[],
+            repay_amount,
+        )?;
+
+        let ratio_after =
+            health::collateral_ratio(&price, vault.collateral_amount, vault.debt_amount)?;
+
+        // the collateral ratio must not drop. Paying off all the debt leaves no
+        // ratio, which is as healthy as it gets.
+        let healthier = match (ratio_before, ratio_after) {
+            (_, None) => true,
+            (Some(before), Some(after)) => after >= before,
+            (None, Some(_)) => false,
+        };
+        if repay_amount == 0 || !healthier {
+            return Err(Error::DeleverageNotHealthier)?;
+        }
+
+        vault_type.save(self.vault_type)?;
+        vault.save(self.vault)?;
+
+        Event::VaultDeleveraged {
+            vault: self.vault.into(),
+            vault_type: self.vault_type.into(),
+            collateral_sold: self.collateral_amount,
+            debt_repaid: repay_amount,
+            collateral_amount: vault.collateral_amount,
+            debt_amount: vault.debt_amount,
+        }
+        .log()
+    }
+
+    /// Swap collateral from the holder for debt tokens into the debt account.
+    /// Returns the amount of debt tokens received.
+    fn swap_collateral(&self, pool_accounts: &'a [AccountInfo<'a>]) -> Result<u64, ProgramError> {
+        let holder_seeds = &[
+            &self.vault_type.key.to_bytes()[..],
+            COLLATERAL_HOLDER_ROLE.as_bytes(),
+            &[self.collateral_holder_nonce],
+        ];
+
+        let holder_authority = expected_program_account_pubkey(self.program_id, holder_seeds)?;
+        if holder_authority.ne(self.collateral_holder_authority.key) {
+            return Err(Error::UnexpectedProgramAccount.into());
+        }
+
+        let debt_account = unpack_token_account(self.debt_account)?;
+        if debt_account.mint.ne(self.debt_token.key) {
+            return Err(Error::InvalidDebtToken.into());
+        }
+
+        swap_tokens(
+            self.token_program,
+            self.swap_program,
+            self.collateral_holder,
+            self.debt_account,
+            self.collateral_holder_authority,
+            &[holder_seeds],
+            pool_accounts,
+            self.collateral_amount,
+            self.minimum_debt_amount,
+        )
+    }
+}
+
+struct InitSavingsContext<'a> {
+    program_id: &'a Pubkey,
+
+    rent: Rent,
+    clock: Clock,
+
+    savings: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    debt_type_owner: &'a AccountInfo<'a>, // signed
+
+    share_token: &'a AccountInfo<'a>,
+    debt_holder: &'a AccountInfo<'a>,
+
+    rate: u64,
+    savings_authority_nonce: u8,
+}
+
+impl<'a> InitSavingsContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut savings = Savings::init_uninitialized(self.savings)?;
+
+        check_program_owned(self.program_id, self.debt_type)?;
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        check_signed_by(&debt_type.owner, self.debt_type_owner)?;
+
+        let savings_authority = expected_program_account_pubkey(
+            self.program_id,
+            &[
+                &self.savings.key.to_bytes()[..],
+                SAVINGS_ROLE.as_bytes(),
+                &[self.savings_authority_nonce],
+            ],
+        )?;
+
+        // only the savings may mint shares, and none may exist before
+        let share_token = unpack_mint(self.share_token)?;
+        if share_token.mint_authority != COption::Some(savings_authority) || share_token.supply != 0
+        {
+            return Err(Error::InvalidShareToken)?;
+        }
+
+        let debt_holder = unpack_token_account(self.debt_holder)?;
+        if debt_holder.owner.ne(&savings_authority) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+        if !debt_type
+            .debt_token
+            .eq(&PublicKey(debt_holder.mint.to_bytes()))
+        {
+            return Err(Error::InvalidDebtToken)?;
+        }
+
+        savings.is_initialized = true;
+        savings.debt_type = self.debt_type.into();
+        savings.share_token = self.share_token.into();
+        savings.debt_holder = self.debt_holder.into();
+        savings.rate = self.rate;
+        savings.accumulator = ACCUMULATOR_BASE;
+        savings.last_update = self.clock.unix_timestamp;
+
+        savings.save_exempt(self.savings, &self.rent)?;
+
+        Event::SavingsInitialized {
+            savings: self.savings.into(),
+            debt_type: self.debt_type.into(),
+            share_token: self.share_token.into(),
+            rate: self.rate,
+        }
+        .log()
+    }
+}
+
+/// Load the savings, requiring the accumulator to be up to date, so that the
+/// shares are priced with the interest accrued until now.
+fn load_dripped_savings(
+    program_id: &Pubkey,
+    savings_account: &AccountInfo,
+    clock: &Clock,
+) -> Result<Savings, ProgramError> {
+    check_program_owned(program_id, savings_account)?;
+    let savings = Savings::load_initialized(savings_account)?;
+
+    if savings.last_update != clock.unix_timestamp {
+        return Err(Error::SavingsNotDripped.into());
+    }
+
+    Ok(savings)
+}
+
+struct SetSavingsRateContext<'a> {
+    program_id: &'a Pubkey,
+
+    clock: Clock,
+
+    savings: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    debt_type_owner: &'a AccountInfo<'a>, // signed
+
+    rate: u64,
+}
+
+impl<'a> SetSavingsRateContext<'a> {
+    fn process(&self) -> ProgramResult {
+        // the old rate applies until now
+        let mut savings = load_dripped_savings(self.program_id, self.savings, &self.clock)?;
+
+        check_program_owned(self.program_id, self.debt_type)?;
+        if !savings.debt_type.is_account(self.debt_type) {
+            return Err(Error::DebtTypeMismatch)?;
+        }
+
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        check_signed_by(&debt_type.owner, self.debt_type_owner)?;
+
+        savings.rate = self.rate;
+        savings.save(self.savings)?;
+
+        Event::SavingsRateSet {
+            savings: self.savings.into(),
+            rate: self.rate,
+        }
+        .log()
+    }
+}
+
+struct DripContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+    clock: Clock,
+
+    savings: &'a AccountInfo<'a>, // writable
+    share_token: &'a AccountInfo<'a>,
+    debt_holder: &'a AccountInfo<'a>, // writable
+
+    debt_type: &'a AccountInfo<'a>,
+    debt_token: &'a AccountInfo<'a>,  // writable
+    debt_minter: &'a AccountInfo<'a>, // program account
+    treasury: &'a AccountInfo<'a>,    // writable
+
+    // writable, vault types of the debt type whose stability fees to collect
+    vault_types: &'a [AccountInfo<'a>],
+
+    debt_minter_nonce: u8,
+}
+
+impl<'a> DripContext<'a> {
+    fn process(&self) -> ProgramResult {
+        check_program_owned(self.program_id, self.savings)?;
+        let mut savings = Savings::load_initialized(self.savings)?;
+
+        if !savings.debt_type.is_account(self.debt_type)
+            || !savings.share_token.is_account(self.share_token)
+            || !savings.debt_holder.is_account(self.debt_holder)
+        {
+            return Err(Error::SavingsMismatch)?;
+        }
+
+        check_program_owned(self.program_id, self.debt_type)?;
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        if !debt_type.debt_token.is_account(self.debt_token) {
+            return Err(Error::InvalidDebtToken)?;
+        }
+
+        let mut treasury = load_treasury(&debt_type, self.treasury)?;
+        self.collect_stability_fees(&mut treasury)?;
+
+        let shares = unpack_mint(self.share_token)?.supply;
+        let previous = savings.accumulator;
+        savings.drip(self.clock.unix_timestamp, shares)?;
+
+        // mint the interest, so that the holder can always pay out all the
+        // shares at the current exchange rate. The interest is paid out of
+        // the stability fees, and stops growing when they run out.
+        let holder_amount = unpack_token_account(self.debt_holder)?.amount;
+        let funded = holder_amount
+            .checked_add(treasury.stability_fees)
+            .ok_or(Error::Overflow)?;
+        savings.cap_accumulator(shares, funded, previous)?;
+        let backing = savings.debt_for(shares)?;
+
+        let interest = backing.saturating_sub(holder_amount);
+        if interest > 0 {
+            let debt_minter_seeds = &[
+                &self.debt_type.key.to_bytes()[..],
+                MINTER_ROLE.as_bytes(),
+                &[self.debt_minter_nonce],
+            ];
+
+            let debt_minter = expected_program_account_pubkey(self.program_id, debt_minter_seeds)?;
+            if debt_minter.ne(self.debt_minter.key) {
+                return Err(Error::UnexpectedProgramAccount)?;
+            }
+
+            treasury.stability_fees = treasury
+                .stability_fees
+                .checked_sub(interest)
+                .ok_or(Error::Overflow)?;
+
+            mint_tokens(
+                self.token_program,
+                self.debt_token,
+                self.debt_holder,
+                self.debt_minter,
+                &[debt_minter_seeds],
+                interest,
+            )?;
+        }
+
+        savings.save(self.savings)?;
+        treasury.save(self.treasury)?;
+
+        Event::SavingsDripped {
+            savings: self.savings.into(),
+            accumulator: savings.accumulator,
+            interest,
+        }
+        .log()
+    }
+
+    /// Accrue the vault types, and move the stability fees of their vaults to
+    /// the treasury.
+    fn collect_stability_fees(&self, treasury: &mut Treasury) -> ProgramResult {
+        for account in self.vault_types {
+            check_program_owned(self.program_id, account)?;
+            let mut vault_type = VaultType::load_initialized(account)?;
+            if !vault_type.debt_type.is_account(self.debt_type) {
+                return Err(Error::DebtTypeMismatch)?;
+            }
+
+            vault_type.accrue(self.clock.unix_timestamp)?;
+            treasury.stability_fees = treasury
+                .stability_fees
+                .checked_add(vault_type.stability_fees)
+                .ok_or(Error::Overflow)?;
+            vault_type.stability_fees = 0;
+
+            vault_type.save(account)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct JoinSavingsContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+    clock: Clock,
+
+    savings: &'a AccountInfo<'a>,
+
+    debt_from: &'a AccountInfo<'a>,           // writable
+    debt_from_authority: &'a AccountInfo<'a>, // signed
+    debt_holder: &'a AccountInfo<'a>,         // writable
+
+    share_token: &'a AccountInfo<'a>,       // writable
+    savings_authority: &'a AccountInfo<'a>, // program account
+    share_to: &'a AccountInfo<'a>,          // writable
+
+    amount: u64,
+    savings_authority_nonce: u8,
+}
+
+impl<'a> JoinSavingsContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let savings = load_dripped_savings(self.program_id, self.savings, &self.clock)?;
+
+        if !savings.share_token.is_account(self.share_token)
+            || !savings.debt_holder.is_account(self.debt_holder)
+        {
+            return Err(Error::SavingsMismatch)?;
+        }
+
+        let savings_authority_seeds = &[
+            &self.savings.key.to_bytes()[..],
+            SAVINGS_ROLE.as_bytes(),
+            &[self.savings_authority_nonce],
+        ];
+
+        let savings_authority =
+            expected_program_account_pubkey(self.program_id, savings_authority_seeds)?;
+        if savings_authority.ne(self.savings_authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        let shares = savings.shares_for(self.amount)?;
+
+        transfer_tokens(
+            self.token_program,
+            self.debt_from,
+            self.debt_holder,
+            self.debt_from_authority,
+            &[],
+            self.amount,
+        )?;
+
+        mint_tokens(
+            self.token_program,
+            self.share_token,
+            self.share_to,
+            self.savings_authority,
+            &[savings_authority_seeds],
+            shares,
+        )?;
+
+        Event::SavingsJoined {
+            savings: self.savings.into(),
+            amount: self.amount,
+            shares,
+        }
+        .log()
+    }
+}
+
+struct ExitSavingsContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+    clock: Clock,
+
+    savings: &'a AccountInfo<'a>,
+
+    share_token: &'a AccountInfo<'a>,          // writable
+    share_from: &'a AccountInfo<'a>,           // writable
+    share_from_authority: &'a AccountInfo<'a>, // signed
+
+    debt_holder: &'a AccountInfo<'a>,       // writable
+    savings_authority: &'a AccountInfo<'a>, // program account
+    debt_to: &'a AccountInfo<'a>,           // writable
+
+    shares: u64,
+    savings_authority_nonce: u8,
+}
+
+impl<'a> ExitSavingsContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let savings = load_dripped_savings(self.program_id, self.savings, &self.clock)?;
+
+        if !savings.share_token.is_account(self.share_token)
+            || !savings.debt_holder.is_account(self.debt_holder)
+        {
+            return Err(Error::SavingsMismatch)?;
+        }
+
+        let savings_authority_seeds = &[
+            &self.savings.key.to_bytes()[..],
+            SAVINGS_ROLE.as_bytes(),
+            &[self.savings_authority_nonce],
+        ];
+
+        let savings_authority =
+            expected_program_account_pubkey(self.program_id, savings_authority_seeds)?;
+        if savings_authority.ne(self.savings_authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        let amount = savings.debt_for(self.shares)?;
+
+        burn_tokens(
+            self.token_program,
+            self.share_token,
+            self.share_from,
+            self.share_from_authority,
+            &[],
+            self.shares,
+        )?;
+
+        transfer_tokens(
+            self.token_program,
+            self.debt_holder,
+            self.debt_to,
+            self.savings_authority,
+            &[savings_authority_seeds],
+            amount,
+        )?;
+
+        Event::SavingsExited {
+            savings: self.savings.into(),
+            shares: self.shares,
+            amount,
+        }
+        .log()
+    }
+}
+
+struct InitTreasuryContext<'a> {
+    rent: Rent,
+
+    treasury: &'a AccountInfo<'a>,        // writable
+    debt_type: &'a AccountInfo<'a>,       // writable
+    debt_type_owner: &'a AccountInfo<'a>, // signed
+
+    governance_token: PublicKey,
+}
+
+impl<'a> InitTreasuryContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut treasury = Treasury::init_uninitialized(self.treasury)?;
+        let mut debt_type = DebtType::load_initialized(self.debt_type)?;
+        check_signed_by(&debt_type.owner, self.debt_type_owner)?;
+
+        // a debt type has only one treasury
+        if debt_type.treasury.ne(&PublicKey::default()) {
+            return Err(Error::TreasuryMismatch)?;
+        }
+
+        treasury.is_initialized = true;
+        treasury.debt_type = self.debt_type.into();
+        treasury.governance_token = self.governance_token.clone();
+        debt_type.treasury = self.treasury.into();
+
+        treasury.save_exempt(self.treasury, &self.rent)?;
+        debt_type.save(self.debt_type)?;
+
+        Event::TreasuryInitialized {
+            treasury: self.treasury.into(),
+            debt_type: self.debt_type.into(),
+            governance_token: self.governance_token.clone(),
+        }
+        .log()
+    }
+}
+
+/// Load the treasury of the debt type, for an instruction that updates it.
+fn load_treasury(debt_type: &DebtType, treasury: &AccountInfo) -> Result<Treasury, ProgramError> {
+    if !debt_type.treasury.is_account(treasury) {
+        return Err(Error::TreasuryMismatch.into());
+    }
+
+    Treasury::load_initialized(treasury)
+}
+
+struct WithdrawSurplusContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    treasury: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    debt_type_owner: &'a AccountInfo<'a>, // signed
+
+    debt_token: &'a AccountInfo<'a>,    // writable
+    debt_minter: &'a AccountInfo<'a>,   // program account
+    debt_receiver: &'a AccountInfo<'a>, // writable
+
+    amount: u64,
+    debt_minter_nonce: u8,
+}
+
+impl<'a> WithdrawSurplusContext<'a> {
+    fn process(&self) -> ProgramResult {
+        check_program_owned(self.program_id, self.debt_type)?;
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        check_signed_by(&debt_type.owner, self.debt_type_owner)?;
+
+        if !debt_type.debt_token.is_account(self.debt_token) {
+            return Err(Error::InvalidDebtToken)?;
+        }
+
+        let mut treasury = load_treasury(&debt_type, self.treasury)?;
+        treasury.surplus = treasury
+            .surplus
+            .checked_sub(self.amount)
+            .ok_or(Error::InsufficientSurplus)?;
+
+        let debt_minter_seeds = &[
+            &self.debt_type.key.to_bytes()[..],
+            MINTER_ROLE.as_bytes(),
+            &[self.debt_minter_nonce],
+        ];
+
+        let debt_minter = expected_program_account_pubkey(self.program_id, debt_minter_seeds)?;
+        if debt_minter.ne(self.debt_minter.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        mint_tokens(
+            self.token_program,
+            self.debt_token,
+            self.debt_receiver,
+            self.debt_minter,
+            &[debt_minter_seeds],
+            self.amount,
+        )?;
+
+        treasury.save(self.treasury)?;
+
+        Event::SurplusWithdrawn {
+            treasury: self.treasury.into(),
+            amount: self.amount,
+            surplus: treasury.surplus,
+        }
+        .log()
+    }
+}
+
+struct WriteOffVaultContext<'a> {
+    program_id: &'a Pubkey,
+
+    treasury: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    vault_type: &'a AccountInfo<'a>,
+    vault: &'a AccountInfo<'a>, // writable
+}
+
+impl<'a> WriteOffVaultContext<'a> {
+    fn process(&self) -> ProgramResult {
+        check_program_owned(self.program_id, self.debt_type)?;
+        check_program_owned(self.program_id, self.vault_type)?;
+
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        let vault_type = VaultType::load_initialized(self.vault_type)?;
+        let mut vault = Vault::load_initialized(self.vault)?;
+
+        if !vault.vault_type.is_account(self.vault_type) {
+            return Err(Error::VaultTypeMismatch)?;
+        }
+        if !vault_type.debt_type.is_account(self.debt_type) {
+            return Err(Error::DebtTypeMismatch)?;
+        }
+
+        // the debt tokens borrowed by the vault stay in circulation, but
+        // nothing is left to repay them
+        if vault.collateral_amount != 0 || vault.debt_amount == 0 {
+            return Err(Error::VaultNotInsolvent)?;
+        }
+
+        let mut treasury = load_treasury(&debt_type, self.treasury)?;
+        treasury.spend(vault.debt_amount).ok_or(Error::Overflow)?;
+        let debt_amount = vault.debt_amount;
+        vault.debt_amount = 0;
+
+        treasury.save(self.treasury)?;
+        vault.save(self.vault)?;
+
+        Event::VaultWrittenOff {
+            vault: self.vault.into(),
+            vault_type: self.vault_type.into(),
+            treasury: self.treasury.into(),
+            debt_amount,
+        }
+        .log()
+    }
+}
+
+struct StartAuctionContext<'a> {
+    program_id: &'a Pubkey,
+
+    rent: Rent,
+    clock: Clock,
+
+    auction: &'a AccountInfo<'a>,  // writable
+    treasury: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    debt_type_owner: &'a AccountInfo<'a>, // signed
+
+    escrow: &'a AccountInfo<'a>,
+
+    kind: AuctionKind,
+    debt_amount: u64,
+    gov_amount: u64,
+    auction_authority_nonce: u8,
+}
+
+impl<'a> StartAuctionContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut auction = Auction::init_uninitialized(self.auction)?;
+
+        check_program_owned(self.program_id, self.debt_type)?;
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        check_signed_by(&debt_type.owner, self.debt_type_owner)?;
+
+        let mut treasury = load_treasury(&debt_type, self.treasury)?;
+        if treasury.governance_token.eq(&PublicKey::default()) {
+            return Err(Error::InvalidGovernanceToken)?;
+        }
+
+        // the auctioned amount is taken out of the treasury until settled. Bad
+        // debt is auctioned only when no surplus is left to net it against.
+        let (bid_token, payout_token) = match self.kind {
+            AuctionKind::Debt => {
+                if treasury.surplus != 0 || treasury.bad_debt < self.debt_amount {
+                    return Err(Error::InvalidAuction)?;
+                }
+                treasury.bad_debt -= self.debt_amount;
+
+                (debt_type.debt_token, treasury.governance_token.clone())
+            }
+            AuctionKind::Surplus => {
+                if treasury.bad_debt != 0 || treasury.surplus < self.debt_amount {
+                    return Err(Error::InvalidAuction)?;
+                }
+                treasury.surplus -= self.debt_amount;
+
+                (treasury.governance_token.clone(), debt_type.debt_token)
+            }
+        };
+
+        let auction_authority = expected_program_account_pubkey(
+            self.program_id,
+            &[
+                &self.auction.key.to_bytes()[..],
+                AUCTION_ROLE.as_bytes(),
+                &[self.auction_authority_nonce],
+            ],
+        )?;
+
+        let escrow = unpack_token_account(self.escrow)?;
+        if escrow.owner.ne(&auction_authority) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+        if bid_token.ne(&PublicKey(escrow.mint.to_bytes())) {
+            return Err(Error::InvalidAuction)?;
+        }
+
+        auction.is_initialized = true;
+        auction.treasury = self.treasury.into();
+        auction.kind = self.kind;
+        auction.debt_amount = self.debt_amount;
+        auction.gov_amount = self.gov_amount;
+        auction.escrow = self.escrow.into();
+        auction.payout_token = payout_token;
+        auction.ends_at = self
+            .clock
+            .unix_timestamp
+            .checked_add(AUCTION_DURATION)
+            .ok_or(Error::Overflow)?;
+
+        auction.save_exempt(self.auction, &self.rent)?;
+        treasury.save(self.treasury)?;
+
+        Event::AuctionStarted {
+            auction: self.auction.into(),
+            treasury: self.treasury.into(),
+            kind: self.kind,
+            debt_amount: self.debt_amount,
+            gov_amount: self.gov_amount,
+            ends_at: auction.ends_at,
+        }
+        .log()
+    }
+}
+
+struct BidContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+    clock: Clock,
+
+    auction: &'a AccountInfo<'a>,           // writable
+    escrow: &'a AccountInfo<'a>,            // writable
+    auction_authority: &'a AccountInfo<'a>, // program account
+
+    bid_from: &'a AccountInfo<'a>,           // writable
+    bid_from_authority: &'a AccountInfo<'a>, // signed
+    payout: &'a AccountInfo<'a>,
+
+    // uninitialized state to record the outbid bid in, once the auction has a
+    // bid
+    refund: Option<&'a AccountInfo<'a>>, // writable
+
+    gov_amount: u64,
+    auction_authority_nonce: u8,
+}
+
+impl<'a> BidContext<'a> {
+    fn process(&self) -> ProgramResult {
+        check_program_owned(self.program_id, self.auction)?;
+        let mut auction = Auction::load_initialized(self.auction)?;
+
+        if self.clock.unix_timestamp >= auction.ends_at {
+            return Err(Error::AuctionEnded)?;
+        }
+
+        if !auction.escrow.is_account(self.escrow) {
+            return Err(Error::InvalidAuction)?;
+        }
+
+        // the lot is minted to the payout account when settled
+        let payout = unpack_token_account(self.payout)?;
+        if auction.payout_token.ne(&PublicKey(payout.mint.to_bytes())) {
+            return Err(match auction.kind {
+                AuctionKind::Debt => Error::InvalidGovernanceToken,
+                AuctionKind::Surplus => Error::InvalidDebtToken,
+            })?;
+        }
+
+        if !auction
+            .is_better_bid(self.gov_amount)
+            .ok_or(Error::Overflow)?
+        {
+            return Err(Error::BidTooLow)?;
+        }
+
+        let auction_authority_seeds = &[
+            &self.auction.key.to_bytes()[..],
+            AUCTION_ROLE.as_bytes(),
+            &[self.auction_authority_nonce],
+        ];
+
+        let auction_authority =
+            expected_program_account_pubkey(self.program_id, auction_authority_seeds)?;
+        if auction_authority.ne(self.auction_authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        // a debt auction escrows the raised debt tokens, a surplus auction the
+        // governance tokens paid
+        let bid_amount = |gov_amount| match auction.kind {
+            AuctionKind::Debt => auction.debt_amount,
+            AuctionKind::Surplus => gov_amount,
+        };
+
+        // the outbid bid stays in the escrow until its bidder claims it, so a
+        // bidder can't block the auction with an account that can't receive it
+        if auction.has_bid() {
+            let refund_account = self.refund.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let mut refund = AuctionRefund::init_uninitialized(refund_account)?;
+
+            refund.is_initialized = true;
+            refund.auction = self.auction.into();
+            refund.escrow = auction.escrow.clone();
+            refund.bidder = auction.bidder.clone();
+            refund.amount = bid_amount(auction.gov_amount);
+            refund.save_exempt(refund_account, &Rent::get()?)?;
+        }
+
+        transfer_tokens(
+            self.token_program,
+            self.bid_from,
+            self.escrow,
+            self.bid_from_authority,
+            &[],
+            bid_amount(self.gov_amount),
+        )?;
+
+        auction.gov_amount = self.gov_amount;
+        auction.bidder = self.bid_from.into();
+        auction.payout = self.payout.into();
+
+        auction.save(self.auction)?;
+
+        Event::AuctionBid {
+            auction: self.auction.into(),
+            bidder: auction.bidder,
+            gov_amount: self.gov_amount,
+        }
+        .log()
+    }
+}
+
+struct ClaimRefundContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    refund: &'a AccountInfo<'a>,            // writable
+    escrow: &'a AccountInfo<'a>,            // writable
+    auction_authority: &'a AccountInfo<'a>, // program account
+
+    refund_to: &'a AccountInfo<'a>, // writable
+    // owner of refund_to, which gets the rent of the refund account
+    receiver: &'a AccountInfo<'a>, // writable
+
+    auction_authority_nonce: u8,
+}
+
+impl<'a> ClaimRefundContext<'a> {
+    fn process(&self) -> ProgramResult {
+        check_program_owned(self.program_id, self.refund)?;
+        let refund = AuctionRefund::load_initialized(self.refund)?;
+
+        if !refund.escrow.is_account(self.escrow) {
+            return Err(Error::InvalidAuction)?;
+        }
+        if !refund.bidder.is_account(self.refund_to) {
+            return Err(Error::OwnerMismatch)?;
+        }
+        check_token_account_owner(self.refund_to, &self.receiver.into())?;
+
+        // the auction may be settled and closed already, its authority still
+        // signs for the escrow
+        let auction_authority_seeds = &[
+            &refund.auction.0[..],
+            AUCTION_ROLE.as_bytes(),
+            &[self.auction_authority_nonce],
+        ];
+
+        let auction_authority =
+            expected_program_account_pubkey(self.program_id, auction_authority_seeds)?;
+        if auction_authority.ne(self.auction_authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        transfer_tokens(
+            self.token_program,
+            self.escrow,
+            self.refund_to,
+            self.auction_authority,
+            &[auction_authority_seeds],
+            refund.amount,
+        )?;
+
+        close_account(self.refund, self.receiver)?;
+
+        Event::AuctionRefundClaimed {
+            auction: refund.auction,
+            refund: self.refund.into(),
+            bidder: refund.bidder,
+            amount: refund.amount,
+        }
+        .log()
+    }
+}
+
+struct SettleAuctionContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+    clock: Clock,
+
+    auction: &'a AccountInfo<'a>,  // writable
+    treasury: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+
+    escrow: &'a AccountInfo<'a>,            // writable
+    auction_authority: &'a AccountInfo<'a>, // program account
+
+    debt_token: &'a AccountInfo<'a>,       // writable
+    governance_token: &'a AccountInfo<'a>, // writable
+    debt_minter: &'a AccountInfo<'a>,      // program account
+
+    payout: &'a AccountInfo<'a>,   // writable
+    receiver: &'a AccountInfo<'a>, // writable, debt type owner
+
+    auction_authority_nonce: u8,
+    debt_minter_nonce: u8,
+}
+
+impl<'a> SettleAuctionContext<'a> {
+    fn process(&self) -> ProgramResult {
+        check_program_owned(self.program_id, self.auction)?;
+        let auction = Auction::load_initialized(self.auction)?;
+
+        if self.clock.unix_timestamp < auction.ends_at {
+            return Err(Error::AuctionNotEnded)?;
+        }
+
+        if !auction.treasury.is_account(self.treasury) || !auction.escrow.is_account(self.escrow) {
+            return Err(Error::InvalidAuction)?;
+        }
+
+        check_program_owned(self.program_id, self.debt_type)?;
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        let mut treasury = load_treasury(&debt_type, self.treasury)?;
+
+        if !debt_type.debt_token.is_account(self.debt_token) {
+            return Err(Error::InvalidDebtToken)?;
+        }
+        if !treasury.governance_token.is_account(self.governance_token) {
+            return Err(Error::InvalidGovernanceToken)?;
+        }
+        if !debt_type.owner.is_account(self.receiver) {
+            return Err(Error::OwnerMismatch)?;
+        }
+
+        if auction.has_bid() {
+            if !auction.payout.is_account(self.payout) {
+                return Err(Error::OwnerMismatch)?;
+            }
+
+            self.pay_winner(&auction)?;
+        } else {
+            // no one bid, the amount goes back to the treasury
+            match auction.kind {
+                AuctionKind::Debt => treasury.spend(auction.debt_amount),
+                AuctionKind::Surplus => treasury.earn(auction.debt_amount),
+            }
+            .ok_or(Error::Overflow)?;
+        }
+
+        treasury.save(self.treasury)?;
+        close_account(self.auction, self.receiver)?;
+
+        Event::AuctionSettled {
+            auction: self.auction.into(),
+            treasury: self.treasury.into(),
+            bidder: auction.bidder,
+            debt_amount: auction.debt_amount,
+            gov_amount: auction.gov_amount,
+        }
+        .log()
+    }
+
+    /// Burn the escrowed bid, and mint the lot to the winner.
+    fn pay_winner(&self, auction: &Auction) -> ProgramResult {
+        let auction_authority_seeds = &[
+            &self.auction.key.to_bytes()[..],
+            AUCTION_ROLE.as_bytes(),
+            &[self.auction_authority_nonce],
+        ];
+
+        let auction_authority =
+            expected_program_account_pubkey(self.program_id, auction_authority_seeds)?;
+        if auction_authority.ne(self.auction_authority.key) {
+            return Err(Error::UnexpectedProgramAccount.into());
+        }
+
+        let debt_minter_seeds = &[
+            &self.debt_type.key.to_bytes()[..],
+            MINTER_ROLE.as_bytes(),
+            &[self.debt_minter_nonce],
+        ];
+
+        let debt_minter = expected_program_account_pubkey(self.program_id, debt_minter_seeds)?;
+        if debt_minter.ne(self.debt_minter.key) {
+            return Err(Error::UnexpectedProgramAccount.into());
+        }
+
+        let (burn_token, burn_amount, mint_token, mint_amount) = match auction.kind {
+            AuctionKind::Debt => (
+                self.debt_token,
+                auction.debt_amount,
+                self.governance_token,
+                auction.gov_amount,
+            ),
+            AuctionKind::Surplus => (
+                self.governance_token,
+                auction.gov_amount,
+                self.debt_token,
+                auction.debt_amount,
+            ),
+        };
+
+        burn_tokens(
+            self.token_program,
+            burn_token,
+            self.escrow,
+            self.auction_authority,
+            &[auction_authority_seeds],
+            burn_amount,
+        )?;
+
+        mint_tokens(
+            self.token_program,
+            mint_token,
+            self.payout,
+            self.debt_minter,
+            &[debt_minter_seeds],
+            mint_amount,
+        )
+    }
+}
+
+struct InitAllowlistContext<'a> {
+    program_id: &'a Pubkey,
+
+    rent: Rent,
+    allowlist: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    debt_type_owner: &'a AccountInfo<'a>, // signed
+}
+
+impl<'a> InitAllowlistContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut allowlist = Allowlist::init_uninitialized(self.allowlist)?;
+
+        check_program_owned(self.program_id, self.debt_type)?;
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        check_signed_by(&debt_type.owner, self.debt_type_owner)?;
+
+        allowlist.is_initialized = true;
+        allowlist.debt_type = self.debt_type.into();
+
+        allowlist.save_exempt(self.allowlist, &self.rent)?;
+
+        Event::AllowlistInitialized {
+            allowlist: self.allowlist.into(),
+            debt_type: self.debt_type.into(),
+        }
+        .log()
+    }
+}
+
+struct UpdateAllowlistContext<'a> {
+    program_id: &'a Pubkey,
+
+    allowlist: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    debt_type_owner: &'a AccountInfo<'a>, // signed
+
+    address: PublicKey,
+    // remove the address if false
+    add: bool,
+}
+
+impl<'a> UpdateAllowlistContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut allowlist = Allowlist::load_initialized(self.allowlist)?;
+
+        check_program_owned(self.program_id, self.debt_type)?;
+        if !allowlist.debt_type.is_account(self.debt_type) {
+            return Err(Error::DebtTypeMismatch)?;
+        }
+
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        check_signed_by(&debt_type.owner, self.debt_type_owner)?;
+
+        if self.add {
+            allowlist.add(self.address.clone())?;
+        } else {
+            allowlist.remove(&self.address);
+        }
+
+        allowlist.save(self.allowlist)?;
+
+        Event::AllowlistUpdated {
+            allowlist: self.allowlist.into(),
+            address: self.address.clone(),
+            added: self.add,
+        }
+        .log()
+    }
+}
+
+struct LiquidateContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    debt_token: &'a AccountInfo<'a>,          // writable
+    debt_from: &'a AccountInfo<'a>,           // writable
+    debt_from_authority: &'a AccountInfo<'a>, // signed
+
+    collateral_holder: &'a AccountInfo<'a>, // writable
+    collateral_holder_authority: &'a AccountInfo<'a>, // program account
+    collateral_to: &'a AccountInfo<'a>,     // writable
+
+    treasury: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    vault_type: &'a AccountInfo<'a>, // writable
+    vault: &'a AccountInfo<'a>,      // writable
+
+    price_oracle: &'a AccountInfo<'a>,
+
+    collateral_holder_nonce: u8,
+}
+
+impl<'a> LiquidateContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault_type = VaultType::load_initialized(self.vault_type)?;
+        let mut vault = Vault::load_initialized(self.vault)?;
+
+        check_debt_token(
+            self.program_id,
+            self.debt_token,
+            self.debt_type,
+            self.vault_type,
+            &vault_type,
+            &vault,
+        )?;
+        let debt_type = DebtType::load_initialized(self.debt_type)?;
+        let mut treasury = load_treasury(&debt_type, self.treasury)?;
+
+        if !vault_type
+            .collateral_token_holder
+            .is_account(self.collateral_holder)
+        {
+            return Err(Error::CollateralHolderAccountMismatch)?;
+        }
+
+        vault_type.accrue_vault(&mut vault, Clock::get()?.unix_timestamp)?;
+
+        let price = collateral_price(
+            self.program_id,
+            self.vault_type,
+            &vault_type,
+            self.price_oracle,
+        )?;
+        if vault.debt_amount == 0 || health::vault_health(&vault, &vault_type, &price)?.is_healthy()
+        {
+            return Err(Error::VaultHealthy)?;
+        }
+
+        let liquidation = health::liquidation(&vault, &vault_type, &price)?;
+        treasury.earn(liquidation.penalty).ok_or(Error::Overflow)?;
+
+        burn_tokens(
+            self.token_program,
+            self.debt_token,
+            self.debt_from,
+            self.debt_from_authority,
+            &[],
+            liquidation.debt_repaid,
+        )?;
+
+        let holder_seeds = &[
+            &self.vault_type.key.to_bytes()[..],
+            COLLATERAL_HOLDER_ROLE.as_bytes(),
+            &[self.collateral_holder_nonce],
+        ];
+
+        let holder_authority = expected_program_account_pubkey(self.program_id, holder_seeds)?;
+        if holder_authority.ne(self.collateral_holder_authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        let collateral_seized = liquidation.collateral_seized;
+        transfer_tokens(
+            self.token_program,
+            self.collateral_holder,
+            self.collateral_to,
+            self.collateral_holder_authority,
+            &[holder_seeds],
+            collateral_seized,
+        )?;
+
+        vault_type.remove_collateral(collateral_seized)?;
+        vault.collateral_amount -= collateral_seized;
+        vault.debt_amount = liquidation.debt_left;
+
+        treasury.save(self.treasury)?;
+        vault_type.save(self.vault_type)?;
+        vault.save(self.vault)?;
+
+        Event::VaultLiquidated {
+            vault: self.vault.into(),
+            vault_type: self.vault_type.into(),
+            treasury: self.treasury.into(),
+            collateral_seized,
+            debt_repaid: liquidation.debt_repaid,
+            penalty: liquidation.penalty,
+            debt_amount: vault.debt_amount,
+        }
+        .log()
+    }
+}
+
+/// Accounts of every collateral of a LiquidateMulti.
+const LIQUIDATE_MULTI_COLLATERAL_ACCOUNTS: usize = 5;
+
+struct LiquidateMultiContext<'a> {
+    program_id: &'a Pubkey,
+
+    token_program: &'a AccountInfo<'a>,
+
+    debt_token: &'a AccountInfo<'a>,          // writable
+    debt_from: &'a AccountInfo<'a>,           // writable
+    debt_from_authority: &'a AccountInfo<'a>, // signed
+
+    treasury: &'a AccountInfo<'a>, // writable
+    debt_type: &'a AccountInfo<'a>,
+    multi_vault: &'a AccountInfo<'a>, // writable
+
+    // for every collateral in the vault, in the order of its slots: the vault
+    // type (writable), its price oracle, collateral holder (writable) and the
+    // holder's authority, and the account receiving the seized collateral
+    // (writable)
+    collateral_accounts: &'a [AccountInfo<'a>],
+
+    // of the collateral holders, by slot
+    collateral_holder_nonces: [u8; MAX_MULTI_VAULT_COLLATERALS],
+}
+
+impl<'a> LiquidateMultiContext<'a> {
+    fn process(&self) -> ProgramResult {
+        let mut vault = MultiVault::load_initialized(self.multi_vault)?;
+
+        let debt_type =
+            load_multi_vault_debt_type(self.program_id, self.debt_token, self.debt_type, &vault)?;
+        let mut treasury = load_treasury(&debt_type, self.treasury)?;
+
+        let slots: Vec<usize> = (0..MAX_MULTI_VAULT_COLLATERALS)
+            .filter(|slot| vault.collaterals[*slot].amount > 0)
+            .collect();
+        if self.collateral_accounts.len() < slots.len() * LIQUIDATE_MULTI_COLLATERAL_ACCOUNTS {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let collateral_accounts: Vec<&[AccountInfo]> = self
+            .collateral_accounts
+            .chunks(LIQUIDATE_MULTI_COLLATERAL_ACCOUNTS)
+            .take(slots.len())
+            .collect();
+
+        let mut vault_types = Vec::with_capacity(slots.len());
+        let mut prices = Vec::with_capacity(slots.len());
+        let mut max_debt: u64 = 0;
+        for (slot, accounts) in slots.iter().zip(&collateral_accounts) {
+            let balance = &vault.collaterals[*slot];
+            if !balance.vault_type.is_account(&accounts[0]) {
+                return Err(Error::VaultTypeMismatch)?;
+            }
+
+            let vault_type = VaultType::load_initialized(&accounts[0])?;
+            let price = collateral_price(self.program_id, &accounts[0], &vault_type, &accounts[1])?;
+            max_debt = max_debt
+                .checked_add(health::max_debt(&vault_type, &price, balance.amount)?)
+                .ok_or(Error::Overflow)?;
+
+            vault_types.push(vault_type);
+            prices.push(price);
+        }
+
+        if vault.debt_amount == 0 || vault.debt_amount <= max_debt {
+            return Err(Error::VaultHealthy)?;
+        }
+
+        let collaterals: Vec<Collateral> = slots
+            .iter()
+            .zip(vault_types.iter().zip(&prices))
+            .map(|(slot, (vault_type, price))| Collateral {
+                vault_type,
+                price: *price,
+                amount: vault.collaterals[*slot].amount,
+            })
+            .collect();
+        let liquidation = health::multi_vault_liquidation(vault.debt_amount, &collaterals)?;
+        treasury.earn(liquidation.penalty).ok_or(Error::Overflow)?;
+
+        burn_tokens(
+            self.token_program,
+            self.debt_token,
+            self.debt_from,
+            self.debt_from_authority,
+            &[],
+            liquidation.debt_repaid,
+        )?;
+
+        let mut collateral_seized = [0; MAX_MULTI_VAULT_COLLATERALS];
+        for (i, (slot, accounts)) in slots.iter().zip(&collateral_accounts).enumerate() {
+            let seized = liquidation.collateral_seized[i];
+            collateral_seized[*slot] = seized;
+            self.seize_collateral(
+                &accounts[0],
+                &mut vault_types[i],
+                &accounts[2..],
+                self.collateral_holder_nonces[*slot],
+                seized,
+            )?;
+
+            let balance = &mut vault.collaterals[*slot];
+            balance.amount -= seized;
+            if balance.amount == 0 {
+                *balance = CollateralBalance::default();
+            }
+        }
+
+        vault.debt_amount = liquidation.debt_left;
+
+        treasury.save(self.treasury)?;
+        vault.save(self.multi_vault)?;
+
+        Event::MultiVaultLiquidated {
+            multi_vault: self.multi_vault.into(),
+            treasury: self.treasury.into(),
+            collateral_seized,
+            debt_repaid: liquidation.debt_repaid,
+            penalty: liquidation.penalty,
+            debt_amount: vault.debt_amount,
+        }
+        .log()
+    }
+
+    /// Transfer the collateral out of the vault type's holder. The accounts
+    /// are the holder, its authority, and the receiving account.
+    fn seize_collateral(
+        &self,
+        vault_type_account: &AccountInfo<'a>,
+        vault_type: &mut VaultType,
+        accounts: &[AccountInfo<'a>],
+        collateral_holder_nonce: u8,
+        amount: u64,
+    ) -> ProgramResult {
+        let (holder, holder_authority, collateral_to) = (&accounts[0], &accounts[1], &accounts[2]);
+
+        if !vault_type.collateral_token_holder.is_account(holder) {
+            return Err(Error::CollateralHolderAccountMismatch)?;
+        }
+
+        let holder_seeds = &[
+            &vault_type_account.key.to_bytes()[..],
+            COLLATERAL_HOLDER_ROLE.as_bytes(),
+            &[collateral_holder_nonce],
+        ];
+
+        let expected_authority = expected_program_account_pubkey(self.program_id, holder_seeds)?;
+        if expected_authority.ne(holder_authority.key) {
+            return Err(Error::UnexpectedProgramAccount)?;
+        }
+
+        transfer_tokens(
+            self.token_program,
+            holder,
+            collateral_to,
+            holder_authority,
+            &[holder_seeds],
+            amount,
+        )?;
+
+        vault_type.remove_collateral(amount)?;
+        vault_type.save(vault_type_account)
+    }
+}
 
 pub struct Processor {}
 
-impl Processor {
-    pub fn process<'a>(
-        program_id: &'a Pubkey,
-        accounts: &'a [AccountInfo<'a>],
-        input: &[u8],
-    ) -> ProgramResult {
-        let accounts = Accounts(accounts);
-        let instruction =
-            Instruction::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)?;
+impl Processor {
+    pub fn process<'a>(
+        program_id: &'a Pubkey,
+        accounts: &'a [AccountInfo<'a>],
+        input: &[u8],
+    ) -> ProgramResult {
+        let accounts = Accounts(accounts);
+        let instruction =
+            Instruction::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+        // match branches would increase stack frame, and hit the hard 4096
+        // frame limit. break the other branches into another function call, and
+        // mark it as never inline.
+        match instruction {
+            Instruction::InitDebtType { debt_token, owner } => InitDebtTypeContext {
+                rent: accounts.get_rent(0)?,
+                debt_type: accounts.get(1)?,
+                debt_token_mint: accounts.get(2)?,
+
+                debt_token,
+                owner,
+            }
+            .process(),
+            Instruction::InitVaultType {
+                debt_type,
+                collateral_token,
+                price_oracle,
+                collateral_token_holder,
+                pricing,
+                params,
+                native,
+            } => InitVaultTypeContext {
+                program_id,
+                rent: accounts.get_rent(0)?,
+                vault_type: accounts.get(1)?,
+                collateral_token_mint: accounts.get(2)?,
+                debt_type_account: accounts.get(3)?,
+                debt_type_owner: accounts.get(4)?,
+
+                debt_type,
+                collateral_token,
+                collateral_token_holder,
+                price_oracle,
+                pricing,
+                params,
+                native,
+            }
+            .process(),
+
+            Instruction::InitVault {
+                vault_type,
+                owner,
+                tokenize,
+            } => InitVaultContext {
+                program_id,
+                rent: accounts.get_rent(0)?,
+                vault: accounts.get(1)?,
+
+                position_token: if tokenize {
+                    Some(PositionTokenAccounts {
+                        token_program: accounts.get(5)?,
+                        rent: accounts.get(0)?,
+                        mint: accounts.get(2)?,
+                        account: accounts.get(3)?,
+                        authority: accounts.get(4)?,
+                    })
+                } else {
+                    None
+                },
+
+                vault_type_account: accounts.get(if tokenize { 6 } else { 2 })?,
+                allowlist: accounts.get(if tokenize { 7 } else { 3 }).ok(),
+
+                vault_type,
+                owner,
+            }
+            .process(),
+
+            Instruction::Stake {
+                amount,
+                collateral_holder_nonce,
+            } => StakeContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                collateral_from: accounts.get(1)?,
+                collateral_from_authority: accounts.get(2)?,
+                collateral_to: accounts.get(3)?,
+
+                vault_type: accounts.get(4)?,
+                vault: accounts.get(5)?,
+
+                extra_accounts: accounts.rest(6),
+
+                amount,
+                collateral_holder_nonce,
+            }
+            .process(),
+            Instruction::Unstake {
+                amount,
+                collateral_holder_nonce,
+                optional_accounts,
+            } => UnstakeContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                collateral_from: accounts.get(1)?,
+                collateral_from_authority: accounts.get(2)?,
+                collateral_to: accounts.get(3)?,
+
+                vault_type: accounts.get(4)?,
+                vault: accounts.get(5)?,
+                vault_owner: accounts.get(6)?,
+
+                price_oracle: accounts.get(7)?,
+
+                extra_accounts: accounts.rest(8),
+
+                amount,
+                collateral_holder_nonce,
+                optional_accounts,
+            }
+            .process(),
+            Instruction::Borrow {
+                amount,
+                debt_minter_nonce,
+                optional_accounts,
+            } => BorrowContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                debt_token: accounts.get(1)?,
+                debt_minter: accounts.get(2)?,
+                debt_receiver: accounts.get(3)?,
+
+                debt_type: accounts.get(4)?,
+                vault_type: accounts.get(5)?,
+                vault: accounts.get(6)?,
+                vault_owner: accounts.get(7)?,
+
+                price_oracle: accounts.get(8)?,
+
+                extra_accounts: accounts.rest(9),
+
+                amount,
+                debt_minter_nonce,
+                optional_accounts,
+            }
+            .process(),
+            Instruction::Repay { amount } => RepayContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                debt_token: accounts.get(1)?,
+                debt_from: accounts.get(2)?,
+                debt_from_authority: accounts.get(3)?,
+
+                debt_type: accounts.get(4)?,
+                vault_type: accounts.get(5)?,
+                vault: accounts.get(6)?,
+
+                amount,
+            }
+            .process(),
+            Instruction::CloseVault => CloseVaultContext {
+                vault: accounts.get(0)?,
+                vault_owner: accounts.get(1)?,
+                receiver: accounts.get(2)?,
+
+                position_account: accounts.get(3).ok(),
+            }
+            .process(),
+            Instruction::TransferVault {
+                new_owner,
+                require_accept,
+            } => TransferVaultContext {
+                vault: accounts.get(0)?,
+                vault_owner: accounts.get(1)?,
+
+                new_owner,
+                require_accept,
+            }
+            .process(),
+            Instruction::AcceptVault => AcceptVaultContext {
+                vault: accounts.get(0)?,
+                new_owner: accounts.get(1)?,
+            }
+            .process(),
+            Instruction::SetVaultDelegate {
+                delegate,
+                permissions,
+                borrow_cap,
+            } => SetVaultDelegateContext {
+                program_id,
+                rent: accounts.get_rent(0)?,
+                vault_delegate: accounts.get(1)?,
+                vault: accounts.get(2)?,
+                vault_owner: accounts.get(3)?,
 
-        // match branches would increase stack frame, and hit the hard 4096
-        // frame limit. break the other branches into another function call, and
-        // mark it as never inline.
-        match instruction {
-            Instruction::InitDebtType { debt_token, owner } => InitDebtTypeContext {
+                position_account: accounts.get(4).ok(),
+
+                delegate,
+                permissions,
+                borrow_cap,
+            }
+            .process(),
+            Instruction::RevokeVaultDelegate => RevokeVaultDelegateContext {
+                program_id,
+                vault_delegate: accounts.get(0)?,
+                vault: accounts.get(1)?,
+                vault_owner: accounts.get(2)?,
+                receiver: accounts.get(3)?,
+
+                position_account: accounts.get(4).ok(),
+            }
+            .process(),
+            Instruction::InitPriceFeed { owner, decimals } => InitPriceFeedContext {
                 rent: accounts.get_rent(0)?,
-                debt_type: accounts.get(1)?,
+                price_feed: accounts.get(1)?,
+                token_mint: accounts.get(2)?,
 
-                debt_token,
                 owner,
+                decimals,
             }
             .process(),
-            Instruction::InitVaultType {
-                debt_type,
-                collateral_token,
-                price_oracle,
-                collateral_token_holder,
-            } => InitVaultTypeContext {
+            Instruction::UpdatePrice { price } => UpdatePriceContext {
+                price_feed: accounts.get(0)?,
+                owner: accounts.get(1)?,
+
+                price,
+            }
+            .process(),
+            Instruction::InitLpPool {
+                owner,
+                price_feed_a,
+                price_feed_b,
+            } => InitLpPoolContext {
+                program_id,
+
                 rent: accounts.get_rent(0)?,
-                vault_type: accounts.get(1)?,
+                lp_pool: accounts.get(1)?,
+                token_a_mint: accounts.get(2)?,
+                token_b_mint: accounts.get(3)?,
+                price_feed_a_account: accounts.get(4)?,
+                price_feed_b_account: accounts.get(5)?,
 
-                debt_type,
-                collateral_token,
-                collateral_token_holder,
-                price_oracle,
+                owner,
+                price_feed_a,
+                price_feed_b,
+            }
+            .process(),
+            Instruction::UpdateLpPool {
+                reserve_a,
+                reserve_b,
+                lp_supply,
+            } => UpdateLpPoolContext {
+                program_id,
+                lp_pool: accounts.get(0)?,
+                price_feed_a: accounts.get(1)?,
+                price_feed_b: accounts.get(2)?,
+                owner: Some(accounts.get(3)?),
+
+                reserves: Some((reserve_a, reserve_b, lp_supply)),
             }
             .process(),
+            Instruction::RefreshLpPool => UpdateLpPoolContext {
+                program_id,
+                lp_pool: accounts.get(0)?,
+                price_feed_a: accounts.get(1)?,
+                price_feed_b: accounts.get(2)?,
+                owner: None,
 
-            Instruction::InitVault { vault_type, owner } => InitVaultContext {
+                reserves: None,
+            }
+            .process(),
+            Instruction::InitMultiVault { debt_type, owner } => InitMultiVaultContext {
+                program_id,
                 rent: accounts.get_rent(0)?,
-                vault: accounts.get(1)?,
+                multi_vault: accounts.get(1)?,
+                debt_type_account: accounts.get(2)?,
 
-                vault_type,
+                vault_type: accounts.get(3).ok(),
+                allowlist: accounts.get(4).ok(),
+
+                debt_type,
                 owner,
             }
             .process(),
+            Instruction::StakeMulti {
+                amount,
+                collateral_holder_nonce,
+            } => StakeMultiContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                collateral_from: accounts.get(1)?,
+                collateral_from_authority: accounts.get(2)?,
+                collateral_to: accounts.get(3)?,
+
+                vault_type: accounts.get(4)?,
+                multi_vault: accounts.get(5)?,
+
+                allowlist: accounts.get(6).ok(),
 
-            Instruction::Stake {
                 amount,
                 collateral_holder_nonce,
-            } => StakeContext {
+            }
+            .process(),
+            Instruction::UnstakeMulti {
+                amount,
+                collateral_holder_nonce,
+            } => UnstakeMultiContext {
                 program_id,
                 token_program: accounts.get(0)?,
 
@@ -339,16 +4130,19 @@ impl Processor {
                 collateral_to: accounts.get(3)?,
 
                 vault_type: accounts.get(4)?,
-                vault: accounts.get(5)?,
+                multi_vault: accounts.get(5)?,
+                vault_owner: accounts.get(6)?,
+
+                valuation_accounts: accounts.rest(7),
 
                 amount,
                 collateral_holder_nonce,
             }
             .process(),
-            Instruction::Borrow {
+            Instruction::BorrowMulti {
                 amount,
                 debt_minter_nonce,
-            } => BorrowContext {
+            } => BorrowMultiContext {
                 program_id,
                 token_program: accounts.get(0)?,
 
@@ -357,25 +4151,399 @@ impl Processor {
                 debt_receiver: accounts.get(3)?,
 
                 debt_type: accounts.get(4)?,
-                vault_type: accounts.get(5)?,
-                vault: accounts.get(6)?,
-                vault_owner: accounts.get(7)?,
+                multi_vault: accounts.get(5)?,
+                vault_owner: accounts.get(6)?,
 
-                price_oracle: accounts.get(8)?,
+                valuation_accounts: accounts.rest(7),
 
                 amount,
                 debt_minter_nonce,
             }
             .process(),
+            Instruction::UpdateVaultTypeParams { params } => UpdateVaultTypeParamsContext {
+                program_id,
+                vault_type: accounts.get(0)?,
+                debt_type: accounts.get(1)?,
+                debt_type_owner: accounts.get(2)?,
+
+                params,
+            }
+            .process(),
+            Instruction::Deleverage {
+                collateral_amount,
+                minimum_debt_amount,
+                collateral_holder_nonce,
+                optional_accounts,
+            } => DeleverageContext {
+                program_id,
+                token_program: accounts.get(0)?,
+                swap_program: accounts.get(1)?,
 
-            _ => Err(ProgramError::InvalidInstructionData),
-        }
+                collateral_holder: accounts.get(2)?,
+                collateral_holder_authority: accounts.get(3)?,
+
+                debt_token: accounts.get(4)?,
+                debt_account: accounts.get(5)?,
+
+                debt_type: accounts.get(6)?,
+                vault_type: accounts.get(7)?,
+                vault: accounts.get(8)?,
+                vault_owner: accounts.get(9)?,
+
+                price_oracle: accounts.get(10)?,
+
+                extra_accounts: accounts.rest(11),
+
+                collateral_amount,
+                minimum_debt_amount,
+                collateral_holder_nonce,
+                optional_accounts,
+            }
+            .process(),
+            Instruction::Leverage {
+                amount,
+                minimum_collateral_amount,
+                debt_minter_nonce,
+                collateral_holder_nonce,
+                optional_accounts,
+            } => LeverageContext {
+                borrow: BorrowContext {
+                    program_id,
+                    token_program: accounts.get(0)?,
+
+                    debt_token: accounts.get(2)?,
+                    debt_minter: accounts.get(3)?,
+                    debt_receiver: accounts.get(4)?,
+
+                    debt_type: accounts.get(7)?,
+                    vault_type: accounts.get(8)?,
+                    vault: accounts.get(9)?,
+                    vault_owner: accounts.get(10)?,
+
+                    price_oracle: accounts.get(11)?,
+
+                    extra_accounts: accounts.rest(12),
+
+                    amount,
+                    debt_minter_nonce,
+                    optional_accounts,
+                },
+                swap_program: accounts.get(1)?,
+
+                leverage_authority: accounts.get(5)?,
+                collateral_holder: accounts.get(6)?,
+
+                minimum_collateral_amount,
+                collateral_holder_nonce,
+            }
+            .process(),
+            Instruction::InitSavings {
+                rate,
+                savings_authority_nonce,
+            } => InitSavingsContext {
+                program_id,
+                rent: accounts.get_rent(0)?,
+                clock: accounts.get_clock(1)?,
+
+                savings: accounts.get(2)?,
+                debt_type: accounts.get(3)?,
+                debt_type_owner: accounts.get(4)?,
+
+                share_token: accounts.get(5)?,
+                debt_holder: accounts.get(6)?,
+
+                rate,
+                savings_authority_nonce,
+            }
+            .process(),
+            Instruction::SetSavingsRate { rate } => SetSavingsRateContext {
+                program_id,
+                clock: accounts.get_clock(0)?,
+
+                savings: accounts.get(1)?,
+                debt_type: accounts.get(2)?,
+                debt_type_owner: accounts.get(3)?,
+
+                rate,
+            }
+            .process(),
+            Instruction::Drip { debt_minter_nonce } => DripContext {
+                program_id,
+                token_program: accounts.get(0)?,
+                clock: accounts.get_clock(1)?,
+
+                savings: accounts.get(2)?,
+                share_token: accounts.get(3)?,
+                debt_holder: accounts.get(4)?,
+
+                debt_type: accounts.get(5)?,
+                debt_token: accounts.get(6)?,
+                debt_minter: accounts.get(7)?,
+                treasury: accounts.get(8)?,
+
+                vault_types: accounts.rest(9),
+
+                debt_minter_nonce,
+            }
+            .process(),
+            Instruction::JoinSavings {
+                amount,
+                savings_authority_nonce,
+            } => JoinSavingsContext {
+                program_id,
+                token_program: accounts.get(0)?,
+                clock: accounts.get_clock(1)?,
+
+                savings: accounts.get(2)?,
+
+                debt_from: accounts.get(3)?,
+                debt_from_authority: accounts.get(4)?,
+                debt_holder: accounts.get(5)?,
+
+                share_token: accounts.get(6)?,
+                savings_authority: accounts.get(7)?,
+                share_to: accounts.get(8)?,
+
+                amount,
+                savings_authority_nonce,
+            }
+            .process(),
+            Instruction::ExitSavings {
+                shares,
+                savings_authority_nonce,
+            } => ExitSavingsContext {
+                program_id,
+                token_program: accounts.get(0)?,
+                clock: accounts.get_clock(1)?,
+
+                savings: accounts.get(2)?,
+
+                share_token: accounts.get(3)?,
+                share_from: accounts.get(4)?,
+                share_from_authority: accounts.get(5)?,
+
+                debt_holder: accounts.get(6)?,
+                savings_authority: accounts.get(7)?,
+                debt_to: accounts.get(8)?,
+
+                shares,
+                savings_authority_nonce,
+            }
+            .process(),
+            Instruction::InitTreasury { governance_token } => InitTreasuryContext {
+                rent: accounts.get_rent(0)?,
+
+                treasury: accounts.get(1)?,
+                debt_type: accounts.get(2)?,
+                debt_type_owner: accounts.get(3)?,
+
+                governance_token,
+            }
+            .process(),
+            Instruction::WithdrawSurplus {
+                amount,
+                debt_minter_nonce,
+            } => WithdrawSurplusContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                treasury: accounts.get(1)?,
+                debt_type: accounts.get(2)?,
+                debt_type_owner: accounts.get(3)?,
+
+                debt_token: accounts.get(4)?,
+                debt_minter: accounts.get(5)?,
+                debt_receiver: accounts.get(6)?,
+
+                amount,
+                debt_minter_nonce,
+            }
+            .process(),
+            Instruction::WriteOffVault => WriteOffVaultContext {
+                program_id,
+                treasury: accounts.get(0)?,
+                debt_type: accounts.get(1)?,
+                vault_type: accounts.get(2)?,
+                vault: accounts.get(3)?,
+            }
+            .process(),
+            Instruction::StartAuction {
+                kind,
+                debt_amount,
+                gov_amount,
+                auction_authority_nonce,
+            } => StartAuctionContext {
+                program_id,
+                rent: accounts.get_rent(0)?,
+                clock: accounts.get_clock(1)?,
+
+                auction: accounts.get(2)?,
+                treasury: accounts.get(3)?,
+                debt_type: accounts.get(4)?,
+                debt_type_owner: accounts.get(5)?,
+
+                escrow: accounts.get(6)?,
+
+                kind,
+                debt_amount,
+                gov_amount,
+                auction_authority_nonce,
+            }
+            .process(),
+            Instruction::Bid {
+                gov_amount,
+                auction_authority_nonce,
+            } => BidContext {
+                program_id,
+                token_program: accounts.get(0)?,
+                clock: accounts.get_clock(1)?,
+
+                auction: accounts.get(2)?,
+                escrow: accounts.get(3)?,
+                auction_authority: accounts.get(4)?,
+
+                bid_from: accounts.get(5)?,
+                bid_from_authority: accounts.get(6)?,
+                payout: accounts.get(7)?,
+
+                refund: accounts.get(8).ok(),
+
+                gov_amount,
+                auction_authority_nonce,
+            }
+            .process(),
+            Instruction::SettleAuction {
+                auction_authority_nonce,
+                debt_minter_nonce,
+            } => SettleAuctionContext {
+                program_id,
+                token_program: accounts.get(0)?,
+                clock: accounts.get_clock(1)?,
+
+                auction: accounts.get(2)?,
+                treasury: accounts.get(3)?,
+                debt_type: accounts.get(4)?,
+
+                escrow: accounts.get(5)?,
+                auction_authority: accounts.get(6)?,
+
+                debt_token: accounts.get(7)?,
+                governance_token: accounts.get(8)?,
+                debt_minter: accounts.get(9)?,
+
+                payout: accounts.get(10)?,
+                receiver: accounts.get(11)?,
+
+                auction_authority_nonce,
+                debt_minter_nonce,
+            }
+            .process(),
+            Instruction::InitAllowlist => InitAllowlistContext {
+                program_id,
+                rent: accounts.get_rent(0)?,
+                allowlist: accounts.get(1)?,
+                debt_type: accounts.get(2)?,
+                debt_type_owner: accounts.get(3)?,
+            }
+            .process(),
+            Instruction::AddToAllowlist { address } => UpdateAllowlistContext {
+                program_id,
+                allowlist: accounts.get(0)?,
+                debt_type: accounts.get(1)?,
+                debt_type_owner: accounts.get(2)?,
+
+                address,
+                add: true,
+            }
+            .process(),
+            Instruction::RemoveFromAllowlist { address } => UpdateAllowlistContext {
+                program_id,
+                allowlist: accounts.get(0)?,
+                debt_type: accounts.get(1)?,
+                debt_type_owner: accounts.get(2)?,
+
+                address,
+                add: false,
+            }
+            .process(),
+            Instruction::Liquidate {
+                collateral_holder_nonce,
+            } => LiquidateContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                debt_token: accounts.get(1)?,
+                debt_from: accounts.get(2)?,
+                debt_from_authority: accounts.get(3)?,
+
+                collateral_holder: accounts.get(4)?,
+                collateral_holder_authority: accounts.get(5)?,
+                collateral_to: accounts.get(6)?,
 
-        // Instruction::Unstake { amount } => {}
-        // Instruction::Repay { amount } => {}
+                treasury: accounts.get(7)?,
+                debt_type: accounts.get(8)?,
+                vault_type: accounts.get(9)?,
+                vault: accounts.get(10)?,
+
+                price_oracle: accounts.get(11)?,
+
+                collateral_holder_nonce,
+            }
+            .process(),
+            Instruction::RepayMulti { amount } => RepayMultiContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                debt_token: accounts.get(1)?,
+                debt_from: accounts.get(2)?,
+                debt_from_authority: accounts.get(3)?,
+
+                debt_type: accounts.get(4)?,
+                multi_vault: accounts.get(5)?,
+
+                amount,
+            }
+            .process(),
+            Instruction::LiquidateMulti {
+                collateral_holder_nonces,
+            } => LiquidateMultiContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                debt_token: accounts.get(1)?,
+                debt_from: accounts.get(2)?,
+                debt_from_authority: accounts.get(3)?,
+
+                treasury: accounts.get(4)?,
+                debt_type: accounts.get(5)?,
+                multi_vault: accounts.get(6)?,
+
+                collateral_accounts: accounts.rest(7),
+
+                collateral_holder_nonces,
+            }
+            .process(),
+            Instruction::ClaimRefund {
+                auction_authority_nonce,
+            } => ClaimRefundContext {
+                program_id,
+                token_program: accounts.get(0)?,
+
+                refund: accounts.get(1)?,
+                escrow: accounts.get(2)?,
+                auction_authority: accounts.get(3)?,
+
+                refund_to: accounts.get(4)?,
+                receiver: accounts.get(5)?,
+
+                auction_authority_nonce,
+            }
+            .process(),
+        }
     }
 }
 
+#[cfg(test)]
 mod tests {
     use crate::borsh_utils;
 
@@ -1,11 +1,30 @@
 use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use std::{convert::TryFrom, io};
 
 use solana_program::{
-  account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
-  program_pack::IsInitialized,
+    account_info::AccountInfo, clock::Slot, program_pack::IsInitialized, pubkey::Pubkey,
 };
 
-use crate::borsh_state::{BorshState, InitBorshState};
+use crate::{
+    borsh_state::{BorshState, InitBorshState},
+    borsh_utils::deserialize_array,
+    error::Error,
+    math::{Decimal, WAD},
+};
+
+/// Ratios are expressed in basis points.
+pub const RATIO_BASE: u64 = 10_000;
+
+/// Fixed point base of rate accumulators, the raw value of a Decimal.
+pub const ACCUMULATOR_BASE: u128 = WAD;
+
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// How much a bid must improve on the best bid of an auction, in RATIO_BASE.
+pub const AUCTION_BID_INCREMENT: u64 = 500;
+
+/// Seconds from the start of an auction until it can be settled.
+pub const AUCTION_DURATION: i64 = 6 * 60 * 60;
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
 pub struct PublicKey(pub [u8; 32]);
 
@@ -21,13 +40,31 @@ impl<'a> From<&'a AccountInfo<'a>> for PublicKey {
     }
 }
 
+impl From<&Pubkey> for PublicKey {
+    fn from(key: &Pubkey) -> Self {
+        PublicKey(key.to_bytes())
+    }
+}
+
+impl From<PublicKey> for Pubkey {
+    fn from(key: PublicKey) -> Self {
+        Pubkey::new_from_array(key.0)
+    }
+}
+
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
 pub struct DebtType {
     pub is_initialized: bool,
 
     // program account should be minter for this token
     pub debt_token: PublicKey,
+    // decimals of the debt token mint
+    pub decimals: u8,
+
     pub owner: PublicKey,
+
+    // set once by InitTreasury
+    pub treasury: PublicKey,
 }
 impl IsInitialized for DebtType {
     fn is_initialized(&self) -> bool {
@@ -43,20 +80,209 @@ pub struct VaultType {
 
     // belongs to this debt type
     pub debt_type: PublicKey,
+    // decimals of the debt token, copied from the debt type
+    pub debt_decimals: u8,
 
     // type of spl-token to accept as collateral
     pub collateral_token: PublicKey,
+    // decimals of the collateral token mint
+    pub collateral_decimals: u8,
 
     // token account to hold the collaterals. A program account owns this token account.
     pub collateral_token_holder: PublicKey,
 
+    // collateral is native SOL. Stake wraps the lamports of the signer into
+    // collateral_token_holder, and Unstake unwraps them.
+    pub native: bool,
+
+    // PriceFeed or LpPool, depending on pricing
     pub price_oracle: PublicKey,
+    pub pricing: CollateralPricing,
 
-    // config
-    // max_collateral_ratio
+    // config, updatable by the debt type owner
+    pub params: VaultTypeParams,
+
+    // total collateral staked by all vaults of this type
+    pub collateral_amount: u64,
     // debt_ceiling
     // current_debt_amount
-    // interest_rate
+
+    // debt owed per unit of debt owed at the start, the raw value of a Decimal.
+    // Grows by the stability fee, and never decreases.
+    pub debt_accumulator: u128,
+    // unix timestamp of the last accrual
+    pub last_accrual: i64,
+    // stability fees the vaults accrued, until a Drip collects them into the
+    // treasury
+    pub stability_fees: u64,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub struct VaultTypeParams {
+    // min collateral value to debt, in basis points
+    pub collateral_ratio: u64,
+
+    // program implementing the swap interface, to trade collateral for debt
+    // tokens. Default if trading is disabled.
+    pub swap_program: PublicKey,
+
+    // added to the debt of every borrow, in basis points of the borrowed amount
+    pub origination_fee: u64,
+
+    // max total collateral of the vault type, 0 if uncapped
+    pub collateral_cap: u64,
+
+    // Allowlist of the vault owners that may open a vault and borrow. Default
+    // if the vault type is permissionless.
+    pub allowlist: PublicKey,
+
+    // added to the debt of a liquidated vault and credited to the treasury, in
+    // basis points of the debt
+    pub liquidation_penalty: u64,
+
+    // yearly rate charged on the debt, in basis points, compounded on every
+    // accrual. It funds the savings rate.
+    pub stability_fee: u64,
+}
+
+impl VaultTypeParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        // borrowing more than the collateral value is never allowed
+        if self.collateral_ratio < RATIO_BASE {
+            return Err(Error::InvalidCollateralRatio);
+        }
+
+        if self.origination_fee >= RATIO_BASE
+            || self.liquidation_penalty >= RATIO_BASE
+            || self.stability_fee >= RATIO_BASE
+        {
+            return Err(Error::InvalidFee);
+        }
+
+        Ok(())
+    }
+
+    /// Origination fee of the borrowed amount, rounded up.
+    pub fn origination_fee(&self, amount: u64) -> Result<u64, Error> {
+        Decimal::from_u64(amount)
+            .try_mul_ceil(Decimal::from_bps(self.origination_fee))?
+            .try_ceil_u64()
+    }
+
+    /// A permissioned vault type only allows the addresses on its allowlist.
+    pub fn is_permissioned(&self) -> bool {
+        self.allowlist.ne(&PublicKey::default())
+    }
+
+    /// Liquidation penalty of the debt amount, rounded up.
+    pub fn liquidation_penalty(&self, debt_amount: u64) -> Result<u64, Error> {
+        Decimal::from_u64(debt_amount)
+            .try_mul_ceil(Decimal::from_bps(self.liquidation_penalty))?
+            .try_ceil_u64()
+    }
+}
+
+/// How the collateral of a vault type is valued.
+#[derive(Clone, Copy, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub enum CollateralPricing {
+    // price_oracle is a PriceFeed of the collateral token
+    #[default]
+    PriceFeed,
+
+    // collateral is a constant product pool's lp token, and price_oracle is an
+    // LpPool
+    LpPool,
+}
+
+impl VaultType {
+    /// Max debt that collateral of the given value can back, rounded down.
+    pub fn max_debt(&self, collateral_value: Decimal) -> Result<u64, Error> {
+        collateral_value
+            .try_div_floor(Decimal::from_bps(self.params.collateral_ratio))?
+            .try_floor_u64()
+    }
+
+    /// Record collateral staked by a vault, up to the collateral cap.
+    pub fn add_collateral(&mut self, amount: u64) -> Result<(), Error> {
+        let collateral_amount = self
+            .collateral_amount
+            .checked_add(amount)
+            .ok_or(Error::Overflow)?;
+
+        if self.params.collateral_cap != 0 && collateral_amount > self.params.collateral_cap {
+            return Err(Error::CollateralCapExceeded);
+        }
+
+        self.collateral_amount = collateral_amount;
+
+        Ok(())
+    }
+
+    /// Record collateral unstaked by a vault.
+    pub fn remove_collateral(&mut self, amount: u64) -> Result<(), Error> {
+        self.collateral_amount = self
+            .collateral_amount
+            .checked_sub(amount)
+            .ok_or(Error::InsufficientCollateral)?;
+
+        Ok(())
+    }
+
+    /// Grow the debt accumulator by the stability fee for the time since the
+    /// last accrual.
+    pub fn accrue(&mut self, now: i64) -> Result<(), Error> {
+        self.debt_accumulator = compound(
+            self.debt_accumulator,
+            self.params.stability_fee,
+            self.last_accrual,
+            now,
+        )?;
+        self.last_accrual = now;
+
+        Ok(())
+    }
+
+    /// Add the stability fee the vault accrued since its last accrual to its
+    /// debt, rounded up, and record it as a fee of the vault type. Returns the
+    /// fee.
+    pub fn accrue_vault(&mut self, vault: &mut Vault, now: i64) -> Result<u64, Error> {
+        self.accrue(now)?;
+
+        let fee = if vault.debt_amount == 0 || vault.debt_accumulator == 0 {
+            0
+        } else {
+            let debt_amount = Decimal::from_u64(vault.debt_amount)
+                .try_mul_ratio_ceil(self.debt_accumulator, vault.debt_accumulator)?
+                .try_ceil_u64()?;
+            debt_amount - vault.debt_amount
+        };
+
+        vault.debt_amount += fee;
+        vault.debt_accumulator = self.debt_accumulator;
+        self.stability_fees = self
+            .stability_fees
+            .checked_add(fee)
+            .ok_or(Error::Overflow)?;
+
+        Ok(fee)
+    }
+}
+
+/// Grow a rate accumulator by the yearly rate, in RATIO_BASE, for the time
+/// between the timestamps.
+fn compound(accumulator: u128, rate: u64, from: i64, now: i64) -> Result<u128, Error> {
+    let elapsed = now
+        .checked_sub(from)
+        .and_then(|elapsed| u64::try_from(elapsed).ok())
+        .ok_or(Error::Overflow)?;
+
+    let accumulator = Decimal::from_raw(accumulator);
+    let growth = accumulator.try_mul_ratio_floor(
+        rate as u128 * elapsed as u128,
+        RATIO_BASE as u128 * SECONDS_PER_YEAR as u128,
+    )?;
+
+    Ok(accumulator.try_add(growth)?.raw())
 }
 
 impl IsInitialized for VaultType {
@@ -78,8 +304,24 @@ pub struct Vault {
     // anyone can repay and stake
     pub owner: PublicKey,
 
+    // set by a two-step TransferVault, and becomes the owner on AcceptVault
+    pub pending_owner: PublicKey,
+
+    // supply-1 token mint. If set, the holder of the token owns the vault
+    // instead of `owner`.
+    pub position_token: PublicKey,
+
     pub debt_amount: u64,
     pub collateral_amount: u64,
+
+    // debt accumulator of the vault type when the vault last accrued the
+    // stability fee. 0 if it never did.
+    pub debt_accumulator: u128,
+}
+impl Vault {
+    pub fn is_tokenized(&self) -> bool {
+        self.position_token.ne(&PublicKey::default())
+    }
 }
 impl IsInitialized for Vault {
     fn is_initialized(&self) -> bool {
@@ -89,6 +331,497 @@ impl IsInitialized for Vault {
 impl BorshState for Vault {}
 impl InitBorshState for Vault {}
 
+/// Price of a collateral token in debt tokens, updated by its owner. The price
+/// is of whole tokens, so it doesn't depend on the decimals of either mint.
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub struct PriceFeed {
+    pub is_initialized: bool,
+
+    // only owner can update the price
+    pub owner: PublicKey,
+
+    // debt tokens for one collateral token is price / 10^decimals
+    pub price: u64,
+    pub decimals: u8,
+
+    // mint of the token priced
+    pub token: PublicKey,
+}
+
+impl PriceFeed {
+    pub fn price(&self) -> Result<Decimal, Error> {
+        Decimal::from_amount(self.price, self.decimals)
+    }
+
+    /// Value in whole debt tokens of an amount in base units of a collateral
+    /// token with the decimals, rounded down.
+    pub fn value(&self, amount: u64, decimals: u8) -> Result<Decimal, Error> {
+        Decimal::from_amount(amount, decimals)?.try_mul_floor(self.price()?)
+    }
+}
+impl IsInitialized for PriceFeed {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl BorshState for PriceFeed {}
+impl InitBorshState for PriceFeed {}
+
+/// Reserves of a constant product pool, reported by a keeper, to value the
+/// pool's lp tokens.
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub struct LpPool {
+    pub is_initialized: bool,
+
+    // only owner can update the reserves
+    pub owner: PublicKey,
+
+    // PriceFeeds of the two pool tokens
+    pub price_feed_a: PublicKey,
+    pub price_feed_b: PublicKey,
+
+    // decimals of the two pool token mints
+    pub decimals_a: u8,
+    pub decimals_b: u8,
+
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    pub lp_supply: u64,
+
+    // fair value of all the lp tokens in whole debt tokens, as of the last
+    // refresh. The raw value of a Decimal.
+    pub pool_value: u128,
+
+    // slot of the last refresh. Prices of past slots are stale, so vaults
+    // only take the pool value refreshed in the current slot.
+    pub last_update: Slot,
+}
+
+impl LpPool {
+    /// Recompute the pool value with the "fair reserves" formula. The reserves
+    /// of a constant product pool can be skewed by a trade, but not their
+    /// product k. At the oracle prices the fair reserves are
+    ///
+    ///   fair_a = sqrt(k * price_b / price_a), fair_b = sqrt(k * price_a / price_b)
+    ///
+    /// so the pool is worth fair_a * price_a + fair_b * price_b, which is
+    /// 2 * sqrt(value_a * value_b).
+    pub fn refresh(
+        &mut self,
+        price_a: &PriceFeed,
+        price_b: &PriceFeed,
+        slot: Slot,
+    ) -> Result<(), Error> {
+        let value_a = price_a.value(self.reserve_a, self.decimals_a)?;
+        let value_b = price_b.value(self.reserve_b, self.decimals_b)?;
+
+        let root = value_a.try_geometric_mean(value_b)?;
+        self.pool_value = root.try_add(root)?.raw();
+        self.last_update = slot;
+
+        Ok(())
+    }
+
+    /// Value of the lp token amount in whole debt tokens, rounded down.
+    pub fn value(&self, lp_amount: u64) -> Result<Decimal, Error> {
+        Decimal::from_raw(self.pool_value)
+            .try_mul_ratio_floor(lp_amount as u128, self.lp_supply as u128)
+    }
+}
+impl IsInitialized for LpPool {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl BorshState for LpPool {}
+impl InitBorshState for LpPool {}
+
+pub const MAX_MULTI_VAULT_COLLATERALS: usize = 4;
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub struct CollateralBalance {
+    // default if the slot is free
+    pub vault_type: PublicKey,
+    pub amount: u64,
+}
+
+/// A vault whose debt is backed by the collaterals of several vault types of
+/// the same debt type.
+#[derive(Clone, Debug, BorshSerialize, BorshSchema, Default, PartialEq)]
+pub struct MultiVault {
+    pub is_initialized: bool,
+
+    pub debt_type: PublicKey,
+
+    // only owner can borrow and unstake
+    pub owner: PublicKey,
+
+    pub debt_amount: u64,
+    pub collaterals: [CollateralBalance; MAX_MULTI_VAULT_COLLATERALS],
+}
+
+impl BorshDeserialize for MultiVault {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(MultiVault {
+            is_initialized: BorshDeserialize::deserialize(buf)?,
+            debt_type: BorshDeserialize::deserialize(buf)?,
+            owner: BorshDeserialize::deserialize(buf)?,
+            debt_amount: BorshDeserialize::deserialize(buf)?,
+            collaterals: deserialize_array(buf)?,
+        })
+    }
+}
+
+impl MultiVault {
+    /// Balance of the vault type's collateral, taking a free slot if the vault
+    /// doesn't hold that collateral yet.
+    pub fn balance_for(&mut self, vault_type: &PublicKey) -> Result<&mut CollateralBalance, Error> {
+        let index = match self
+            .collaterals
+            .iter()
+            .position(|b| b.vault_type.eq(vault_type))
+        {
+            Some(index) => index,
+            None => self
+                .collaterals
+                .iter()
+                .position(|b| b.vault_type.eq(&PublicKey::default()))
+                .ok_or(Error::TooManyCollaterals)?,
+        };
+
+        let balance = &mut self.collaterals[index];
+        balance.vault_type = vault_type.clone();
+
+        Ok(balance)
+    }
+}
+impl IsInitialized for MultiVault {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl BorshState for MultiVault {}
+impl InitBorshState for MultiVault {}
+
+/// Permissions that a vault owner can grant to a `VaultDelegate`. Staking and
+/// repaying need no permission, anyone can do that.
+pub mod delegate_permission {
+    pub const BORROW: u8 = 1 << 0;
+    pub const UNSTAKE: u8 = 1 << 1;
+    pub const DELEVERAGE: u8 = 1 << 2;
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub struct VaultDelegate {
+    pub is_initialized: bool,
+
+    pub vault: PublicKey,
+
+    // the vault owner that granted the permissions. The delegate stops working
+    // if the vault is transferred to another owner.
+    pub vault_owner: PublicKey,
+
+    // key allowed to act on the vault
+    pub delegate: PublicKey,
+
+    // bit set of delegate_permission
+    pub permissions: u8,
+
+    // max debt the delegate may borrow in total
+    pub borrow_cap: u64,
+    pub borrowed_amount: u64,
+}
+impl IsInitialized for VaultDelegate {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl BorshState for VaultDelegate {}
+impl InitBorshState for VaultDelegate {}
+
+pub const MAX_ALLOWLIST_ENTRIES: usize = 32;
+
+/// Addresses allowed to use the permissioned vault types of a debt type.
+#[derive(Clone, Debug, BorshSerialize, BorshSchema, Default, PartialEq)]
+pub struct Allowlist {
+    pub is_initialized: bool,
+
+    pub debt_type: PublicKey,
+
+    // default for a free entry
+    pub entries: [PublicKey; MAX_ALLOWLIST_ENTRIES],
+}
+impl BorshDeserialize for Allowlist {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Allowlist {
+            is_initialized: BorshDeserialize::deserialize(buf)?,
+            debt_type: BorshDeserialize::deserialize(buf)?,
+            entries: deserialize_array(buf)?,
+        })
+    }
+}
+impl IsInitialized for Allowlist {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl BorshState for Allowlist {}
+impl InitBorshState for Allowlist {}
+
+impl Allowlist {
+    pub fn contains(&self, address: &PublicKey) -> bool {
+        address.ne(&PublicKey::default()) && self.entries.contains(address)
+    }
+
+    pub fn add(&mut self, address: PublicKey) -> Result<(), Error> {
+        if self.contains(&address) {
+            return Ok(());
+        }
+
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.eq(&&PublicKey::default()))
+            .ok_or(Error::AllowlistFull)?;
+        *entry = address;
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, address: &PublicKey) {
+        for entry in self.entries.iter_mut().filter(|entry| entry.eq(&address)) {
+            *entry = PublicKey::default();
+        }
+    }
+}
+
+/// Savings module of a debt type. Debt tokens locked in the savings earn the
+/// savings rate, paid out as the growing exchange rate of the share token.
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub struct Savings {
+    pub is_initialized: bool,
+
+    pub debt_type: PublicKey,
+
+    // mint of the share token, minted by the savings authority
+    pub share_token: PublicKey,
+
+    // token account of the locked debt tokens, owned by the savings authority
+    pub debt_holder: PublicKey,
+
+    // yearly rate in RATIO_BASE, compounded on every drip
+    pub rate: u64,
+
+    // debt tokens per share, the raw value of a Decimal. Never decreases.
+    pub accumulator: u128,
+
+    // unix timestamp of the last drip
+    pub last_update: i64,
+}
+impl IsInitialized for Savings {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl BorshState for Savings {}
+impl InitBorshState for Savings {}
+
+/// Protocol revenue and losses of a debt type, in debt tokens.
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub struct Treasury {
+    pub is_initialized: bool,
+
+    pub debt_type: PublicKey,
+
+    // fees earned and not yet withdrawn. Backed by vault debt, so withdrawing
+    // mints new debt tokens.
+    pub surplus: u64,
+
+    // debt tokens in circulation that no vault debt backs
+    pub bad_debt: u64,
+
+    // stability fees collected from the vault types, which pay the savings
+    // interest. Backed by vault debt like the surplus.
+    pub stability_fees: u64,
+
+    // minted by the debt minter to cover bad debt in debt auctions, and
+    // burned in surplus auctions
+    pub governance_token: PublicKey,
+}
+impl IsInitialized for Treasury {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl BorshState for Treasury {}
+impl InitBorshState for Treasury {}
+
+#[derive(Clone, Copy, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub enum AuctionKind {
+    // raise debt tokens to cover bad debt, by minting governance tokens
+    #[default]
+    Debt,
+
+    // sell surplus debt tokens for governance tokens, which are burned
+    Surplus,
+}
+
+/// Auction of a treasury. The amount of debt tokens is fixed, and bidders
+/// compete on the amount of governance tokens.
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub struct Auction {
+    pub is_initialized: bool,
+
+    pub treasury: PublicKey,
+    pub kind: AuctionKind,
+
+    // debt tokens raised by a debt auction, or sold by a surplus auction
+    pub debt_amount: u64,
+
+    // governance tokens minted to the winner of a debt auction, or paid by the
+    // winner of a surplus auction. The starting lot until the first bid.
+    pub gov_amount: u64,
+
+    // token account holding the best bid, owned by the auction authority
+    pub escrow: PublicKey,
+
+    // token account that paid the best bid, which can claim it back from an
+    // AuctionRefund when outbid. Default if no one bid yet.
+    pub bidder: PublicKey,
+
+    // token account of the best bidder that receives the lot
+    pub payout: PublicKey,
+
+    // mint of the lot, the governance token of a debt auction, or the debt
+    // token of a surplus auction
+    pub payout_token: PublicKey,
+
+    // unix timestamp
+    pub ends_at: i64,
+}
+impl IsInitialized for Auction {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl BorshState for Auction {}
+impl InitBorshState for Auction {}
+
+impl Auction {
+    pub fn has_bid(&self) -> bool {
+        self.bidder.ne(&PublicKey::default())
+    }
+
+    /// A debt auction takes the lowest governance tokens minted, a surplus
+    /// auction the highest governance tokens paid. A bid must beat the best bid
+    /// by AUCTION_BID_INCREMENT.
+    pub fn is_better_bid(&self, gov_amount: u64) -> Option<bool> {
+        let base = RATIO_BASE as u128;
+        let increment = base.checked_add(AUCTION_BID_INCREMENT as u128)?;
+        let bid = gov_amount as u128;
+        let best = self.gov_amount as u128;
+
+        let better = match (self.kind, self.has_bid()) {
+            (AuctionKind::Debt, false) => bid <= best,
+            (AuctionKind::Debt, true) => bid.checked_mul(increment)? <= best.checked_mul(base)?,
+            (AuctionKind::Surplus, false) => bid > 0 && bid >= best,
+            (AuctionKind::Surplus, true) => {
+                bid.checked_mul(base)? >= best.checked_mul(increment)?
+            }
+        };
+
+        Some(better)
+    }
+}
+
+/// A bid that was outbid, left in the escrow of its auction until it's
+/// claimed back. The next bidder creates it.
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, Default, PartialEq)]
+pub struct AuctionRefund {
+    pub is_initialized: bool,
+
+    pub auction: PublicKey,
+    pub escrow: PublicKey,
+
+    // token account that paid the bid, and gets it back
+    pub bidder: PublicKey,
+    pub amount: u64,
+}
+impl IsInitialized for AuctionRefund {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl BorshState for AuctionRefund {}
+impl InitBorshState for AuctionRefund {}
+
+impl Treasury {
+    /// Record revenue. It covers bad debt first.
+    pub fn earn(&mut self, amount: u64) -> Option<()> {
+        let covered = amount.min(self.bad_debt);
+        self.bad_debt -= covered;
+        self.surplus = self.surplus.checked_add(amount - covered)?;
+
+        Some(())
+    }
+
+    /// Record a cost. It is paid out of surplus first, the rest becomes bad
+    /// debt.
+    pub fn spend(&mut self, amount: u64) -> Option<()> {
+        let paid = amount.min(self.surplus);
+        self.surplus -= paid;
+        self.bad_debt = self.bad_debt.checked_add(amount - paid)?;
+
+        Some(())
+    }
+}
+
+impl Savings {
+    /// Grow the accumulator by the rate for the time since the last drip.
+    /// Nothing accrues while there are no shares, so the first shares don't
+    /// join at an exchange rate that no one earned.
+    pub fn drip(&mut self, now: i64, shares: u64) -> Result<(), Error> {
+        let rate = if shares == 0 { 0 } else { self.rate };
+        self.accumulator = compound(self.accumulator, rate, self.last_update, now)?;
+        self.last_update = now;
+
+        Ok(())
+    }
+
+    /// Limit the accumulator, so that the shares redeem no more than the
+    /// backing. It still never decreases.
+    pub fn cap_accumulator(
+        &mut self,
+        shares: u64,
+        backing: u64,
+        previous: u128,
+    ) -> Result<(), Error> {
+        if shares == 0 {
+            return Ok(());
+        }
+
+        let cap = Decimal::from_u64(backing).try_div_floor(Decimal::from_u64(shares))?;
+        self.accumulator = self.accumulator.min(cap.raw().max(previous));
+
+        Ok(())
+    }
+
+    /// Shares bought by the debt amount, rounded down.
+    pub fn shares_for(&self, debt_amount: u64) -> Result<u64, Error> {
+        Decimal::from_u64(debt_amount)
+            .try_div_floor(Decimal::from_raw(self.accumulator))?
+            .try_floor_u64()
+    }
+
+    /// Debt tokens redeemed by the shares, rounded down.
+    pub fn debt_for(&self, shares: u64) -> Result<u64, Error> {
+        Decimal::from_u64(shares)
+            .try_mul_floor(Decimal::from_raw(self.accumulator))?
+            .try_floor_u64()
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use crate::borsh_utils;
 
@@ -99,5 +832,270 @@ mod tests {
         println!("DebtType len: {}", borsh_utils::get_packed_len::<DebtType>());
         println!("VaultType len: {}", borsh_utils::get_packed_len::<VaultType>());
         println!("Vault len: {}", borsh_utils::get_packed_len::<Vault>());
+        println!("PriceFeed len: {}", borsh_utils::get_packed_len::<PriceFeed>());
+        println!("LpPool len: {}", borsh_utils::get_packed_len::<LpPool>());
+        println!(
+            "MultiVault len: {}",
+            borsh_utils::get_packed_len::<MultiVault>()
+        );
+        println!(
+            "VaultDelegate len: {}",
+            borsh_utils::get_packed_len::<VaultDelegate>()
+        );
+        println!(
+            "Allowlist len: {}",
+            borsh_utils::get_packed_len::<Allowlist>()
+        );
+        println!("Savings len: {}", borsh_utils::get_packed_len::<Savings>());
+        println!(
+            "Treasury len: {}",
+            borsh_utils::get_packed_len::<Treasury>()
+        );
+        println!("Auction len: {}", borsh_utils::get_packed_len::<Auction>());
+        println!(
+            "AuctionRefund len: {}",
+            borsh_utils::get_packed_len::<AuctionRefund>()
+        );
+    }
+
+    #[test]
+    fn test_max_debt() {
+        let price_feed = PriceFeed {
+            price: 4_500_000,
+            decimals: 2,
+            ..PriceFeed::default()
+        };
+        let vault_type = VaultType {
+            params: VaultTypeParams {
+                collateral_ratio: 15000,
+                ..VaultTypeParams::default()
+            },
+            ..VaultType::default()
+        };
+
+        let value = price_feed.value(2, 0).unwrap();
+        assert_eq!(value, Decimal::from_u64(90000));
+        assert_eq!(vault_type.max_debt(value), Ok(60000));
+
+        // 2 of a 9 decimals token
+        assert_eq!(price_feed.value(2_000_000_000, 9), Ok(value));
+
+        assert_eq!(price_feed.value(u64::MAX, 0), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_collateral_cap() {
+        let mut vault_type = VaultType {
+            params: VaultTypeParams {
+                collateral_cap: 100,
+                ..VaultTypeParams::default()
+            },
+            ..VaultType::default()
+        };
+
+        assert_eq!(vault_type.add_collateral(100), Ok(()));
+        assert_eq!(
+            vault_type.add_collateral(1),
+            Err(Error::CollateralCapExceeded)
+        );
+        assert_eq!(vault_type.remove_collateral(40), Ok(()));
+        assert_eq!(vault_type.add_collateral(40), Ok(()));
+
+        // a lowered cap doesn't force unstaking
+        vault_type.params.collateral_cap = 50;
+        assert_eq!(vault_type.remove_collateral(10), Ok(()));
+        assert_eq!(vault_type.collateral_amount, 90);
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let mut allowlist = Allowlist::default();
+        let address = PublicKey([1; 32]);
+
+        assert!(!allowlist.contains(&PublicKey::default()));
+        assert!(!allowlist.contains(&address));
+
+        allowlist.add(address.clone()).unwrap();
+        allowlist.add(address.clone()).unwrap();
+        assert!(allowlist.contains(&address));
+        assert_eq!(
+            allowlist.entries.iter().filter(|e| e.eq(&&address)).count(),
+            1
+        );
+
+        allowlist.remove(&address);
+        assert!(!allowlist.contains(&address));
+
+        for i in 0..MAX_ALLOWLIST_ENTRIES {
+            allowlist.add(PublicKey([i as u8 + 2; 32])).unwrap();
+        }
+        assert_eq!(allowlist.add(address), Err(Error::AllowlistFull));
+
+        let data = allowlist.try_to_vec().unwrap();
+        assert_eq!(data.len(), borsh_utils::get_packed_len::<Allowlist>());
+        assert_eq!(Allowlist::try_from_slice(&data).unwrap(), allowlist);
+    }
+
+    #[test]
+    fn test_origination_fee() {
+        let params = VaultTypeParams {
+            collateral_ratio: RATIO_BASE,
+            origination_fee: 50,
+            ..VaultTypeParams::default()
+        };
+
+        assert_eq!(params.origination_fee(10_000), Ok(50));
+        assert_eq!(params.origination_fee(10_001), Ok(51));
+        assert_eq!(params.origination_fee(0), Ok(0));
+        assert_eq!(params.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_liquidation_penalty() {
+        let mut params = VaultTypeParams {
+            collateral_ratio: RATIO_BASE,
+            liquidation_penalty: 1_000,
+            ..VaultTypeParams::default()
+        };
+
+        assert_eq!(params.liquidation_penalty(1_000), Ok(100));
+        assert_eq!(params.liquidation_penalty(1_001), Ok(101));
+        assert_eq!(params.validate(), Ok(()));
+
+        params.liquidation_penalty = RATIO_BASE;
+        assert_eq!(params.validate(), Err(Error::InvalidFee));
+    }
+
+    #[test]
+    fn test_stability_fee() {
+        let mut vault_type = VaultType {
+            params: VaultTypeParams {
+                collateral_ratio: RATIO_BASE,
+                stability_fee: 1_000,
+                ..VaultTypeParams::default()
+            },
+            debt_accumulator: ACCUMULATOR_BASE,
+            ..VaultType::default()
+        };
+        assert_eq!(vault_type.params.validate(), Ok(()));
+
+        let mut vault = Vault {
+            debt_amount: 1_001,
+            debt_accumulator: ACCUMULATOR_BASE,
+            ..Vault::default()
+        };
+        // a vault that never accrued owes nothing for the time before
+        let mut new_vault = Vault {
+            debt_amount: 1_000,
+            ..Vault::default()
+        };
+
+        // 10% over a year, rounded up
+        let year = SECONDS_PER_YEAR as i64;
+        assert_eq!(vault_type.accrue_vault(&mut vault, year), Ok(101));
+        assert_eq!(vault.debt_amount, 1_102);
+        assert_eq!(vault_type.accrue_vault(&mut new_vault, year), Ok(0));
+        assert_eq!(new_vault.debt_amount, 1_000);
+
+        assert_eq!(vault_type.accrue_vault(&mut new_vault, 2 * year), Ok(100));
+        assert_eq!(new_vault.debt_amount, 1_100);
+        assert_eq!(vault_type.stability_fees, 201);
+
+        // the clock never goes back
+        assert_eq!(vault_type.accrue(year), Err(Error::Overflow));
+
+        vault_type.params.stability_fee = RATIO_BASE;
+        assert_eq!(vault_type.params.validate(), Err(Error::InvalidFee));
+    }
+
+    #[test]
+    fn test_lp_pool_value() {
+        let price_a = PriceFeed {
+            price: 4,
+            ..PriceFeed::default()
+        };
+        let price_b = PriceFeed {
+            price: 1,
+            ..PriceFeed::default()
+        };
+
+        let mut pool = LpPool {
+            reserve_a: 1000,
+            reserve_b: 4000,
+            lp_supply: 100,
+            ..LpPool::default()
+        };
+        pool.refresh(&price_a, &price_b, 1).unwrap();
+        assert_eq!(Decimal::from_raw(pool.pool_value), Decimal::from_u64(8000));
+        assert_eq!(pool.value(10), Ok(Decimal::from_u64(800)));
+
+        // skewing the reserves with a trade keeps k, but not the spot value
+        pool.reserve_a = 500;
+        pool.reserve_b = 8000;
+        pool.refresh(&price_a, &price_b, 1).unwrap();
+        assert_eq!(Decimal::from_raw(pool.pool_value), Decimal::from_u64(8000));
+
+        // the same pool, with token b of 6 decimals
+        pool.decimals_b = 6;
+        pool.reserve_b = 8_000_000_000;
+        pool.refresh(&price_a, &price_b, 1).unwrap();
+        assert_eq!(Decimal::from_raw(pool.pool_value), Decimal::from_u64(8000));
+
+        pool.lp_supply = 0;
+        assert_eq!(pool.value(10), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_savings_drip() {
+        let mut savings = Savings {
+            rate: 500,
+            accumulator: ACCUMULATOR_BASE,
+            ..Savings::default()
+        };
+
+        // nothing accrues without shares
+        savings.drip(SECONDS_PER_YEAR as i64, 0).unwrap();
+        assert_eq!(savings.accumulator, ACCUMULATOR_BASE);
+        assert_eq!(savings.last_update, SECONDS_PER_YEAR as i64);
+
+        // 5% over a year
+        savings.last_update = 0;
+        savings.drip(SECONDS_PER_YEAR as i64, 1_000_000).unwrap();
+        assert_eq!(savings.debt_for(1_000_000), Ok(1_050_000));
+        assert_eq!(savings.shares_for(1_050_000), Ok(1_000_000));
+
+        // the clock never goes back
+        assert_eq!(savings.drip(0, 1_000_000), Err(Error::Overflow));
+
+        // the interest is limited to the backing, but isn't taken back
+        let previous = savings.accumulator;
+        savings
+            .drip(2 * SECONDS_PER_YEAR as i64, 1_000_000)
+            .unwrap();
+        savings
+            .cap_accumulator(1_000_000, 1_060_000, previous)
+            .unwrap();
+        assert_eq!(savings.debt_for(1_000_000), Ok(1_060_000));
+        savings.cap_accumulator(1_000_000, 0, previous).unwrap();
+        assert_eq!(savings.debt_for(1_000_000), Ok(1_050_000));
+    }
+
+    #[test]
+    fn test_auction_bids() {
+        let mut auction = Auction {
+            kind: AuctionKind::Debt,
+            gov_amount: 1000,
+            ..Auction::default()
+        };
+        assert_eq!(auction.is_better_bid(1000), Some(true));
+        assert_eq!(auction.is_better_bid(1001), Some(false));
+
+        auction.bidder = PublicKey([1; 32]);
+        assert_eq!(auction.is_better_bid(1000), Some(false));
+        assert_eq!(auction.is_better_bid(952), Some(true));
+
+        auction.kind = AuctionKind::Surplus;
+        assert_eq!(auction.is_better_bid(1049), Some(false));
+        assert_eq!(auction.is_better_bid(1050), Some(true));
     }
 }
@@ -0,0 +1,97 @@
+//! Interface of the swap programs that collateral is traded through
+//!
+//! A swap program configured on a vault type must accept this instruction. Pool
+//! specific accounts are passed through as is, so an adapter program can route
+//! the trade to any AMM or orderbook.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub enum SwapInstruction {
+    /// Swap `amount_in` source tokens for at least `minimum_amount_out`
+    /// destination tokens.
+    ///
+    /// Accounts:
+    ///
+    /// 0. `[writable]` source token account
+    /// 1. `[writable]` destination token account
+    /// 2. `[signer]` source authority
+    /// 3. `[]` token program
+    /// 4. .. pool accounts
+    Swap {
+        amount_in: u64,
+        minimum_amount_out: u64,
+    },
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn swap(
+    swap_program_id: &Pubkey,
+    source: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    token_program_id: &Pubkey,
+    pool_accounts: &[AccountMeta],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Swap {
+        amount_in,
+        minimum_amount_out,
+    }
+    .try_to_vec()
+    .map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    let mut accounts = vec![
+        AccountMeta::new(*source, false),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    accounts.extend_from_slice(pool_accounts);
+
+    Ok(Instruction {
+        program_id: *swap_program_id,
+        accounts,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap_instruction() {
+        let pool = AccountMeta::new(Pubkey::new_unique(), false);
+        let inx = swap(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &spl_token::id(),
+            std::slice::from_ref(&pool),
+            100,
+            90,
+        )
+        .unwrap();
+
+        assert_eq!(inx.accounts.len(), 5);
+        assert_eq!(inx.accounts[4], pool);
+
+        match SwapInstruction::try_from_slice(&inx.data).unwrap() {
+            SwapInstruction::Swap {
+                amount_in,
+                minimum_amount_out,
+            } => {
+                assert_eq!(amount_in, 100);
+                assert_eq!(minimum_amount_out, 90);
+            }
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use solana_program::{
   account_info::AccountInfo,
-  clock::Clock,
+  entrypoint::ProgramResult,
   program_error::ProgramError,
-  sysvar::{rent::Rent, Sysvar},
+  sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
+use crate::error::Error;
+
 pub struct Accounts<'a>(pub &'a [AccountInfo<'a>]);
 
 impl<'a> Accounts<'a> {
@@ -14,6 +16,11 @@ impl<'a> Accounts<'a> {
       self.0.get(i).ok_or(ProgramError::NotEnoughAccountKeys)
   }
 
+  /// All the accounts from index i onward, possibly empty.
+  pub fn rest(&self, i: usize) -> &'a [AccountInfo<'a>] {
+      self.0.get(i..).unwrap_or(&[])
+  }
+
   pub fn get_rent(&self, i: usize) -> Result<Rent, ProgramError> {
       Rent::from_account_info(self.get(i)?)
   }
@@ -21,4 +28,22 @@ impl<'a> Accounts<'a> {
   pub fn get_clock(&self, i: usize) -> Result<Clock, ProgramError> {
       Clock::from_account_info(self.get(i)?)
   }
-}
\ No newline at end of file
+}
+
+/// Zero out the account data and move all its lamports to the receiver. The
+/// runtime garbage collects the account at the end of the transaction.
+pub fn close_account(account: &AccountInfo, receiver: &AccountInfo) -> ProgramResult {
+  let lamports = account.lamports();
+  **account.lamports.borrow_mut() = 0;
+  **receiver.lamports.borrow_mut() = receiver
+    .lamports()
+    .checked_add(lamports)
+    .ok_or(Error::Overflow)?;
+
+  let mut data = (*account.data).borrow_mut();
+  for byte in data.iter_mut() {
+    *byte = 0;
+  }
+
+  Ok(())
+}
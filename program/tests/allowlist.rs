@@ -0,0 +1,228 @@
+mod common;
+
+use common::{create_state, program_id, Env, COLLATERAL_RATIO};
+use solana_faucet::{
+    error::Error,
+    instruction,
+    state::{Allowlist, MultiVault, Vault, VaultTypeParams},
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use spl_token::state::Mint;
+
+fn custom(error: Error) -> Result<(), ProgramError> {
+    Err(error.into())
+}
+
+/// An Env whose vault type is permissioned by an empty allowlist. The vault of
+/// the user was opened before.
+fn setup() -> (Env, Pubkey) {
+    let mut env = Env::new();
+
+    let allowlist = create_state::<Allowlist>(&mut env.bank);
+    env.bank
+        .process(
+            &instruction::init_allowlist(&program_id(), &allowlist, &env.debt_type, &env.admin)
+                .unwrap(),
+        )
+        .unwrap();
+    env.bank
+        .process(
+            &instruction::update_vault_type_params(
+                &program_id(),
+                &env.vault_type,
+                &env.debt_type,
+                &env.admin,
+                VaultTypeParams {
+                    collateral_ratio: COLLATERAL_RATIO,
+                    allowlist: (&allowlist).into(),
+                    ..VaultTypeParams::default()
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    (env, allowlist)
+}
+
+fn update_allowlist(env: &mut Env, allowlist: &Pubkey, add: bool) {
+    let update = if add {
+        instruction::add_to_allowlist
+    } else {
+        instruction::remove_from_allowlist
+    };
+    env.bank
+        .process(
+            &update(
+                &program_id(),
+                allowlist,
+                &env.debt_type,
+                &env.admin,
+                &env.user,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+}
+
+fn borrow_instruction(env: &Env, allowlist: Option<&Pubkey>, amount: u64) -> Instruction {
+    instruction::borrow(
+        &program_id(),
+        &env.debt_token,
+        &env.debt_minter,
+        &env.user_debt,
+        &env.debt_type,
+        &env.vault_type,
+        &env.vault,
+        &env.user,
+        &env.price_feed,
+        None,
+        allowlist,
+        None,
+        None,
+        amount,
+        env.debt_minter_nonce,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_borrow_allowlist() {
+    let (mut env, allowlist) = setup();
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+
+    assert_eq!(
+        env.bank.process(&borrow_instruction(&env, None, 100)),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+    assert_eq!(
+        env.bank
+            .process(&borrow_instruction(&env, Some(&allowlist), 100)),
+        custom(Error::NotAllowlisted)
+    );
+
+    update_allowlist(&mut env, &allowlist, true);
+    env.bank
+        .process(&borrow_instruction(&env, Some(&allowlist), 100))
+        .unwrap();
+
+    let vault: Vault = env.bank.load(&env.vault);
+    assert_eq!(vault.debt_amount, 100);
+}
+
+#[test]
+fn test_init_tokenized_vault_allowlist() {
+    let (mut env, allowlist) = setup();
+
+    let mint = env.bank.create_account(Mint::LEN, &spl_token::id());
+    let position_account = env
+        .bank
+        .create_account(spl_token::state::Account::LEN, &spl_token::id());
+    let vault = create_state::<Vault>(&mut env.bank);
+    let instruction = instruction::init_vault(
+        &program_id(),
+        &vault,
+        &env.vault_type,
+        &env.user,
+        Some((&mint, &position_account)),
+        Some(&allowlist),
+    )
+    .unwrap();
+
+    // the position token accounts keep their place before the vault type
+    assert_eq!(instruction.accounts[2], AccountMeta::new(mint, false));
+    assert_eq!(
+        instruction.accounts[6],
+        AccountMeta::new_readonly(env.vault_type, false)
+    );
+
+    assert_eq!(
+        env.bank.process(&instruction),
+        custom(Error::NotAllowlisted)
+    );
+
+    update_allowlist(&mut env, &allowlist, true);
+    env.bank.process(&instruction).unwrap();
+
+    let vault: Vault = env.bank.load(&vault);
+    assert_eq!(vault.position_token, (&mint).into());
+}
+
+#[test]
+fn test_multi_vault_allowlist() {
+    let (mut env, allowlist) = setup();
+
+    let multi_vault = create_state::<MultiVault>(&mut env.bank);
+    let init = |allowlist| {
+        instruction::init_multi_vault(
+            &program_id(),
+            &multi_vault,
+            &env.debt_type,
+            &env.user,
+            Some(&env.vault_type),
+            allowlist,
+        )
+        .unwrap()
+    };
+    let (without_allowlist, with_allowlist) = (init(None), init(Some(&allowlist)));
+    assert_eq!(
+        env.bank.process(&without_allowlist),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+    assert_eq!(
+        env.bank.process(&with_allowlist),
+        custom(Error::NotAllowlisted)
+    );
+
+    update_allowlist(&mut env, &allowlist, true);
+    env.bank.process(&with_allowlist).unwrap();
+
+    let stake = instruction::stake_multi(
+        &program_id(),
+        &env.user_collateral,
+        &env.user,
+        &env.collateral_holder,
+        &env.vault_type,
+        &multi_vault,
+        Some(&allowlist),
+        1_000,
+        env.collateral_holder_nonce,
+    )
+    .unwrap();
+    env.bank.process(&stake).unwrap();
+
+    // a removed owner can no longer stake or borrow
+    update_allowlist(&mut env, &allowlist, false);
+    assert_eq!(env.bank.process(&stake), custom(Error::NotAllowlisted));
+
+    let borrow = instruction::borrow_multi(
+        &program_id(),
+        &env.debt_token,
+        &env.debt_minter,
+        &env.user_debt,
+        &env.debt_type,
+        &multi_vault,
+        &env.user,
+        &[
+            AccountMeta::new_readonly(env.vault_type, false),
+            AccountMeta::new_readonly(env.price_feed, false),
+        ],
+        None,
+        &[AccountMeta::new_readonly(allowlist, false)],
+        100,
+        env.debt_minter_nonce,
+    )
+    .unwrap();
+    assert_eq!(env.bank.process(&borrow), custom(Error::NotAllowlisted));
+
+    update_allowlist(&mut env, &allowlist, true);
+    env.bank.process(&borrow).unwrap();
+
+    let vault: MultiVault = env.bank.load(&multi_vault);
+    assert_eq!(vault.debt_amount, 100);
+}
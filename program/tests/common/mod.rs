@@ -0,0 +1,640 @@
+//! A bank for integration tests, on solana-program-test.
+//!
+//! The program runs natively, registered with `ProgramTest::add_program`,
+//! next to the spl-token program and the mock swap program. `cargo test-bpf`
+//! runs the program's BPF build instead. The tests are synchronous: the Bank
+//! drives the banks client on a runtime of its own.
+//!
+//! solana-program-test 1.7 registers its builtin programs with a macro that
+//! current compilers don't expand the way it expects, so the workspace
+//! patches solana-sdk-macro, see `patches/solana-sdk-macro`. Its BPF
+//! interpreter crashes when built by current compilers, so spl-token runs
+//! natively too, in place of the BPF build of the test genesis.
+
+#![allow(dead_code)]
+
+pub mod swap;
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    sync::{Mutex, Once},
+    thread::{self, ThreadId},
+};
+
+use borsh::{BorshDeserialize, BorshSchema};
+use solana_faucet::{
+    borsh_utils::get_packed_len,
+    instruction,
+    processor::Processor,
+    state::{CollateralPricing, DebtType, PriceFeed, Vault, VaultType, VaultTypeParams},
+};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::{Clock, Slot},
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{self, rent::Rent},
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{from_account, Account},
+    signature::{Keypair, Signature, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use tokio::runtime::Runtime;
+
+static PROGRAM_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+
+pub fn program_id() -> Pubkey {
+    PROGRAM_ID
+}
+
+/// The program's processor, for `processor!`.
+fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: &[u8],
+) -> ProgramResult {
+    with_accounts(program_id, accounts, |program_id, accounts| {
+        Processor::process(program_id, accounts, input)
+    })
+}
+
+/// A copy of an account passed to a program.
+struct AccountCopy {
+    key: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    owner: Pubkey,
+    executable: bool,
+    rent_epoch: u64,
+}
+
+/// Call `f` with copies of `accounts`, and write their lamports and data back
+/// if it succeeds. The processor and the cpi module take AccountInfos that
+/// live as long as the slice, which the ones of the runtime don't. A key
+/// passed more than once shares the same copy.
+pub fn with_accounts<F>(program_id: &Pubkey, accounts: &[AccountInfo], f: F) -> ProgramResult
+where
+    F: for<'b> FnOnce(&'b Pubkey, &'b [AccountInfo<'b>]) -> ProgramResult,
+{
+    let program_id = *program_id;
+    let mut copies: Vec<AccountCopy> = vec![];
+    for info in accounts {
+        if copies.iter().all(|copy| copy.key != *info.key) {
+            copies.push(AccountCopy {
+                key: *info.key,
+                lamports: info.lamports(),
+                data: info.data.borrow().to_vec(),
+                owner: *info.owner,
+                executable: info.executable,
+                rent_epoch: info.rent_epoch,
+            });
+        }
+    }
+
+    {
+        let unique_infos: Vec<AccountInfo> = copies
+            .iter_mut()
+            .map(|copy| {
+                AccountInfo::new(
+                    &copy.key,
+                    false,
+                    false,
+                    &mut copy.lamports,
+                    &mut copy.data,
+                    &copy.owner,
+                    copy.executable,
+                    copy.rent_epoch,
+                )
+            })
+            .collect();
+        let infos: Vec<AccountInfo> = accounts
+            .iter()
+            .map(|account| {
+                let mut info = unique_infos
+                    .iter()
+                    .find(|info| info.key == account.key)
+                    .unwrap()
+                    .clone();
+                info.is_signer = account.is_signer;
+                info.is_writable = account.is_writable;
+                info
+            })
+            .collect();
+
+        f(&program_id, &infos)?;
+    }
+
+    for copy in copies {
+        let info = accounts.iter().find(|info| *info.key == copy.key).unwrap();
+        **info.lamports.borrow_mut() = copy.lamports;
+        info.data.borrow_mut().copy_from_slice(&copy.data);
+    }
+
+    Ok(())
+}
+
+/// The programs of a Bank. A test can add its own before starting the Bank.
+pub fn program_test() -> ProgramTest {
+    LogRecorder::install();
+
+    let mut test = ProgramTest::default();
+    test.add_program(
+        "solana_faucet",
+        program_id(),
+        processor!(process_instruction),
+    );
+    test.add_builtin_program(
+        "spl_token",
+        spl_token::id(),
+        processor!(spl_token::processor::Processor::process).unwrap(),
+    );
+    test.add_builtin_program(
+        "mock_swap",
+        swap::SWAP_PROGRAM_ID,
+        processor!(swap::process).unwrap(),
+    );
+
+    // ProgramTest::default() sets up its own filter
+    log::set_max_level(log::LevelFilter::Debug);
+    test
+}
+
+/// Records the program logs, which the runtime writes to its debug log from
+/// the thread of the bank, as the banks client of 1.7 doesn't return them.
+struct LogRecorder;
+
+static LOG_RECORDER: LogRecorder = LogRecorder;
+static LOGS: Mutex<Vec<(ThreadId, String)>> = Mutex::new(vec![]);
+
+impl LogRecorder {
+    const TARGET: &'static str = "solana_runtime::message_processor";
+
+    fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| log::set_logger(&LOG_RECORDER).unwrap());
+    }
+}
+
+impl log::Log for LogRecorder {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target() == LogRecorder::TARGET
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let line = (thread::current().id(), record.args().to_string());
+            LOGS.lock().unwrap().push(line);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+pub struct Bank {
+    pub rent: Rent,
+    context: ProgramTestContext,
+    runtime: Runtime,
+    // the keypairs of the signers the tests use, and of the payers
+    signers: HashMap<Pubkey, Keypair>,
+    // the bank rejects a transaction it has already processed, so an identical
+    // one is paid for by another payer. A new blockhash isn't valid after a
+    // warp, as the root bank of ProgramTestContext doesn't know it.
+    payers: Vec<Pubkey>,
+    sent: HashSet<Signature>,
+    // the thread processing the transactions, once a test asked for the logs
+    log_thread: Option<ThreadId>,
+}
+
+impl Bank {
+    pub fn new() -> Self {
+        Bank::start(program_test())
+    }
+
+    pub fn start(test: ProgramTest) -> Self {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let context = runtime.block_on(test.start_with_context());
+        let payer = Keypair::from_bytes(&context.payer.to_bytes()).unwrap();
+
+        Bank {
+            rent: Rent::default(),
+            context,
+            runtime,
+            payers: vec![payer.pubkey()],
+            signers: vec![(payer.pubkey(), payer)].into_iter().collect(),
+            sent: HashSet::new(),
+            log_thread: None,
+        }
+    }
+
+    /// A new key that signs the instructions it is a signer of.
+    pub fn new_signer(&mut self) -> Pubkey {
+        let keypair = Keypair::new();
+        let key = keypair.pubkey();
+        self.signers.insert(key, keypair);
+        key
+    }
+
+    /// Transfer lamports to an account from the payer of the transactions.
+    pub fn fund(&mut self, key: &Pubkey, lamports: u64) {
+        let payer = self.payers[0];
+        self.process(&system_instruction::transfer(&payer, key, lamports))
+            .unwrap();
+    }
+
+    /// Create a rent exempt account of `space` zeroed bytes.
+    pub fn create_account(&mut self, space: usize, owner: &Pubkey) -> Pubkey {
+        self.create_account_with(space, owner, |_| vec![])
+    }
+
+    /// Like create_account, initializing the account with the instructions
+    /// built for its key, in the same transaction.
+    pub fn create_account_with<F>(&mut self, space: usize, owner: &Pubkey, init: F) -> Pubkey
+    where
+        F: FnOnce(&Pubkey) -> Vec<Instruction>,
+    {
+        let key = self.new_signer();
+        let mut instructions = vec![system_instruction::create_account(
+            &self.payers[0],
+            &key,
+            self.rent.minimum_balance(space),
+            space as u64,
+            owner,
+        )];
+        instructions.extend(init(&key));
+        self.process_transaction(&instructions).unwrap();
+        key
+    }
+
+    pub fn account(&mut self, key: &Pubkey) -> Account {
+        let banks_client = &mut self.context.banks_client;
+        self.runtime
+            .block_on(async { banks_client.get_account(*key).await })
+            .unwrap()
+            .expect("account not found")
+    }
+
+    pub fn load<T: BorshDeserialize>(&mut self, key: &Pubkey) -> T {
+        T::try_from_slice(&self.account(key).data).unwrap()
+    }
+
+    pub fn slot(&mut self) -> Slot {
+        let clock: Clock = from_account(&self.account(&sysvar::clock::id())).unwrap();
+        clock.slot
+    }
+
+    /// Move the bank to the slot, which has to be at least 2 slots ahead.
+    pub fn warp_to_slot(&mut self, slot: Slot) {
+        self.context.warp_to_slot(slot).unwrap();
+    }
+
+    /// The logs of the instructions processed since the last call.
+    pub fn take_logs(&mut self) -> Vec<String> {
+        let log_thread = match self.log_thread {
+            Some(log_thread) => log_thread,
+            None => self.find_log_thread(),
+        };
+
+        let mut logs = LOGS.lock().unwrap();
+        let (taken, rest) = logs
+            .drain(..)
+            .partition(|(thread, _)| *thread == log_thread);
+        *logs = rest;
+        taken.into_iter().map(|(_, line)| line).collect()
+    }
+
+    /// Log a message naming the payer, and find the thread that logged it.
+    fn find_log_thread(&mut self) -> ThreadId {
+        let marker = self.payers[0].to_string();
+        self.process(&swap::log_instruction(&marker)).unwrap();
+
+        let log_thread = LOGS
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, line)| line.contains(&marker))
+            .map(|(thread, _)| *thread)
+            .expect("memo not logged");
+        self.log_thread = Some(log_thread);
+        log_thread
+    }
+
+    /// Lamports of an account, 0 if it doesn't exist.
+    pub fn lamports(&mut self, key: &Pubkey) -> u64 {
+        let banks_client = &mut self.context.banks_client;
+        self.runtime
+            .block_on(async { banks_client.get_balance(*key).await })
+            .unwrap()
+    }
+
+    pub fn token_balance(&mut self, key: &Pubkey) -> u64 {
+        spl_token::state::Account::unpack(&self.account(key).data)
+            .unwrap()
+            .amount
+    }
+
+    /// Process a single instruction in a transaction of its own.
+    pub fn process(&mut self, instruction: &Instruction) -> ProgramResult {
+        self.process_transaction(std::slice::from_ref(instruction))
+    }
+
+    /// Process the instructions in a transaction signed by the signers of
+    /// their accounts, and return the error of the instruction that failed.
+    pub fn process_transaction(&mut self, instructions: &[Instruction]) -> ProgramResult {
+        let transaction = self
+            .payers
+            .iter()
+            .map(|payer| self.sign(instructions, payer))
+            .find(|transaction| !self.sent.contains(&transaction.signatures[0]));
+        let transaction = match transaction {
+            Some(transaction) => transaction,
+            None => {
+                self.add_payer();
+                return self.process_transaction(instructions);
+            }
+        };
+        self.sent.insert(transaction.signatures[0]);
+
+        let banks_client = &mut self.context.banks_client;
+        let result = self
+            .runtime
+            .block_on(async { banks_client.process_transaction(transaction).await });
+        match result {
+            Ok(()) => Ok(()),
+            Err(TransportError::TransactionError(TransactionError::InstructionError(_, err))) => {
+                Err(ProgramError::try_from(err).unwrap_or_else(|err| panic!("{}", err)))
+            }
+            Err(err) => panic!("transaction failed: {}", err),
+        }
+    }
+
+    fn sign(&self, instructions: &[Instruction], payer: &Pubkey) -> Transaction {
+        let mut signers = vec![&self.signers[payer]];
+        for meta in instructions.iter().flat_map(|ix| &ix.accounts) {
+            if !meta.is_signer || signers.iter().any(|signer| signer.pubkey() == meta.pubkey) {
+                continue;
+            }
+
+            let signer = self
+                .signers
+                .get(&meta.pubkey)
+                .unwrap_or_else(|| panic!("no keypair for the signer {}", meta.pubkey));
+            signers.push(signer);
+        }
+
+        Transaction::new_signed_with_payer(
+            instructions,
+            Some(payer),
+            &signers,
+            self.context.last_blockhash,
+        )
+    }
+
+    fn add_payer(&mut self) {
+        let payer = self.new_signer();
+        self.fund(&payer, 1_000_000_000);
+        self.payers.push(payer);
+    }
+}
+
+/// Allocate a program owned account for a state of type T.
+pub fn create_state<T: BorshSchema>(bank: &mut Bank) -> Pubkey {
+    bank.create_account(get_packed_len::<T>(), &program_id())
+}
+
+pub fn create_mint(bank: &mut Bank, authority: &Pubkey, decimals: u8) -> Pubkey {
+    bank.create_account_with(spl_token::state::Mint::LEN, &spl_token::id(), |mint| {
+        vec![spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            mint,
+            authority,
+            None,
+            decimals,
+        )
+        .unwrap()]
+    })
+}
+
+pub fn create_token_account(bank: &mut Bank, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+    bank.create_account_with(
+        spl_token::state::Account::LEN,
+        &spl_token::id(),
+        |account| {
+            vec![
+                spl_token::instruction::initialize_account(&spl_token::id(), account, mint, owner)
+                    .unwrap(),
+            ]
+        },
+    )
+}
+
+pub fn mint_to(bank: &mut Bank, mint: &Pubkey, account: &Pubkey, authority: &Pubkey, amount: u64) {
+    bank.process(
+        &spl_token::instruction::mint_to(&spl_token::id(), mint, account, authority, &[], amount)
+            .unwrap(),
+    )
+    .unwrap();
+}
+
+/// A debt type, a vault type priced by a price feed, and an empty vault, with
+/// a user holding collateral tokens.
+pub struct Env {
+    pub bank: Bank,
+
+    // owner of the debt type, price feed and collateral mint
+    pub admin: Pubkey,
+
+    pub user: Pubkey,
+    pub user_collateral: Pubkey,
+    pub user_debt: Pubkey,
+
+    pub debt_token: Pubkey,
+    pub debt_type: Pubkey,
+    pub debt_minter: Pubkey,
+    pub debt_minter_nonce: u8,
+
+    pub collateral_token: Pubkey,
+    pub price_feed: Pubkey,
+    pub vault_type: Pubkey,
+    pub collateral_holder: Pubkey,
+    pub collateral_holder_nonce: u8,
+
+    pub vault: Pubkey,
+}
+
+pub const COLLATERAL_RATIO: u64 = 15_000;
+pub const USER_COLLATERAL: u64 = 10_000;
+
+impl Env {
+    pub fn new() -> Self {
+        Env::with_decimals(0, 0)
+    }
+
+    /// An Env whose collateral and debt token mints have the decimals.
+    pub fn with_decimals(collateral_decimals: u8, debt_decimals: u8) -> Self {
+        Env::start(program_test(), collateral_decimals, debt_decimals)
+    }
+
+    /// An Env on a Bank of the programs of `test`.
+    pub fn start(test: ProgramTest, collateral_decimals: u8, debt_decimals: u8) -> Self {
+        let mut bank = Bank::start(test);
+        let admin = bank.new_signer();
+        let user = bank.new_signer();
+
+        let debt_type = create_state::<DebtType>(&mut bank);
+        let (debt_minter, debt_minter_nonce) =
+            Pubkey::find_program_address(&[&debt_type.to_bytes()[..], b"minter"], &program_id());
+        let debt_token = create_mint(&mut bank, &debt_minter, debt_decimals);
+        bank.process(
+            &instruction::init_debt_type(&program_id(), &debt_type, &debt_token, &admin).unwrap(),
+        )
+        .unwrap();
+
+        let collateral_token = create_mint(&mut bank, &admin, collateral_decimals);
+        let user_collateral = create_token_account(&mut bank, &collateral_token, &user);
+        mint_to(
+            &mut bank,
+            &collateral_token,
+            &user_collateral,
+            &admin,
+            USER_COLLATERAL,
+        );
+        let user_debt = create_token_account(&mut bank, &debt_token, &user);
+
+        // one collateral token is worth 2 debt tokens
+        let price_feed = create_state::<PriceFeed>(&mut bank);
+        bank.process(
+            &instruction::init_price_feed(&program_id(), &price_feed, &collateral_token, &admin, 0)
+                .unwrap(),
+        )
+        .unwrap();
+        bank.process(&instruction::update_price(&program_id(), &price_feed, &admin, 2).unwrap())
+            .unwrap();
+
+        let vault_type = create_state::<VaultType>(&mut bank);
+        let (holder_authority, collateral_holder_nonce) =
+            Pubkey::find_program_address(&[&vault_type.to_bytes()[..], b"holder"], &program_id());
+        let collateral_holder =
+            create_token_account(&mut bank, &collateral_token, &holder_authority);
+        Env::init_vault_type(
+            &mut bank,
+            &vault_type,
+            &debt_type,
+            &admin,
+            &collateral_token,
+            &collateral_holder,
+            &price_feed,
+        );
+
+        let vault = create_state::<Vault>(&mut bank);
+        bank.process(
+            &instruction::init_vault(&program_id(), &vault, &vault_type, &user, None, None)
+                .unwrap(),
+        )
+        .unwrap();
+
+        Env {
+            bank,
+            admin,
+            user,
+            user_collateral,
+            user_debt,
+            debt_token,
+            debt_type,
+            debt_minter,
+            debt_minter_nonce,
+            collateral_token,
+            price_feed,
+            vault_type,
+            collateral_holder,
+            collateral_holder_nonce,
+            vault,
+        }
+    }
+
+    /// Initialize the vault type state, priced by the price feed.
+    pub fn init_vault_type(
+        bank: &mut Bank,
+        vault_type: &Pubkey,
+        debt_type: &Pubkey,
+        debt_type_owner: &Pubkey,
+        collateral_token: &Pubkey,
+        collateral_holder: &Pubkey,
+        price_feed: &Pubkey,
+    ) {
+        bank.process(
+            &instruction::init_vault_type(
+                &program_id(),
+                vault_type,
+                debt_type,
+                debt_type_owner,
+                collateral_token,
+                collateral_holder,
+                price_feed,
+                CollateralPricing::PriceFeed,
+                VaultTypeParams {
+                    collateral_ratio: COLLATERAL_RATIO,
+                    ..VaultTypeParams::default()
+                },
+                false,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    }
+
+    pub fn stake_instruction(&self, amount: u64) -> Instruction {
+        instruction::stake(
+            &program_id(),
+            &self.user_collateral,
+            &self.user,
+            &self.collateral_holder,
+            &self.vault_type,
+            &self.vault,
+            &[],
+            amount,
+            self.collateral_holder_nonce,
+        )
+        .unwrap()
+    }
+
+    pub fn borrow_instruction(&self, amount: u64) -> Instruction {
+        self.borrow_instruction_with(None, None, amount)
+    }
+
+    pub fn borrow_instruction_with(
+        &self,
+        treasury: Option<&Pubkey>,
+        vault_delegate: Option<&Pubkey>,
+        amount: u64,
+    ) -> Instruction {
+        instruction::borrow(
+            &program_id(),
+            &self.debt_token,
+            &self.debt_minter,
+            &self.user_debt,
+            &self.debt_type,
+            &self.vault_type,
+            &self.vault,
+            &self.user,
+            &self.price_feed,
+            treasury,
+            None,
+            None,
+            vault_delegate,
+            amount,
+            self.debt_minter_nonce,
+        )
+        .unwrap()
+    }
+}
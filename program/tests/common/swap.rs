@@ -0,0 +1,218 @@
+//! A swap program for the tests. It trades at the fixed rate of a pool, paying
+//! out of a reserve token account owned by the pool's authority. It also
+//! writes the state of its accounts, e.g. state forged by another program, and
+//! logs messages.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::AccountMeta,
+    instruction::Instruction,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use super::{create_token_account, Bank};
+
+pub static SWAP_PROGRAM_ID: Pubkey = Pubkey::new_from_array([9; 32]);
+
+/// Swap is encoded like `solana_faucet::swap::SwapInstruction::Swap`.
+#[derive(BorshSerialize, BorshDeserialize)]
+enum MockInstruction {
+    Swap {
+        amount_in: u64,
+        minimum_amount_out: u64,
+    },
+    /// Write the data to an account of the program, of the same length.
+    ///
+    /// Accounts:
+    ///
+    /// 0. `[writable]` the account
+    Write {
+        data: Vec<u8>,
+    },
+    Log {
+        message: String,
+    },
+}
+
+pub fn log_instruction(message: &str) -> Instruction {
+    let data = MockInstruction::Log {
+        message: message.to_string(),
+    };
+    Instruction::new_with_bytes(SWAP_PROGRAM_ID, &data.try_to_vec().unwrap(), vec![])
+}
+
+/// Create an account of the mock program holding the data.
+pub fn create_account_with_data(bank: &mut Bank, data: Vec<u8>) -> Pubkey {
+    bank.create_account_with(data.len(), &SWAP_PROGRAM_ID, |key| {
+        vec![Instruction::new_with_bytes(
+            SWAP_PROGRAM_ID,
+            &MockInstruction::Write { data }.try_to_vec().unwrap(),
+            vec![AccountMeta::new(*key, false)],
+        )]
+    })
+}
+
+/// State of a pool: `amount_in` source tokens buy `amount_out` destination
+/// tokens.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct Rate {
+    amount_in: u64,
+    amount_out: u64,
+}
+
+/// A pool and its reserves, passed to the swap instruction as pool accounts.
+pub struct Pool {
+    pub state: Pubkey,
+    pub reserve_in: Pubkey,
+    pub reserve_out: Pubkey,
+    pub authority: Pubkey,
+}
+
+impl Pool {
+    /// Create a pool trading `amount_in` of `mint_in` for `amount_out` of
+    /// `mint_out`, with empty reserves.
+    pub fn create(
+        bank: &mut Bank,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+        amount_in: u64,
+        amount_out: u64,
+    ) -> Self {
+        let rate = Rate {
+            amount_in,
+            amount_out,
+        };
+        let state = create_account_with_data(bank, rate.try_to_vec().unwrap());
+
+        let (authority, _) = Pubkey::find_program_address(&[state.as_ref()], &SWAP_PROGRAM_ID);
+        let reserve_in = create_token_account(bank, mint_in, &authority);
+        let reserve_out = create_token_account(bank, mint_out, &authority);
+
+        Pool {
+            state,
+            reserve_in,
+            reserve_out,
+            authority,
+        }
+    }
+
+    pub fn accounts(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new_readonly(self.state, false),
+            AccountMeta::new(self.reserve_in, false),
+            AccountMeta::new(self.reserve_out, false),
+            AccountMeta::new_readonly(self.authority, false),
+        ]
+    }
+}
+
+pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+    match MockInstruction::try_from_slice(input)
+        .map_err(|_| ProgramError::InvalidInstructionData)?
+    {
+        MockInstruction::Swap {
+            amount_in,
+            minimum_amount_out,
+        } => swap(program_id, accounts, amount_in, minimum_amount_out),
+        MockInstruction::Write { data } => {
+            let account = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if account.owner != program_id {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+
+            let mut account_data = account.data.borrow_mut();
+            if account_data.len() != data.len() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            account_data.copy_from_slice(&data);
+            Ok(())
+        }
+        MockInstruction::Log { message } => {
+            msg!("{}", message);
+            Ok(())
+        }
+    }
+}
+
+fn swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> ProgramResult {
+    let (
+        source,
+        destination,
+        authority,
+        token_program,
+        state,
+        reserve_in,
+        reserve_out,
+        pool_authority,
+    ) = match accounts {
+        [source, destination, authority, token_program, state, reserve_in, reserve_out, pool_authority, ..] => {
+            (
+                source,
+                destination,
+                authority,
+                token_program,
+                state,
+                reserve_in,
+                reserve_out,
+                pool_authority,
+            )
+        }
+        _ => return Err(ProgramError::NotEnoughAccountKeys),
+    };
+
+    if state.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let rate =
+        Rate::try_from_slice(&state.data.borrow()).map_err(|_| ProgramError::InvalidAccountData)?;
+    let amount_out = (amount_in as u128 * rate.amount_out as u128 / rate.amount_in as u128) as u64;
+    if amount_out < minimum_amount_out {
+        return Err(ProgramError::Custom(0));
+    }
+
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            source.key,
+            reserve_in.key,
+            authority.key,
+            &[],
+            amount_in,
+        )?,
+        &[
+            source.clone(),
+            reserve_in.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    let (_, nonce) = Pubkey::find_program_address(&[state.key.as_ref()], program_id);
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            reserve_out.key,
+            destination.key,
+            pool_authority.key,
+            &[],
+            amount_out,
+        )?,
+        &[
+            reserve_out.clone(),
+            destination.clone(),
+            pool_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[state.key.as_ref(), &[nonce]]],
+    )
+}
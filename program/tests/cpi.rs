@@ -0,0 +1,146 @@
+mod common;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use common::{program_id, program_test, with_accounts, Env};
+use solana_faucet::{cpi, error::Error, state::Vault};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use solana_program_test::processor;
+
+static CALLER_ID: Pubkey = Pubkey::new_from_array([8; 32]);
+
+/// The instructions of a program calling the program through the cpi module.
+#[derive(BorshSerialize, BorshDeserialize)]
+enum CallerInstruction {
+    /// Accounts: the program, then those of its Stake instruction.
+    Stake { amount: u64, nonce: u8 },
+    /// Accounts: the program, then those of its Borrow instruction. The vault
+    /// is loaded after the borrow.
+    Borrow { amount: u64, nonce: u8 },
+    /// Load the account as a vault of the program.
+    Load,
+}
+
+fn process_caller(_: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+    let instruction = CallerInstruction::try_from_slice(input)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    with_accounts(&CALLER_ID, accounts, |_, infos| match instruction {
+        CallerInstruction::Stake { amount, nonce } => cpi::stake(
+            &infos[0],
+            cpi::Stake {
+                token_program: &infos[1],
+                collateral_from: &infos[2],
+                collateral_from_authority: &infos[3],
+                collateral_holder: &infos[4],
+                vault_type: &infos[5],
+                vault: &infos[6],
+                native_accounts: &[],
+            },
+            amount,
+            nonce,
+            &[],
+        ),
+        CallerInstruction::Borrow { amount, nonce } => {
+            cpi::borrow(
+                &infos[0],
+                cpi::Borrow {
+                    token_program: &infos[1],
+                    debt_token: &infos[2],
+                    debt_minter: &infos[3],
+                    debt_receiver: &infos[4],
+                    debt_type: &infos[5],
+                    vault_type: &infos[6],
+                    vault: &infos[7],
+                    vault_owner: &infos[8],
+                    price_oracle: &infos[9],
+                    treasury: None,
+                    allowlist: None,
+                    position_account: None,
+                    vault_delegate: None,
+                },
+                amount,
+                nonce,
+                &[],
+            )?;
+
+            cpi::load::<Vault>(infos[0].key, &infos[7]).map(|_| ())
+        }
+        CallerInstruction::Load => cpi::load::<Vault>(&program_id(), &infos[0]).map(|_| ()),
+    })
+}
+
+/// An Env with the calling program.
+fn caller_env() -> Env {
+    let mut test = program_test();
+    test.add_builtin_program("caller", CALLER_ID, processor!(process_caller).unwrap());
+    Env::start(test, 0, 0)
+}
+
+fn caller_instruction(instruction: CallerInstruction, metas: &[AccountMeta]) -> Instruction {
+    Instruction::new_with_bytes(
+        CALLER_ID,
+        &instruction.try_to_vec().unwrap(),
+        metas.to_vec(),
+    )
+}
+
+fn with_program(metas: &[AccountMeta]) -> Vec<AccountMeta> {
+    let mut accounts = vec![AccountMeta::new_readonly(program_id(), false)];
+    accounts.extend_from_slice(metas);
+    accounts
+}
+
+#[test]
+fn test_cpi_stake_and_borrow() {
+    let mut env = caller_env();
+
+    let stake = CallerInstruction::Stake {
+        amount: 1_000,
+        nonce: env.collateral_holder_nonce,
+    };
+    let metas = with_program(&env.stake_instruction(0).accounts);
+    env.bank
+        .process(&caller_instruction(stake, &metas))
+        .unwrap();
+
+    let borrow = CallerInstruction::Borrow {
+        amount: 500,
+        nonce: env.debt_minter_nonce,
+    };
+    let metas = with_program(&env.borrow_instruction(0).accounts);
+    env.bank
+        .process(&caller_instruction(borrow, &metas))
+        .unwrap();
+
+    let vault: Vault = env.bank.load(&env.vault);
+    assert_eq!(vault.collateral_amount, 1_000);
+    assert_eq!(vault.debt_amount, 500);
+    assert_eq!(env.bank.token_balance(&env.user_debt), 500);
+}
+
+#[test]
+fn test_load_checks_owner() {
+    let mut env = caller_env();
+
+    env.bank
+        .process(&caller_instruction(
+            CallerInstruction::Load,
+            &[AccountMeta::new_readonly(env.vault, false)],
+        ))
+        .unwrap();
+
+    // a token account is not a state account of the program
+    assert_eq!(
+        env.bank.process(&caller_instruction(
+            CallerInstruction::Load,
+            &[AccountMeta::new_readonly(env.user_collateral, false)],
+        )),
+        Err(Error::UnexpectedProgramAccount.into())
+    );
+}
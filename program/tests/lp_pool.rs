@@ -0,0 +1,191 @@
+mod common;
+
+use common::{create_mint, create_state, create_token_account, program_id, Env};
+use solana_faucet::{
+    error::Error,
+    instruction,
+    state::{CollateralPricing, LpPool, PriceFeed, Vault, VaultType, VaultTypeParams},
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+
+/// A vault type whose collateral is the lp token of a pool of the collateral
+/// token and a token worth 1, with a vault that staked 100 lp tokens.
+struct LpEnv {
+    env: Env,
+    lp_pool: Pubkey,
+    price_feed_a: Pubkey,
+    price_feed_b: Pubkey,
+}
+
+impl LpEnv {
+    fn new() -> Self {
+        let mut env = Env::new();
+
+        let token_b = create_mint(&mut env.bank, &env.admin, 0);
+        let price_feed_b = create_state::<PriceFeed>(&mut env.bank);
+        env.bank
+            .process(
+                &instruction::init_price_feed(
+                    &program_id(),
+                    &price_feed_b,
+                    &token_b,
+                    &env.admin,
+                    0,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        env.bank
+            .process(
+                &instruction::update_price(&program_id(), &price_feed_b, &env.admin, 1).unwrap(),
+            )
+            .unwrap();
+
+        // 1000 tokens worth 2 and 2000 tokens worth 1 back 1000 lp tokens, so
+        // an lp token is worth 4
+        let lp_pool = create_state::<LpPool>(&mut env.bank);
+        env.bank
+            .process(
+                &instruction::init_lp_pool(
+                    &program_id(),
+                    &lp_pool,
+                    &env.admin,
+                    &env.price_feed,
+                    &price_feed_b,
+                    &env.collateral_token,
+                    &token_b,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        env.bank
+            .process(
+                &instruction::update_lp_pool(
+                    &program_id(),
+                    &lp_pool,
+                    &env.price_feed,
+                    &price_feed_b,
+                    &env.admin,
+                    1_000,
+                    2_000,
+                    1_000,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        // the collateral token stands in for the lp token
+        let vault_type = create_state::<VaultType>(&mut env.bank);
+        let (holder_authority, collateral_holder_nonce) =
+            Pubkey::find_program_address(&[&vault_type.to_bytes()[..], b"holder"], &program_id());
+        let collateral_holder =
+            create_token_account(&mut env.bank, &env.collateral_token, &holder_authority);
+        env.bank
+            .process(
+                &instruction::init_vault_type(
+                    &program_id(),
+                    &vault_type,
+                    &env.debt_type,
+                    &env.admin,
+                    &env.collateral_token,
+                    &collateral_holder,
+                    &lp_pool,
+                    CollateralPricing::LpPool,
+                    VaultTypeParams {
+                        collateral_ratio: 20_000,
+                        ..VaultTypeParams::default()
+                    },
+                    false,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        let vault = create_state::<Vault>(&mut env.bank);
+        env.bank
+            .process(
+                &instruction::init_vault(&program_id(), &vault, &vault_type, &env.user, None, None)
+                    .unwrap(),
+            )
+            .unwrap();
+
+        env.vault_type = vault_type;
+        env.vault = vault;
+        env.collateral_holder = collateral_holder;
+        env.collateral_holder_nonce = collateral_holder_nonce;
+        let price_feed_a = env.price_feed;
+        env.price_feed = lp_pool;
+        env.bank.process(&env.stake_instruction(100)).unwrap();
+
+        LpEnv {
+            env,
+            lp_pool,
+            price_feed_a,
+            price_feed_b,
+        }
+    }
+
+    fn refresh_instruction(&self) -> Instruction {
+        instruction::refresh_lp_pool(
+            &program_id(),
+            &self.lp_pool,
+            &self.price_feed_a,
+            &self.price_feed_b,
+        )
+        .unwrap()
+    }
+}
+
+#[test]
+fn test_borrow_against_lp_tokens() {
+    let mut lp = LpEnv::new();
+
+    // 100 lp tokens are worth 400, which supports 200 debt at 200%
+    lp.env
+        .bank
+        .process(&lp.env.borrow_instruction(200))
+        .unwrap();
+    assert_eq!(lp.env.bank.token_balance(&lp.env.user_debt), 200);
+
+    // the pool value of a past slot is stale
+    let slot = lp.env.bank.slot();
+    lp.env.bank.warp_to_slot(slot + 2);
+    assert_eq!(
+        lp.env.bank.process(&lp.env.borrow_instruction(1)),
+        Err(Error::StaleLpPool.into())
+    );
+
+    // anyone can refresh the pool at the current prices
+    lp.env
+        .bank
+        .process(
+            &instruction::update_price(&program_id(), &lp.price_feed_a, &lp.env.admin, 4).unwrap(),
+        )
+        .unwrap();
+    lp.env.bank.process(&lp.refresh_instruction()).unwrap();
+    lp.env.bank.process(&lp.env.borrow_instruction(1)).unwrap();
+    assert_eq!(lp.env.bank.token_balance(&lp.env.user_debt), 201);
+}
+
+#[test]
+fn test_init_lp_pool_checks_price_feed_tokens() {
+    let mut env = Env::new();
+    let token_b = create_mint(&mut env.bank, &env.admin, 0);
+
+    // the price feed of the collateral token can't price token b
+    let lp_pool = create_state::<LpPool>(&mut env.bank);
+    let instruction = instruction::init_lp_pool(
+        &program_id(),
+        &lp_pool,
+        &env.admin,
+        &env.price_feed,
+        &env.price_feed,
+        &env.collateral_token,
+        &token_b,
+    )
+    .unwrap();
+    assert_eq!(
+        env.bank.process(&instruction),
+        Err(Error::PriceFeedTokenMismatch.into())
+    );
+}
@@ -0,0 +1,351 @@
+mod common;
+
+use common::{
+    create_state, create_token_account, program_id, swap::create_account_with_data, Env,
+    COLLATERAL_RATIO,
+};
+use solana_faucet::{
+    error::Error,
+    instruction::{self, LiquidateMultiCollateral},
+    state::{CollateralBalance, DebtType, MultiVault, Treasury, VaultType, VaultTypeParams},
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// An Env with a second vault type of the same collateral, and a multi vault
+/// that staked 500 collateral into each.
+struct MultiEnv {
+    env: Env,
+    vault_type_b: Pubkey,
+    collateral_holder_b: Pubkey,
+    collateral_holder_nonce_b: u8,
+    multi_vault: Pubkey,
+    treasury: Pubkey,
+}
+
+fn update_params(env: &mut Env, vault_type: &Pubkey, params: VaultTypeParams) {
+    env.bank
+        .process(
+            &instruction::update_vault_type_params(
+                &program_id(),
+                vault_type,
+                &env.debt_type,
+                &env.admin,
+                params,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+}
+
+fn set_origination_fee(env: &mut Env, vault_type: &Pubkey, origination_fee: u64) {
+    update_params(
+        env,
+        vault_type,
+        VaultTypeParams {
+            collateral_ratio: COLLATERAL_RATIO,
+            origination_fee,
+            ..VaultTypeParams::default()
+        },
+    );
+}
+
+fn setup() -> MultiEnv {
+    let mut env = Env::new();
+
+    let vault_type_b = create_state::<VaultType>(&mut env.bank);
+    let (holder_authority, holder_nonce_b) =
+        Pubkey::find_program_address(&[&vault_type_b.to_bytes()[..], b"holder"], &program_id());
+    let collateral_holder_b =
+        create_token_account(&mut env.bank, &env.collateral_token, &holder_authority);
+    Env::init_vault_type(
+        &mut env.bank,
+        &vault_type_b,
+        &env.debt_type,
+        &env.admin,
+        &env.collateral_token,
+        &collateral_holder_b,
+        &env.price_feed,
+    );
+
+    let treasury = create_state::<Treasury>(&mut env.bank);
+    env.bank
+        .process(
+            &instruction::init_treasury(
+                &program_id(),
+                &treasury,
+                &env.debt_type,
+                &env.admin,
+                &Pubkey::new_unique(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    let multi_vault = create_state::<MultiVault>(&mut env.bank);
+    env.bank
+        .process(
+            &instruction::init_multi_vault(
+                &program_id(),
+                &multi_vault,
+                &env.debt_type,
+                &env.user,
+                None,
+                None,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    for (vault_type, holder, nonce) in &[
+        (
+            env.vault_type,
+            env.collateral_holder,
+            env.collateral_holder_nonce,
+        ),
+        (vault_type_b, collateral_holder_b, holder_nonce_b),
+    ] {
+        let instruction = instruction::stake_multi(
+            &program_id(),
+            &env.user_collateral,
+            &env.user,
+            holder,
+            vault_type,
+            &multi_vault,
+            None,
+            500,
+            *nonce,
+        )
+        .unwrap();
+        env.bank.process(&instruction).unwrap();
+    }
+
+    MultiEnv {
+        env,
+        vault_type_b,
+        collateral_holder_b,
+        collateral_holder_nonce_b: holder_nonce_b,
+        multi_vault,
+        treasury,
+    }
+}
+
+fn borrow_multi_instruction(m: &MultiEnv, treasury: Option<&Pubkey>, amount: u64) -> Instruction {
+    let env = &m.env;
+    instruction::borrow_multi(
+        &program_id(),
+        &env.debt_token,
+        &env.debt_minter,
+        &env.user_debt,
+        &env.debt_type,
+        &m.multi_vault,
+        &env.user,
+        &[
+            AccountMeta::new_readonly(env.vault_type, false),
+            AccountMeta::new_readonly(env.price_feed, false),
+            AccountMeta::new_readonly(m.vault_type_b, false),
+            AccountMeta::new_readonly(env.price_feed, false),
+        ],
+        treasury,
+        &[],
+        amount,
+        env.debt_minter_nonce,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_borrow_multi() {
+    let mut m = setup();
+    assert_eq!(m.env.bank.token_balance(&m.collateral_holder_b), 500);
+
+    // without a fee, the treasury isn't needed
+    let instruction = borrow_multi_instruction(&m, None, 1_000);
+    m.env.bank.process(&instruction).unwrap();
+
+    let vault: MultiVault = m.env.bank.load(&m.multi_vault);
+    assert_eq!(vault.debt_amount, 1_000);
+    assert_eq!(m.env.bank.token_balance(&m.env.user_debt), 1_000);
+}
+
+#[test]
+fn test_borrow_multi_origination_fee() {
+    let mut m = setup();
+    let vault_type_a = m.env.vault_type;
+    let vault_type_b = m.vault_type_b;
+    set_origination_fee(&mut m.env, &vault_type_a, 100);
+    set_origination_fee(&mut m.env, &vault_type_b, 200);
+
+    let instruction = borrow_multi_instruction(&m, None, 1_000);
+    assert_eq!(
+        m.env.bank.process(&instruction),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+
+    // the highest fee of the collaterals applies
+    let instruction = borrow_multi_instruction(&m, Some(&m.treasury), 1_000);
+    m.env.bank.process(&instruction).unwrap();
+
+    let vault: MultiVault = m.env.bank.load(&m.multi_vault);
+    assert_eq!(vault.debt_amount, 1_020);
+    assert_eq!(m.env.bank.token_balance(&m.env.user_debt), 1_000);
+    let treasury: Treasury = m.env.bank.load(&m.treasury);
+    assert_eq!(treasury.surplus, 20);
+}
+
+#[test]
+fn test_init_multi_vault_checks_debt_type() {
+    let mut env = Env::new();
+
+    let user = env.user;
+    let init = |multi_vault: &Pubkey, debt_type: &Pubkey| {
+        instruction::init_multi_vault(&program_id(), multi_vault, debt_type, &user, None, None)
+            .unwrap()
+    };
+
+    // a debt type not owned by the program
+    let forged = create_account_with_data(&mut env.bank, vec![0; 1]);
+    let multi_vault = create_state::<MultiVault>(&mut env.bank);
+    assert_eq!(
+        env.bank.process(&init(&multi_vault, &forged)),
+        Err(Error::UnexpectedProgramAccount.into())
+    );
+
+    let uninitialized = create_state::<DebtType>(&mut env.bank);
+    assert_eq!(
+        env.bank.process(&init(&multi_vault, &uninitialized)),
+        Err(ProgramError::UninitializedAccount)
+    );
+
+    let debt_type = env.debt_type;
+    env.bank.process(&init(&multi_vault, &debt_type)).unwrap();
+}
+
+#[test]
+fn test_repay_multi() {
+    let mut m = setup();
+    let instruction = borrow_multi_instruction(&m, None, 1_000);
+    m.env.bank.process(&instruction).unwrap();
+
+    let repay = |m: &MultiEnv, amount| {
+        instruction::repay_multi(
+            &program_id(),
+            &m.env.debt_token,
+            &m.env.user_debt,
+            &m.env.user,
+            &m.env.debt_type,
+            &m.multi_vault,
+            amount,
+        )
+        .unwrap()
+    };
+
+    m.env.bank.process(&repay(&m, 400)).unwrap();
+
+    let vault: MultiVault = m.env.bank.load(&m.multi_vault);
+    assert_eq!(vault.debt_amount, 600);
+    assert_eq!(m.env.bank.token_balance(&m.env.user_debt), 600);
+
+    let instruction = repay(&m, 601);
+    assert_eq!(
+        m.env.bank.process(&instruction),
+        Err(Error::RepayExceedsDebt.into())
+    );
+}
+
+/// The user liquidates their own multi vault.
+fn liquidate_multi_instruction(m: &MultiEnv) -> Instruction {
+    let env = &m.env;
+    let holder_authority = |vault_type: &Pubkey| {
+        Pubkey::find_program_address(&[&vault_type.to_bytes()[..], b"holder"], &program_id()).0
+    };
+    let (authority_a, authority_b) = (
+        holder_authority(&env.vault_type),
+        holder_authority(&m.vault_type_b),
+    );
+
+    instruction::liquidate_multi(
+        &program_id(),
+        &env.debt_token,
+        &env.user_debt,
+        &env.user,
+        &m.treasury,
+        &env.debt_type,
+        &m.multi_vault,
+        &[
+            LiquidateMultiCollateral {
+                vault_type: &env.vault_type,
+                price_oracle: &env.price_feed,
+                collateral_holder: &env.collateral_holder,
+                collateral_holder_authority: &authority_a,
+                collateral_to: &env.user_collateral,
+            },
+            LiquidateMultiCollateral {
+                vault_type: &m.vault_type_b,
+                price_oracle: &env.price_feed,
+                collateral_holder: &m.collateral_holder_b,
+                collateral_holder_authority: &authority_b,
+                collateral_to: &env.user_collateral,
+            },
+        ],
+        [
+            env.collateral_holder_nonce,
+            m.collateral_holder_nonce_b,
+            0,
+            0,
+        ],
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_liquidate_multi() {
+    let mut m = setup();
+    // the 2000 the collaterals are worth back 1333 of debt together, though
+    // neither could back 1000 alone
+    let instruction = borrow_multi_instruction(&m, None, 1_000);
+    m.env.bank.process(&instruction).unwrap();
+
+    // and 200 more debt tokens from the single vault, to pay the penalty
+    m.env.bank.process(&m.env.stake_instruction(1_000)).unwrap();
+    m.env.bank.process(&m.env.borrow_instruction(200)).unwrap();
+
+    let instruction = liquidate_multi_instruction(&m);
+    assert_eq!(
+        m.env.bank.process(&instruction),
+        Err(Error::VaultHealthy.into())
+    );
+
+    // the collaterals now back 500 together, and the higher penalty is 10%
+    let vault_type_a = m.env.vault_type;
+    let vault_type_b = m.vault_type_b;
+    for (vault_type, liquidation_penalty) in &[(vault_type_a, 500), (vault_type_b, 1_000)] {
+        update_params(
+            &mut m.env,
+            vault_type,
+            VaultTypeParams {
+                collateral_ratio: 40_000,
+                liquidation_penalty: *liquidation_penalty,
+                ..VaultTypeParams::default()
+            },
+        );
+    }
+
+    let instruction = liquidate_multi_instruction(&m);
+    m.env.bank.process(&instruction).unwrap();
+
+    // 1100 of debt and penalty is worth all of the first collateral and 50 of
+    // the second, and the owner keeps the other 450
+    let vault: MultiVault = m.env.bank.load(&m.multi_vault);
+    assert_eq!(vault.debt_amount, 0);
+    assert_eq!(vault.collaterals[0], CollateralBalance::default());
+    assert_eq!(vault.collaterals[1].amount, 450);
+    assert_eq!(m.env.bank.token_balance(&m.collateral_holder_b), 450);
+    assert_eq!(m.env.bank.token_balance(&m.env.user_debt), 100);
+
+    let treasury: Treasury = m.env.bank.load(&m.treasury);
+    assert_eq!(treasury.surplus, 100);
+}
@@ -0,0 +1,175 @@
+// The program wraps lamports into a token account it allocates, which the
+// native runtime of solana-program-test can't resize. Run with
+// `cargo test-bpf`.
+#![cfg(feature = "test-bpf")]
+
+mod common;
+
+use common::{create_state, create_token_account, program_id, Env};
+use solana_faucet::{
+    instruction,
+    state::{CollateralPricing, Vault, VaultType, VaultTypeParams},
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction::SystemError,
+    system_program, sysvar,
+};
+use spl_token::native_mint;
+
+const USER_LAMPORTS: u64 = 1_000_000_000;
+
+/// A vault type of native SOL, and a vault of the user, who holds lamports.
+struct NativeEnv {
+    env: Env,
+    vault_type: Pubkey,
+    holder: Pubkey,
+    holder_authority: Pubkey,
+    holder_nonce: u8,
+    vault: Pubkey,
+}
+
+fn setup() -> NativeEnv {
+    let mut env = Env::new();
+
+    let vault_type = create_state::<VaultType>(&mut env.bank);
+    let (holder_authority, holder_nonce) =
+        Pubkey::find_program_address(&[&vault_type.to_bytes()[..], b"holder"], &program_id());
+    let holder = create_token_account(&mut env.bank, &native_mint::id(), &holder_authority);
+    env.bank
+        .process(
+            &instruction::init_vault_type(
+                &program_id(),
+                &vault_type,
+                &env.debt_type,
+                &env.admin,
+                &native_mint::id(),
+                &holder,
+                &env.price_feed,
+                CollateralPricing::PriceFeed,
+                VaultTypeParams {
+                    collateral_ratio: common::COLLATERAL_RATIO,
+                    ..VaultTypeParams::default()
+                },
+                true,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    let vault = create_state::<Vault>(&mut env.bank);
+    env.bank
+        .process(
+            &instruction::init_vault(&program_id(), &vault, &vault_type, &env.user, None, None)
+                .unwrap(),
+        )
+        .unwrap();
+
+    env.bank.fund(&env.user, USER_LAMPORTS);
+
+    NativeEnv {
+        env,
+        vault_type,
+        holder,
+        holder_authority,
+        holder_nonce,
+        vault,
+    }
+}
+
+fn native_accounts(n: &NativeEnv) -> Vec<AccountMeta> {
+    let (wrap_account, _) =
+        Pubkey::find_program_address(&[&n.vault_type.to_bytes()[..], b"wrap"], &program_id());
+    vec![
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(native_mint::id(), false),
+        AccountMeta::new(wrap_account, false),
+    ]
+}
+
+fn stake_instruction(n: &NativeEnv, amount: u64) -> Instruction {
+    // the collateral comes out of the signer's lamports, not a token account
+    instruction::stake(
+        &program_id(),
+        &n.env.user_collateral,
+        &n.env.user,
+        &n.holder,
+        &n.vault_type,
+        &n.vault,
+        &native_accounts(n),
+        amount,
+        n.holder_nonce,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_stake_and_unstake_native() {
+    let mut n = setup();
+    let holder_lamports = n.env.bank.lamports(&n.holder);
+
+    n.env.bank.process(&stake_instruction(&n, 1_000)).unwrap();
+
+    // the rent of the temporary wrap account goes back to the user
+    assert_eq!(n.env.bank.token_balance(&n.holder), 1_000);
+    assert_eq!(n.env.bank.lamports(&n.holder), holder_lamports + 1_000);
+    assert_eq!(n.env.bank.lamports(&n.env.user), USER_LAMPORTS - 1_000);
+    assert_eq!(n.env.bank.lamports(&native_accounts(&n)[3].pubkey), 0);
+    let vault: Vault = n.env.bank.load(&n.vault);
+    assert_eq!(vault.collateral_amount, 1_000);
+
+    // unwrapped to a new system account, with the rent the user paid for the
+    // wrap account
+    let receiver = Pubkey::new_unique();
+    let instruction = instruction::unstake(
+        &program_id(),
+        &n.holder,
+        &n.holder_authority,
+        &receiver,
+        &n.vault_type,
+        &n.vault,
+        &n.env.user,
+        &n.env.price_feed,
+        &native_accounts(&n),
+        None,
+        None,
+        400,
+        n.holder_nonce,
+    )
+    .unwrap();
+    n.env.bank.process(&instruction).unwrap();
+
+    let rent = n
+        .env
+        .bank
+        .rent
+        .minimum_balance(spl_token::state::Account::LEN);
+    assert_eq!(n.env.bank.token_balance(&n.holder), 600);
+    assert_eq!(n.env.bank.lamports(&receiver), 400 + rent);
+    assert_eq!(
+        n.env.bank.lamports(&n.env.user),
+        USER_LAMPORTS - 1_000 - rent
+    );
+    let vault: Vault = n.env.bank.load(&n.vault);
+    assert_eq!(vault.collateral_amount, 600);
+}
+
+#[test]
+fn test_stake_native_insufficient_lamports() {
+    let mut n = setup();
+
+    // the system program rejects the transfer, and nothing changes
+    let instruction = stake_instruction(&n, USER_LAMPORTS);
+    assert_eq!(
+        n.env.bank.process(&instruction),
+        Err(ProgramError::Custom(
+            SystemError::ResultWithNegativeLamports as u32
+        ))
+    );
+    assert_eq!(n.env.bank.lamports(&n.env.user), USER_LAMPORTS);
+    assert_eq!(n.env.bank.token_balance(&n.holder), 0);
+}
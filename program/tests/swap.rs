@@ -0,0 +1,358 @@
+mod common;
+
+use common::{
+    create_state, create_token_account, mint_to, program_id,
+    swap::{Pool, SWAP_PROGRAM_ID},
+    Env, COLLATERAL_RATIO,
+};
+use solana_faucet::{
+    error::Error,
+    instruction,
+    state::{delegate_permission, Vault, VaultDelegate, VaultType, VaultTypeParams},
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+
+/// An Env whose vault type trades through the mock swap program, with a vault
+/// that staked 1000 collateral.
+fn swap_env() -> Env {
+    let mut env = Env::new();
+
+    env.bank
+        .process(
+            &instruction::update_vault_type_params(
+                &program_id(),
+                &env.vault_type,
+                &env.debt_type,
+                &env.admin,
+                VaultTypeParams {
+                    collateral_ratio: COLLATERAL_RATIO,
+                    swap_program: (&SWAP_PROGRAM_ID).into(),
+                    ..VaultTypeParams::default()
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+    env
+}
+
+/// A swap Env whose vault borrowed 1333 debt.
+fn setup() -> Env {
+    let mut env = swap_env();
+    env.bank.process(&env.borrow_instruction(1_333)).unwrap();
+    env
+}
+
+/// Borrow `amount` debt tokens into the account, against collateral of the
+/// admin in a vault type of its own.
+fn borrow_into(env: &mut Env, account: &Pubkey, amount: u64) {
+    let vault_type = create_state::<VaultType>(&mut env.bank);
+    let (holder_authority, holder_nonce) =
+        Pubkey::find_program_address(&[&vault_type.to_bytes()[..], b"holder"], &program_id());
+    let holder = create_token_account(&mut env.bank, &env.collateral_token, &holder_authority);
+    Env::init_vault_type(
+        &mut env.bank,
+        &vault_type,
+        &env.debt_type,
+        &env.admin,
+        &env.collateral_token,
+        &holder,
+        &env.price_feed,
+    );
+
+    let vault = create_state::<Vault>(&mut env.bank);
+    env.bank
+        .process(
+            &instruction::init_vault(&program_id(), &vault, &vault_type, &env.admin, None, None)
+                .unwrap(),
+        )
+        .unwrap();
+
+    // the collateral is worth twice the debt
+    let collateral = create_token_account(&mut env.bank, &env.collateral_token, &env.admin);
+    mint_to(
+        &mut env.bank,
+        &env.collateral_token,
+        &collateral,
+        &env.admin,
+        amount,
+    );
+    env.bank
+        .process(
+            &instruction::stake(
+                &program_id(),
+                &collateral,
+                &env.admin,
+                &holder,
+                &vault_type,
+                &vault,
+                &[],
+                amount,
+                holder_nonce,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    env.bank
+        .process(
+            &instruction::borrow(
+                &program_id(),
+                &env.debt_token,
+                &env.debt_minter,
+                account,
+                &env.debt_type,
+                &vault_type,
+                &vault,
+                &env.admin,
+                &env.price_feed,
+                None,
+                None,
+                None,
+                None,
+                amount,
+                env.debt_minter_nonce,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+}
+
+/// A pool buying collateral for debt tokens at the rate.
+fn collateral_pool(env: &mut Env, amount_in: u64, amount_out: u64) -> Pool {
+    let pool = Pool::create(
+        &mut env.bank,
+        &env.collateral_token,
+        &env.debt_token,
+        amount_in,
+        amount_out,
+    );
+    borrow_into(env, &pool.reserve_out, 10_000);
+    pool
+}
+
+/// A pool buying debt tokens for collateral at the rate.
+fn debt_pool(env: &mut Env, amount_in: u64, amount_out: u64) -> Pool {
+    let pool = Pool::create(
+        &mut env.bank,
+        &env.debt_token,
+        &env.collateral_token,
+        amount_in,
+        amount_out,
+    );
+    mint_to(
+        &mut env.bank,
+        &env.collateral_token,
+        &pool.reserve_out,
+        &env.admin,
+        10_000,
+    );
+    pool
+}
+
+/// A debt token account owned by the leverage authority of the vault type.
+fn leverage_account(env: &mut Env) -> Pubkey {
+    let authority = instruction::leverage_authority(&program_id(), &env.vault_type);
+    create_token_account(&mut env.bank, &env.debt_token, &authority)
+}
+
+fn leverage_instruction(
+    env: &Env,
+    pool: &Pool,
+    leverage_account: &Pubkey,
+    signer: &Pubkey,
+    vault_delegate: Option<&Pubkey>,
+    amount: u64,
+    minimum: u64,
+) -> Instruction {
+    instruction::leverage(
+        &program_id(),
+        &SWAP_PROGRAM_ID,
+        &env.debt_token,
+        &env.debt_minter,
+        leverage_account,
+        &env.collateral_holder,
+        &env.debt_type,
+        &env.vault_type,
+        &env.vault,
+        signer,
+        &env.price_feed,
+        None,
+        None,
+        None,
+        vault_delegate,
+        &pool.accounts(),
+        amount,
+        minimum,
+        env.debt_minter_nonce,
+        env.collateral_holder_nonce,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_leverage() {
+    let mut env = swap_env();
+    // the oracle price, 2 debt tokens per collateral
+    let pool = debt_pool(&mut env, 2, 1);
+    let leverage_account = leverage_account(&mut env);
+
+    let instruction =
+        leverage_instruction(&env, &pool, &leverage_account, &env.user, None, 600, 300);
+    env.bank.process(&instruction).unwrap();
+
+    let vault: Vault = env.bank.load(&env.vault);
+    assert_eq!(vault.collateral_amount, 1_300);
+    assert_eq!(vault.debt_amount, 600);
+    assert_eq!(env.bank.token_balance(&env.collateral_holder), 1_300);
+    assert_eq!(env.bank.token_balance(&leverage_account), 0);
+    assert_eq!(env.bank.token_balance(&pool.reserve_in), 600);
+}
+
+#[test]
+fn test_leverage_by_delegate() {
+    let mut env = swap_env();
+    let pool = debt_pool(&mut env, 2, 1);
+    let leverage_account = leverage_account(&mut env);
+
+    let delegate = env.bank.new_signer();
+    let vault_delegate = create_state::<VaultDelegate>(&mut env.bank);
+    env.bank
+        .process(
+            &instruction::set_vault_delegate(
+                &program_id(),
+                &vault_delegate,
+                &env.vault,
+                &env.user,
+                None,
+                &delegate,
+                delegate_permission::BORROW,
+                1_000,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    let instruction = leverage_instruction(
+        &env,
+        &pool,
+        &leverage_account,
+        &delegate,
+        Some(&vault_delegate),
+        600,
+        300,
+    );
+    env.bank.process(&instruction).unwrap();
+
+    let vault: Vault = env.bank.load(&env.vault);
+    assert_eq!(vault.collateral_amount, 1_300);
+    assert_eq!(vault.debt_amount, 600);
+
+    let vault_delegate: VaultDelegate = env.bank.load(&vault_delegate);
+    assert_eq!(vault_delegate.borrowed_amount, 600);
+}
+
+#[test]
+fn test_leverage_account_not_program_owned() {
+    let mut env = swap_env();
+    let pool = debt_pool(&mut env, 2, 1);
+
+    let instruction = leverage_instruction(&env, &pool, &env.user_debt, &env.user, None, 600, 300);
+    assert_eq!(
+        env.bank.process(&instruction),
+        Err(Error::OwnerMismatch.into())
+    );
+}
+
+#[test]
+fn test_leverage_unhealthy() {
+    let mut env = swap_env();
+    // buying at half the oracle price doesn't back the debt
+    let pool = debt_pool(&mut env, 4, 1);
+    let leverage_account = leverage_account(&mut env);
+
+    let instruction =
+        leverage_instruction(&env, &pool, &leverage_account, &env.user, None, 2_400, 600);
+    assert_eq!(
+        env.bank.process(&instruction),
+        Err(Error::CollateralRatioTooLow.into())
+    );
+
+    let vault: Vault = env.bank.load(&env.vault);
+    assert_eq!(vault.collateral_amount, 1_000);
+    assert_eq!(vault.debt_amount, 0);
+}
+
+fn deleverage_instruction(env: &Env, pool: &Pool, amount: u64, minimum: u64) -> Instruction {
+    let (holder_authority, _) =
+        Pubkey::find_program_address(&[&env.vault_type.to_bytes()[..], b"holder"], &program_id());
+
+    instruction::deleverage(
+        &program_id(),
+        &SWAP_PROGRAM_ID,
+        &env.collateral_holder,
+        &holder_authority,
+        &env.debt_token,
+        &env.user_debt,
+        &env.debt_type,
+        &env.vault_type,
+        &env.vault,
+        &env.user,
+        &env.price_feed,
+        None,
+        None,
+        &pool.accounts(),
+        amount,
+        minimum,
+        env.collateral_holder_nonce,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_deleverage() {
+    let mut env = setup();
+    // the oracle price, 2 debt tokens per collateral
+    let pool = collateral_pool(&mut env, 1, 2);
+
+    env.bank
+        .process(&deleverage_instruction(&env, &pool, 500, 1_000))
+        .unwrap();
+
+    let vault: Vault = env.bank.load(&env.vault);
+    assert_eq!(vault.collateral_amount, 500);
+    assert_eq!(vault.debt_amount, 333);
+    assert_eq!(env.bank.token_balance(&env.collateral_holder), 500);
+    // the proceeds repaid the debt
+    assert_eq!(env.bank.token_balance(&env.user_debt), 1_333);
+    assert_eq!(env.bank.token_balance(&pool.reserve_in), 500);
+}
+
+#[test]
+fn test_deleverage_not_healthier() {
+    let mut env = setup();
+    // selling at half the oracle price leaves the vault less collateralized
+    let pool = collateral_pool(&mut env, 1, 1);
+
+    assert_eq!(
+        env.bank
+            .process(&deleverage_instruction(&env, &pool, 500, 500)),
+        Err(Error::DeleverageNotHealthier.into())
+    );
+
+    let vault: Vault = env.bank.load(&env.vault);
+    assert_eq!(vault.collateral_amount, 1_000);
+    assert_eq!(vault.debt_amount, 1_333);
+}
+
+#[test]
+fn test_deleverage_swap_result_below_minimum() {
+    let mut env = setup();
+    let pool = collateral_pool(&mut env, 1, 2);
+
+    assert!(env
+        .bank
+        .process(&deleverage_instruction(&env, &pool, 500, 1_001))
+        .is_err());
+}
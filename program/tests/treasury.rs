@@ -0,0 +1,342 @@
+mod common;
+
+use common::{create_mint, create_state, create_token_account, program_id, Env, COLLATERAL_RATIO};
+use solana_faucet::{
+    error::Error,
+    instruction,
+    state::{Auction, AuctionKind, AuctionRefund, Treasury, Vault, VaultTypeParams},
+};
+use solana_program::{instruction::Instruction, program_error::ProgramError, pubkey::Pubkey};
+
+/// An Env charging a 10% liquidation penalty, with a treasury, and a vault
+/// that staked 1000 collateral and borrowed 1333 debt.
+struct TreasuryEnv {
+    env: Env,
+    treasury: Pubkey,
+    governance_token: Pubkey,
+}
+
+fn setup() -> TreasuryEnv {
+    let mut env = Env::new();
+
+    env.bank
+        .process(
+            &instruction::update_vault_type_params(
+                &program_id(),
+                &env.vault_type,
+                &env.debt_type,
+                &env.admin,
+                VaultTypeParams {
+                    collateral_ratio: COLLATERAL_RATIO,
+                    liquidation_penalty: 1_000,
+                    ..VaultTypeParams::default()
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    let governance_token = create_mint(&mut env.bank, &env.debt_minter, 0);
+    let treasury = create_state::<Treasury>(&mut env.bank);
+    env.bank
+        .process(
+            &instruction::init_treasury(
+                &program_id(),
+                &treasury,
+                &env.debt_type,
+                &env.admin,
+                &governance_token,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+    env.bank.process(&env.borrow_instruction(1_333)).unwrap();
+
+    TreasuryEnv {
+        env,
+        treasury,
+        governance_token,
+    }
+}
+
+/// The user liquidates their own vault.
+fn liquidate_instruction(t: &TreasuryEnv) -> Instruction {
+    let env = &t.env;
+    let (holder_authority, _) =
+        Pubkey::find_program_address(&[&env.vault_type.to_bytes()[..], b"holder"], &program_id());
+
+    instruction::liquidate(
+        &program_id(),
+        &env.debt_token,
+        &env.user_debt,
+        &env.user,
+        &env.collateral_holder,
+        &holder_authority,
+        &env.user_collateral,
+        &t.treasury,
+        &env.debt_type,
+        &env.vault_type,
+        &env.vault,
+        &env.price_feed,
+        env.collateral_holder_nonce,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_liquidate_healthy_vault() {
+    let mut t = setup();
+
+    let instruction = liquidate_instruction(&t);
+    assert_eq!(
+        t.env.bank.process(&instruction),
+        Err(Error::VaultHealthy.into())
+    );
+}
+
+#[test]
+fn test_liquidate() {
+    let mut t = setup();
+    // the collateral is now worth 1000, less than the debt
+    t.env
+        .bank
+        .process(
+            &instruction::update_price(&program_id(), &t.env.price_feed, &t.env.admin, 1).unwrap(),
+        )
+        .unwrap();
+
+    let instruction = liquidate_instruction(&t);
+    t.env.bank.process(&instruction).unwrap();
+
+    // the penalty of 134 adds to the debt, 1000 of which the collateral covers
+    let vault: Vault = t.env.bank.load(&t.env.vault);
+    assert_eq!(vault.collateral_amount, 0);
+    assert_eq!(vault.debt_amount, 467);
+    assert_eq!(t.env.bank.token_balance(&t.env.collateral_holder), 0);
+    assert_eq!(t.env.bank.token_balance(&t.env.user_collateral), 10_000);
+    assert_eq!(t.env.bank.token_balance(&t.env.user_debt), 333);
+
+    let treasury: Treasury = t.env.bank.load(&t.treasury);
+    assert_eq!(treasury.surplus, 134);
+
+    // the shortfall nets against the penalty
+    t.env
+        .bank
+        .process(
+            &instruction::write_off_vault(
+                &program_id(),
+                &t.treasury,
+                &t.env.debt_type,
+                &t.env.vault_type,
+                &t.env.vault,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    let treasury: Treasury = t.env.bank.load(&t.treasury);
+    assert_eq!(treasury.surplus, 0);
+    assert_eq!(treasury.bad_debt, 333);
+}
+
+#[test]
+fn test_liquidate_leaves_excess_collateral() {
+    let mut t = setup();
+    // the 2000 the collateral is worth backs no more than 1000 of debt, and
+    // without a penalty the user can repay all of theirs
+    t.env
+        .bank
+        .process(
+            &instruction::update_vault_type_params(
+                &program_id(),
+                &t.env.vault_type,
+                &t.env.debt_type,
+                &t.env.admin,
+                VaultTypeParams {
+                    collateral_ratio: 20_000,
+                    ..VaultTypeParams::default()
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    let instruction = liquidate_instruction(&t);
+    t.env.bank.process(&instruction).unwrap();
+
+    // the debt of 1333 is worth 667 collateral, and the owner keeps the other
+    // 333
+    let vault: Vault = t.env.bank.load(&t.env.vault);
+    assert_eq!(vault.collateral_amount, 333);
+    assert_eq!(vault.debt_amount, 0);
+    assert_eq!(t.env.bank.token_balance(&t.env.collateral_holder), 333);
+    assert_eq!(t.env.bank.token_balance(&t.env.user_collateral), 9_667);
+    assert_eq!(t.env.bank.token_balance(&t.env.user_debt), 0);
+}
+
+/// A debt auction raising the 333 of debt written off the liquidated vault.
+struct DebtAuction {
+    auction: Pubkey,
+    escrow: Pubkey,
+    auction_authority: Pubkey,
+    auction_authority_nonce: u8,
+}
+
+fn start_debt_auction(t: &mut TreasuryEnv) -> DebtAuction {
+    t.env
+        .bank
+        .process(
+            &instruction::update_price(&program_id(), &t.env.price_feed, &t.env.admin, 1).unwrap(),
+        )
+        .unwrap();
+    t.env.bank.process(&liquidate_instruction(t)).unwrap();
+    t.env
+        .bank
+        .process(
+            &instruction::write_off_vault(
+                &program_id(),
+                &t.treasury,
+                &t.env.debt_type,
+                &t.env.vault_type,
+                &t.env.vault,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    let auction = create_state::<Auction>(&mut t.env.bank);
+    let (auction_authority, auction_authority_nonce) =
+        Pubkey::find_program_address(&[&auction.to_bytes()[..], b"auction"], &program_id());
+    let escrow = create_token_account(&mut t.env.bank, &t.env.debt_token, &auction_authority);
+    t.env
+        .bank
+        .process(
+            &instruction::start_auction(
+                &program_id(),
+                &auction,
+                &t.treasury,
+                &t.env.debt_type,
+                &t.env.admin,
+                &escrow,
+                AuctionKind::Debt,
+                333,
+                1_000,
+                auction_authority_nonce,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    DebtAuction {
+        auction,
+        escrow,
+        auction_authority,
+        auction_authority_nonce,
+    }
+}
+
+/// The user bids out of their debt token account.
+fn bid_instruction(
+    t: &TreasuryEnv,
+    a: &DebtAuction,
+    payout: &Pubkey,
+    refund: Option<&Pubkey>,
+    gov_amount: u64,
+) -> Instruction {
+    instruction::bid(
+        &program_id(),
+        &a.auction,
+        &a.escrow,
+        &a.auction_authority,
+        &t.env.user_debt,
+        &t.env.user,
+        payout,
+        refund,
+        gov_amount,
+        a.auction_authority_nonce,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_bid_payout_token() {
+    let mut t = setup();
+    let a = start_debt_auction(&mut t);
+
+    let payout = create_token_account(&mut t.env.bank, &t.governance_token, &t.env.user);
+    let wrong_token = bid_instruction(&t, &a, &t.env.user_debt, None, 900);
+    let instruction = bid_instruction(&t, &a, &payout, None, 900);
+
+    // the lot is minted in governance tokens
+    assert_eq!(
+        t.env.bank.process(&wrong_token),
+        Err(Error::InvalidGovernanceToken.into())
+    );
+
+    t.env.bank.process(&instruction).unwrap();
+
+    let auction: Auction = t.env.bank.load(&a.auction);
+    assert_eq!(auction.gov_amount, 900);
+    assert_eq!(auction.payout, (&payout).into());
+    assert_eq!(t.env.bank.token_balance(&a.escrow), 333);
+}
+
+#[test]
+fn test_claim_refund() {
+    let mut t = setup();
+    let a = start_debt_auction(&mut t);
+
+    let payout = create_token_account(&mut t.env.bank, &t.governance_token, &t.env.user);
+    let instruction = bid_instruction(&t, &a, &payout, None, 900);
+    t.env.bank.process(&instruction).unwrap();
+
+    // borrow the 333 debt tokens of a second bid
+    t.env.bank.process(&t.env.stake_instruction(2_000)).unwrap();
+    t.env.bank.process(&t.env.borrow_instruction(333)).unwrap();
+
+    // outbidding takes the account that records the refund
+    let instruction = bid_instruction(&t, &a, &payout, None, 800);
+    assert_eq!(
+        t.env.bank.process(&instruction),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+
+    let refund = create_state::<AuctionRefund>(&mut t.env.bank);
+    let instruction = bid_instruction(&t, &a, &payout, Some(&refund), 800);
+    t.env.bank.process(&instruction).unwrap();
+
+    // both bids are in the escrow
+    assert_eq!(t.env.bank.token_balance(&a.escrow), 666);
+    assert_eq!(t.env.bank.token_balance(&t.env.user_debt), 0);
+    let record: AuctionRefund = t.env.bank.load(&refund);
+    assert_eq!(record.bidder, (&t.env.user_debt).into());
+    assert_eq!(record.amount, 333);
+
+    let claim = |refund_to: &Pubkey| {
+        instruction::claim_refund(
+            &program_id(),
+            &refund,
+            &a.escrow,
+            &a.auction_authority,
+            refund_to,
+            &t.env.user,
+            a.auction_authority_nonce,
+        )
+        .unwrap()
+    };
+    let wrong_account = claim(&t.env.user_collateral);
+    let instruction = claim(&t.env.user_debt);
+
+    assert_eq!(
+        t.env.bank.process(&wrong_account),
+        Err(Error::OwnerMismatch.into())
+    );
+
+    t.env.bank.process(&instruction).unwrap();
+
+    assert_eq!(t.env.bank.token_balance(&a.escrow), 333);
+    assert_eq!(t.env.bank.token_balance(&t.env.user_debt), 333);
+    assert_eq!(t.env.bank.lamports(&refund), 0);
+}
@@ -0,0 +1,377 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::{
+    create_state, create_token_account, program_id, swap::create_account_with_data, Env,
+    COLLATERAL_RATIO, USER_COLLATERAL,
+};
+use solana_faucet::{
+    error::Error,
+    event::{parse_logs, Event},
+    instruction,
+    state::{
+        delegate_permission, CollateralPricing, DebtType, Treasury, Vault, VaultDelegate,
+        VaultType, VaultTypeParams,
+    },
+};
+use solana_program::{
+    instruction::AccountMeta, program_error::ProgramError, program_option::COption,
+    program_pack::Pack, pubkey::Pubkey,
+};
+use spl_token::{error::TokenError, state::Mint};
+
+fn custom(error: Error) -> Result<(), ProgramError> {
+    Err(error.into())
+}
+
+#[test]
+fn test_stake_and_borrow() {
+    let mut env = Env::new();
+
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+    assert_eq!(
+        env.bank.token_balance(&env.user_collateral),
+        USER_COLLATERAL - 1_000
+    );
+    assert_eq!(env.bank.token_balance(&env.collateral_holder), 1_000);
+
+    // 1000 collateral is worth 2000 debt, which supports 1333 debt at 150%
+    env.bank.process(&env.borrow_instruction(1_333)).unwrap();
+    assert_eq!(env.bank.token_balance(&env.user_debt), 1_333);
+
+    let vault: Vault = env.bank.load(&env.vault);
+    assert_eq!(vault.collateral_amount, 1_000);
+    assert_eq!(vault.debt_amount, 1_333);
+
+    let vault_type: VaultType = env.bank.load(&env.vault_type);
+    assert_eq!(vault_type.collateral_amount, 1_000);
+
+    assert_eq!(
+        env.bank.process(&env.borrow_instruction(1)),
+        custom(Error::CollateralRatioTooLow)
+    );
+}
+
+#[test]
+fn test_events() {
+    let mut env = Env::new();
+    env.bank.take_logs();
+
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+    env.bank.process(&env.borrow_instruction(1_000)).unwrap();
+    // a failed instruction logs no event
+    assert!(env.bank.process(&env.borrow_instruction(1_000)).is_err());
+
+    let logs = env.bank.take_logs();
+    assert_eq!(
+        parse_logs(&program_id(), &logs).unwrap(),
+        vec![
+            Event::VaultStaked {
+                vault: (&env.vault).into(),
+                vault_type: (&env.vault_type).into(),
+                amount: 1_000,
+                collateral_amount: 1_000,
+            },
+            Event::DebtBorrowed {
+                vault: (&env.vault).into(),
+                vault_type: (&env.vault_type).into(),
+                amount: 1_000,
+                fee: 0,
+                debt_amount: 1_000,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_borrow_origination_fee() {
+    let mut env = Env::new();
+    env.bank
+        .process(
+            &instruction::update_vault_type_params(
+                &program_id(),
+                &env.vault_type,
+                &env.debt_type,
+                &env.admin,
+                VaultTypeParams {
+                    collateral_ratio: COLLATERAL_RATIO,
+                    origination_fee: 100,
+                    ..VaultTypeParams::default()
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    let treasury = create_state::<Treasury>(&mut env.bank);
+    env.bank
+        .process(
+            &instruction::init_treasury(
+                &program_id(),
+                &treasury,
+                &env.debt_type,
+                &env.admin,
+                &Pubkey::new_unique(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+
+    // the fee is credited to the treasury, which is then required
+    assert_eq!(
+        env.bank.process(&env.borrow_instruction(1_000)),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+
+    // a treasury without a delegate isn't mistaken for one
+    let stranger = env.bank.new_signer();
+    let mut instruction = env.borrow_instruction_with(Some(&treasury), None, 1_000);
+    instruction.accounts[7] = AccountMeta::new_readonly(stranger, true);
+    assert_eq!(env.bank.process(&instruction), custom(Error::OwnerMismatch));
+
+    env.bank
+        .process(&env.borrow_instruction_with(Some(&treasury), None, 1_000))
+        .unwrap();
+
+    let vault: Vault = env.bank.load(&env.vault);
+    assert_eq!(vault.debt_amount, 1_010);
+    assert_eq!(env.bank.token_balance(&env.user_debt), 1_000);
+    let treasury: Treasury = env.bank.load(&treasury);
+    assert_eq!(treasury.surplus, 10);
+}
+
+#[test]
+fn test_borrow_with_decimals() {
+    let mut env = Env::with_decimals(9, 6);
+
+    let debt_type: DebtType = env.bank.load(&env.debt_type);
+    assert_eq!(debt_type.decimals, 6);
+    let vault_type: VaultType = env.bank.load(&env.vault_type);
+    assert_eq!(vault_type.collateral_decimals, 9);
+    assert_eq!(vault_type.debt_decimals, 6);
+
+    // 10000 collateral is 0.00001 tokens, worth 0.00002 debt tokens or 20 in
+    // debt base units, which supports 13 at 150%
+    env.bank
+        .process(&env.stake_instruction(USER_COLLATERAL))
+        .unwrap();
+    env.bank.process(&env.borrow_instruction(13)).unwrap();
+    assert_eq!(
+        env.bank.process(&env.borrow_instruction(1)),
+        custom(Error::CollateralRatioTooLow)
+    );
+}
+
+#[test]
+fn test_tokenized_vault() {
+    let mut env = Env::new();
+
+    let mint = env.bank.create_account(Mint::LEN, &spl_token::id());
+    let position_account = env
+        .bank
+        .create_account(spl_token::state::Account::LEN, &spl_token::id());
+    let vault = create_state::<Vault>(&mut env.bank);
+    env.bank
+        .process(
+            &instruction::init_vault(
+                &program_id(),
+                &vault,
+                &env.vault_type,
+                &env.user,
+                Some((&mint, &position_account)),
+                None,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    let position_mint = Mint::unpack(&env.bank.account(&mint).data).unwrap();
+    assert_eq!(position_mint.supply, 1);
+    assert_eq!(position_mint.mint_authority, COption::None);
+    assert_eq!(env.bank.token_balance(&position_account), 1);
+    let vault_state: Vault = env.bank.load(&vault);
+    assert_eq!(vault_state.position_token, (&mint).into());
+
+    // the position token can't back another vault
+    let other_account = env
+        .bank
+        .create_account(spl_token::state::Account::LEN, &spl_token::id());
+    let other_vault = create_state::<Vault>(&mut env.bank);
+    assert_eq!(
+        env.bank.process(
+            &instruction::init_vault(
+                &program_id(),
+                &other_vault,
+                &env.vault_type,
+                &env.user,
+                Some((&mint, &other_account)),
+                None,
+            )
+            .unwrap(),
+        ),
+        Err(TokenError::AlreadyInUse.into())
+    );
+}
+
+#[test]
+fn test_stake_vault_type_mismatch() {
+    let mut env = Env::new();
+
+    let other_vault_type = create_state::<VaultType>(&mut env.bank);
+    Env::init_vault_type(
+        &mut env.bank,
+        &other_vault_type,
+        &env.debt_type,
+        &env.admin,
+        &env.collateral_token,
+        &env.collateral_holder,
+        &env.price_feed,
+    );
+
+    let mut instruction = env.stake_instruction(1_000);
+    instruction.accounts[4] = AccountMeta::new(other_vault_type, false);
+    assert_eq!(
+        env.bank.process(&instruction),
+        custom(Error::VaultTypeMismatch)
+    );
+}
+
+#[test]
+fn test_init_vault_type_owner_mismatch() {
+    let mut env = Env::new();
+
+    let vault_type = create_state::<VaultType>(&mut env.bank);
+    let mut instruction = instruction::init_vault_type(
+        &program_id(),
+        &vault_type,
+        &env.debt_type,
+        &env.user,
+        &env.collateral_token,
+        &env.collateral_holder,
+        &env.price_feed,
+        CollateralPricing::PriceFeed,
+        VaultTypeParams {
+            collateral_ratio: COLLATERAL_RATIO,
+            ..VaultTypeParams::default()
+        },
+        false,
+    )
+    .unwrap();
+    assert_eq!(env.bank.process(&instruction), custom(Error::OwnerMismatch));
+
+    // the owner has to sign
+    instruction.accounts[4] = AccountMeta::new_readonly(env.admin, false);
+    assert_eq!(
+        env.bank.process(&instruction),
+        Err(ProgramError::MissingRequiredSignature)
+    );
+}
+
+#[test]
+fn test_stake_collateral_holder_mismatch() {
+    let mut env = Env::new();
+
+    let not_holder = create_token_account(&mut env.bank, &env.collateral_token, &env.user);
+    let mut instruction = env.stake_instruction(1_000);
+    instruction.accounts[3] = AccountMeta::new(not_holder, false);
+    assert_eq!(
+        env.bank.process(&instruction),
+        custom(Error::CollateralHolderAccountMismatch)
+    );
+    assert_eq!(
+        env.bank.token_balance(&env.user_collateral),
+        USER_COLLATERAL
+    );
+}
+
+#[test]
+fn test_borrow_owner_mismatch() {
+    let mut env = Env::new();
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+
+    let stranger = env.bank.new_signer();
+    let mut instruction = env.borrow_instruction(100);
+    instruction.accounts[7] = AccountMeta::new_readonly(stranger, true);
+    assert_eq!(env.bank.process(&instruction), custom(Error::OwnerMismatch));
+}
+
+#[test]
+fn test_borrow_forged_delegate() {
+    let mut env = Env::new();
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+
+    // a delegate state that wasn't created by the program
+    let attacker = env.bank.new_signer();
+    let delegate = VaultDelegate {
+        is_initialized: true,
+        vault: (&env.vault).into(),
+        vault_owner: (&env.user).into(),
+        delegate: (&attacker).into(),
+        permissions: delegate_permission::BORROW,
+        borrow_cap: u64::MAX,
+        ..VaultDelegate::default()
+    };
+    let forged = create_account_with_data(&mut env.bank, delegate.try_to_vec().unwrap());
+
+    let mut instruction = env.borrow_instruction_with(None, Some(&forged), 100);
+    instruction.accounts[7] = AccountMeta::new_readonly(attacker, true);
+    assert_eq!(
+        env.bank.process(&instruction),
+        custom(Error::UnexpectedProgramAccount)
+    );
+}
+
+#[test]
+fn test_borrow_flagged_account_missing() {
+    let mut env = Env::new();
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+
+    // flagged in the instruction data but not passed
+    let mut instruction = env.borrow_instruction_with(None, Some(&Pubkey::new_unique()), 100);
+    instruction.accounts.pop();
+    assert_eq!(
+        env.bank.process(&instruction),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+}
+
+#[test]
+fn test_borrow_invalid_debt_token() {
+    let mut env = Env::new();
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+
+    let mut instruction = env.borrow_instruction(100);
+    instruction.accounts[1] = AccountMeta::new(env.collateral_token, false);
+    assert_eq!(
+        env.bank.process(&instruction),
+        custom(Error::InvalidDebtToken)
+    );
+}
+
+#[test]
+fn test_borrow_invalid_price_oracle() {
+    let mut env = Env::new();
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+
+    let mut instruction = env.borrow_instruction(100);
+    instruction.accounts[8] = AccountMeta::new_readonly(Pubkey::new_unique(), false);
+    assert_eq!(
+        env.bank.process(&instruction),
+        custom(Error::InvalidPriceOracle)
+    );
+}
+
+#[test]
+fn test_borrow_unexpected_debt_minter() {
+    let mut env = Env::new();
+    env.bank.process(&env.stake_instruction(1_000)).unwrap();
+
+    let mut instruction = env.borrow_instruction(100);
+    instruction.accounts[2] = AccountMeta::new_readonly(env.user, false);
+    assert_eq!(
+        env.bank.process(&instruction),
+        custom(Error::UnexpectedProgramAccount)
+    );
+    assert_eq!(env.bank.token_balance(&env.user_debt), 0);
+}
@@ -11,25 +11,51 @@ import {
 
 import {
   Borrow,
+  CloseVault,
   DebtType,
   InitDebtType,
+  InitPriceFeed,
   InitVault,
   InitVaultType,
+  OptionalAccounts,
+  PriceFeed,
+  Repay,
   Stake,
+  Unstake,
+  UpdatePrice,
   Vault,
   VaultType,
 } from "./schema";
 
 interface IInitDebtTypeAccounts {
   debtType?: Account; // writable, signed
+  debtToken: PublicKey; // initialized mint
 }
 
 interface IInitVaultTypeAccounts {
   vaultType?: Account; // writable, signed
+  collateralToken: PublicKey; // initialized mint
+  debtType: PublicKey;
+  debtTypeOwner: Account; // signed
 }
 
 interface IInitVaultAccounts {
   vault?: Account; // writable, signed
+  vaultType: PublicKey;
+  // if tokenize is set: the mint and token account of the position token,
+  // allocated to spl-token but not initialized
+  positionToken?: { mint: PublicKey; account: PublicKey };
+  allowlist?: PublicKey; // if the vault type is permissioned
+}
+
+interface IInitPriceFeedAccounts {
+  priceFeed?: Account; // writable, signed
+  tokenMint: PublicKey;
+}
+
+interface IUpdatePriceAccounts {
+  priceFeed: PublicKey; // writable
+  owner: Account; // signed
 }
 
 interface IStakeAccounts {
@@ -37,7 +63,7 @@ interface IStakeAccounts {
   collateralFromAuthority: Account; // writable, signed
   collateralTo; // writable (program)
 
-  vaultType: PublicKey;
+  vaultType: PublicKey; // writable
   vault: PublicKey; // writable
 }
 
@@ -52,6 +78,73 @@ interface IBorrowAccounts {
   vaultOwner: Account; // signed
 
   priceOracle: PublicKey;
+
+  positionAccount?: PublicKey; // if the vault is tokenized
+  vaultDelegate?: PublicKey; // writable, if vaultOwner is a delegate
+  // writable, debt type treasury. Required if the vault type charges a fee.
+  treasury?: PublicKey;
+  allowlist?: PublicKey; // if the vault type is permissioned
+}
+
+interface IRepayAccounts {
+  debtToken: PublicKey; // writable
+  debtFrom: PublicKey; // writable
+  debtFromAuthority: Account; // signed
+
+  debtType: PublicKey;
+  vaultType: PublicKey;
+  vault: PublicKey; // writable
+}
+
+interface IUnstakeAccounts {
+  collateralFrom: PublicKey; // writable (program)
+  collateralFromAuthority: PublicKey; // program
+  collateralTo: PublicKey; // writable
+
+  vaultType: PublicKey; // writable
+  vault: PublicKey; // writable
+  vaultOwner: Account; // signed, owner or delegate
+
+  priceOracle: PublicKey;
+
+  positionAccount?: PublicKey; // if the vault is tokenized
+  vaultDelegate?: PublicKey; // writable, if vaultOwner is a delegate
+}
+
+interface ICloseVaultAccounts {
+  vault: PublicKey; // writable
+  vaultOwner: Account; // signed
+  receiver: PublicKey; // writable
+}
+
+// The optional accounts passed, in the order of their bits, and the bits set.
+function optionalAccounts(accounts: {
+  positionAccount?: PublicKey;
+  vaultDelegate?: PublicKey;
+  treasury?: PublicKey;
+  allowlist?: PublicKey;
+}) {
+  const candidates = [
+    {
+      bit: OptionalAccounts.Position,
+      key: accounts.positionAccount,
+      write: false,
+    },
+    { bit: OptionalAccounts.Delegate, key: accounts.vaultDelegate, write: true },
+    { bit: OptionalAccounts.Treasury, key: accounts.treasury, write: true },
+    { bit: OptionalAccounts.Allowlist, key: accounts.allowlist, write: false },
+  ];
+
+  let bits = 0;
+  const passed: Array<PublicKey | { write: PublicKey }> = [];
+  for (const { bit, key, write } of candidates) {
+    if (key) {
+      bits |= bit;
+      passed.push(write ? { write: key } : key);
+    }
+  }
+
+  return { bits, accounts: passed };
 }
 
 export class DebtProgram extends BaseProgram {
@@ -77,6 +170,7 @@ export class DebtProgram extends BaseProgram {
         this.instruction(instruction.serialize(), [
           SYSVAR_RENT_PUBKEY,
           { write: debtType },
+          accounts.debtToken,
         ]),
       ],
       [this.account, debtType],
@@ -101,15 +195,18 @@ export class DebtProgram extends BaseProgram {
         this.instruction(instruction.serialize(), [
           SYSVAR_RENT_PUBKEY,
           { write: vaultType },
+          accounts.collateralToken,
+          accounts.debtType,
+          accounts.debtTypeOwner,
         ]),
       ],
-      [this.account, vaultType],
+      [this.account, vaultType, accounts.debtTypeOwner],
     );
 
     return vaultType;
   }
 
-  async initVault(instruction: InitVault, accounts: IInitVaultAccounts = {}) {
+  async initVault(instruction: InitVault, accounts: IInitVaultAccounts) {
     const vault = accounts.vault || new Account();
 
     await this.sendTx(
@@ -122,6 +219,16 @@ export class DebtProgram extends BaseProgram {
         this.instruction(instruction.serialize(), [
           SYSVAR_RENT_PUBKEY,
           { write: vault },
+          ...(accounts.positionToken
+            ? [
+                { write: accounts.positionToken.mint },
+                { write: accounts.positionToken.account },
+                await this.positionAuthority(vault.publicKey),
+                SPLToken.programID,
+              ]
+            : []),
+          accounts.vaultType,
+          ...(accounts.allowlist ? [accounts.allowlist] : []),
         ]),
       ],
       [this.account, vault],
@@ -130,6 +237,51 @@ export class DebtProgram extends BaseProgram {
     return vault;
   }
 
+  async positionAuthority(vault: PublicKey): Promise<PublicKey> {
+    const [authority] = await PublicKey.findProgramAddress(
+      [vault.toBuffer(), Buffer.from("position")],
+      this.programID,
+    );
+    return authority;
+  }
+
+  async initPriceFeed(
+    instruction: InitPriceFeed,
+    accounts: IInitPriceFeedAccounts,
+  ) {
+    const priceFeed = accounts.priceFeed || new Account();
+
+    await this.sendTx(
+      [
+        await this.sys.createRentFreeAccountInstruction({
+          newPubicKey: priceFeed.publicKey,
+          space: PriceFeed.size,
+          programID: this.programID,
+        }),
+        this.instruction(instruction.serialize(), [
+          SYSVAR_RENT_PUBKEY,
+          { write: priceFeed },
+          accounts.tokenMint,
+        ]),
+      ],
+      [this.account, priceFeed],
+    );
+
+    return priceFeed;
+  }
+
+  async updatePrice(instruction: UpdatePrice, accounts: IUpdatePriceAccounts) {
+    await this.sendTx(
+      [
+        this.instruction(instruction.serialize(), [
+          { write: accounts.priceFeed },
+          accounts.owner,
+        ]),
+      ],
+      [this.account, accounts.owner],
+    );
+  }
+
   async stake(instruction: Stake, accounts: IStakeAccounts) {
     await this.sendTx(
       [
@@ -138,7 +290,7 @@ export class DebtProgram extends BaseProgram {
           { write: accounts.collateralFrom },
           accounts.collateralFromAuthority,
           { write: accounts.collateralTo },
-          accounts.vaultType,
+          { write: accounts.vaultType },
           { write: accounts.vault },
         ]),
       ],
@@ -146,7 +298,32 @@ export class DebtProgram extends BaseProgram {
     );
   }
 
+  async unstake(instruction: Unstake, accounts: IUnstakeAccounts) {
+    const optional = optionalAccounts(accounts);
+    instruction.optional_accounts = optional.bits;
+
+    await this.sendTx(
+      [
+        this.instruction(instruction.serialize(), [
+          SPLToken.programID,
+          { write: accounts.collateralFrom },
+          accounts.collateralFromAuthority,
+          { write: accounts.collateralTo },
+          { write: accounts.vaultType },
+          { write: accounts.vault },
+          accounts.vaultOwner,
+          accounts.priceOracle,
+          ...optional.accounts,
+        ]),
+      ],
+      [this.account, accounts.vaultOwner],
+    );
+  }
+
   async borrow(instruction: Borrow, accounts: IBorrowAccounts) {
+    const optional = optionalAccounts(accounts);
+    instruction.optional_accounts = optional.bits;
+
     await this.sendTx(
       [
         this.instruction(instruction.serialize(), [
@@ -157,10 +334,43 @@ export class DebtProgram extends BaseProgram {
           { write: accounts.debtReceiver },
 
           accounts.debtType,
-          accounts.vaultType,
+          { write: accounts.vaultType },
           { write: accounts.vault },
           accounts.vaultOwner,
           accounts.priceOracle,
+          ...optional.accounts,
+        ]),
+      ],
+      [this.account, accounts.vaultOwner],
+    );
+  }
+
+  async repay(instruction: Repay, accounts: IRepayAccounts) {
+    await this.sendTx(
+      [
+        this.instruction(instruction.serialize(), [
+          SPLToken.programID,
+
+          { write: accounts.debtToken },
+          { write: accounts.debtFrom },
+          accounts.debtFromAuthority,
+
+          accounts.debtType,
+          { write: accounts.vaultType },
+          { write: accounts.vault },
+        ]),
+      ],
+      [this.account, accounts.debtFromAuthority],
+    );
+  }
+
+  async closeVault(instruction: CloseVault, accounts: ICloseVaultAccounts) {
+    await this.sendTx(
+      [
+        this.instruction(instruction.serialize(), [
+          { write: accounts.vault },
+          accounts.vaultOwner,
+          { write: accounts.receiver },
         ]),
       ],
       [this.account, accounts.vaultOwner],
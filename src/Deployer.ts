@@ -12,7 +12,16 @@ import { conn } from "./context";
 import { Connection } from "@solana/web3.js";
 import { log } from "./logger";
 import { DebtProgram } from "./DebtProgram";
-import { FaucetConfig, InitDebtType, InitVaultType } from "./schema";
+import BN from "bn.js";
+import {
+  CollateralPricing,
+  FaucetConfig,
+  InitDebtType,
+  InitPriceFeed,
+  InitVaultType,
+  UpdatePrice,
+  VaultTypeParams,
+} from "./schema";
 
 export interface DeployState {
   debtProgram: PublicKey;
@@ -65,22 +74,13 @@ export class Deployer {
     const debtToken = new Account();
     const debtType = new Account();
 
-    await this.program.initDebtType(
-      new InitDebtType({
-        debt_token: debtToken.publicKey,
-        owner: this.wallet.pubkey,
-      }),
-      {
-        debtType,
-      },
-    );
-
     // TODO: maybe serialize the program account
     const programMinter = await this.programAccount(
       debtType.publicKey,
       "minter",
     );
 
+    // the debt type records the mint's decimals, so the mint comes first
     const spltoken = new SPLToken(this.wallet);
     await spltoken.initializeMint({
       account: debtToken,
@@ -88,6 +88,17 @@ export class Deployer {
       decimals: 9,
     });
 
+    await this.program.initDebtType(
+      new InitDebtType({
+        debt_token: debtToken.publicKey,
+        owner: this.wallet.pubkey,
+      }),
+      {
+        debtType,
+        debtToken: debtToken.publicKey,
+      },
+    );
+
     this.state.debtToken = debtToken.publicKey;
     this.state.debtType = debtType.publicKey;
   }
@@ -103,29 +114,58 @@ export class Deployer {
     const collateralToken = new Account();
     const collateralTokenHolder = new Account();
     const vaultType = new Account();
-    const priceOracle = new Account();
+
+    // create a test collateral token using the wallet as minter
+    const spltoken = new SPLToken(this.wallet);
+    await spltoken.initializeMint({
+      account: collateralToken,
+      mintAuthority: this.wallet.pubkey,
+      decimals: 9,
+    });
+
+    // FIXME: switch to a real oracle...
+    const priceOracle = await this.program.initPriceFeed(
+      new InitPriceFeed({
+        owner: this.wallet.pubkey,
+        decimals: 0,
+      }),
+      { tokenMint: collateralToken.publicKey },
+    );
+    await this.program.updatePrice(
+      new UpdatePrice({ price: new BN(45000) }),
+      { priceFeed: priceOracle.publicKey, owner: this.wallet.account },
+    );
 
     await this.program.initVaultType(
       new InitVaultType({
         debt_type: this.state.debtType,
         collateral_token: collateralToken.publicKey,
         collateral_token_holder: collateralTokenHolder.publicKey,
-        // FIXME: switch to a real oracle...
         price_oracle: priceOracle.publicKey,
+        pricing: CollateralPricing.PriceFeed,
+        params: new VaultTypeParams({
+          // 150%
+          collateral_ratio: new BN(15000),
+          // no deleverage until a swap program is configured
+          swap_program: new PublicKey(Buffer.alloc(32)),
+          origination_fee: new BN(0),
+          // uncapped
+          collateral_cap: new BN(0),
+          // permissionless
+          allowlist: new PublicKey(Buffer.alloc(32)),
+          liquidation_penalty: new BN(0),
+          stability_fee: new BN(0),
+        }),
+        native: false,
       }),
       {
         vaultType,
+        collateralToken: collateralToken.publicKey,
+        debtType: this.state.debtType,
+        debtTypeOwner: this.wallet.account,
       },
     );
 
-    // create a test collateral token using the wallet as minter
-    const spltoken = new SPLToken(this.wallet);
-    await spltoken.initializeMint({
-      account: collateralToken,
-      mintAuthority: this.wallet.pubkey,
-      decimals: 9,
-    });
-
     // initialize token's collateral holder token account
     const programHolder = await this.programAccount(vaultType.publicKey, "holder")
     await spltoken.initializeAccount({
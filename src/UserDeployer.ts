@@ -98,7 +98,9 @@ export class UserDeployer {
       new InitVault({
         vault_type: this.deploy.vaultType,
         owner: this.wallet.account.publicKey,
+        tokenize: false,
       }),
+      { vaultType: this.deploy.vaultType },
     );
 
     this.state.vault = vault.publicKey;
@@ -140,6 +142,8 @@ export class UserDeployer {
       new Borrow({
         amount,
         debtMinterNonce: debtMinter.nonce,
+        // set by the program client from the accounts passed
+        optional_accounts: 0,
       }),
       {
         debtToken: this.deploy.debtToken,
@@ -152,6 +156,8 @@ export class UserDeployer {
         vaultOwner: this.wallet.account,
 
         priceOracle: this.deploy.priceOracle,
+        // the deployed vault type charges no fee and is permissionless, so
+        // neither the treasury nor the allowlist is passed
       },
     );
   }
@@ -26,16 +26,37 @@ export abstract class BaseState extends BaseStruct {
 }
 
 export class DebtType extends BaseState {
-  public static size = 65;
+  public static size = 98;
 
   public static schema = {
     kind: "struct",
-    fields: [["amount", "u64"]],
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["debtToken", [32], mappers.pubkey],
+      ["decimals", "u8"],
+      ["owner", [32], mappers.pubkey],
+      ["treasury", [32], mappers.pubkey],
+    ],
+  };
+}
+
+export class VaultTypeParams extends BaseStruct {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["collateral_ratio", "u64"],
+      ["swap_program", [32], mappers.pubkey],
+      ["origination_fee", "u64"],
+      ["collateral_cap", "u64"],
+      ["allowlist", [32], mappers.pubkey],
+      ["liquidation_penalty", "u64"],
+      ["stability_fee", "u64"],
+    ],
   };
 }
 
 export class VaultType extends BaseState {
-  public static size = 129;
+  public static size = 277;
 
   public static schema = {
     kind: "struct",
@@ -44,7 +65,7 @@ export class VaultType extends BaseState {
 }
 
 export class Vault extends BaseState {
-  public static size = 81;
+  public static size = 161;
 
   public static schema = {
     kind: "struct",
@@ -52,8 +73,193 @@ export class Vault extends BaseState {
       ["isInitialized", "u8", mappers.bool],
       ["vaultType", [32], mappers.pubkey],
       ["owner", [32], mappers.pubkey],
+      ["pendingOwner", [32], mappers.pubkey],
+      ["positionToken", [32], mappers.pubkey],
       ["debtAmount", "u64"],
       ["collateralAmount", "u64"],
+      ["debtAccumulator", "u128"],
+    ],
+  };
+}
+
+export class PriceFeed extends BaseState {
+  public static size = 74;
+
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["owner", [32], mappers.pubkey],
+      ["price", "u64"],
+      ["decimals", "u8"],
+      ["token", [32], mappers.pubkey],
+    ],
+  };
+}
+
+// bits of the optional accounts passed after the fixed accounts, in this order
+export enum OptionalAccounts {
+  Position = 1 << 0,
+  Delegate = 1 << 1,
+  Treasury = 1 << 2,
+  Allowlist = 1 << 3,
+}
+
+export enum CollateralPricing {
+  PriceFeed = 0,
+  LpPool = 1,
+}
+
+export class LpPool extends BaseState {
+  public static size = 147;
+
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["owner", [32], mappers.pubkey],
+      ["priceFeedA", [32], mappers.pubkey],
+      ["priceFeedB", [32], mappers.pubkey],
+      ["decimalsA", "u8"],
+      ["decimalsB", "u8"],
+      ["reserveA", "u64"],
+      ["reserveB", "u64"],
+      ["lpSupply", "u64"],
+      // whole debt tokens, scaled by 10^18
+      ["poolValue", "u128"],
+      // slot of the last refresh
+      ["lastUpdate", "u64"],
+    ],
+  };
+}
+
+export class CollateralBalance extends BaseStruct {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["vaultType", [32], mappers.pubkey],
+      ["amount", "u64"],
+    ],
+  };
+}
+
+export class MultiVault extends BaseState {
+  public static size = 233;
+
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["debtType", [32], mappers.pubkey],
+      ["owner", [32], mappers.pubkey],
+      ["debtAmount", "u64"],
+      ["collaterals", [CollateralBalance, 4]],
+    ],
+  };
+}
+
+export class VaultDelegate extends BaseState {
+  public static size = 114;
+
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["vault", [32], mappers.pubkey],
+      ["vaultOwner", [32], mappers.pubkey],
+      ["delegate", [32], mappers.pubkey],
+      ["permissions", "u8"],
+      ["borrowCap", "u64"],
+      ["borrowedAmount", "u64"],
+    ],
+  };
+}
+
+export class Allowlist extends BaseState {
+  public static size = 1057;
+
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["debtType", [32], mappers.pubkey],
+      ["entries", [[32], 32]],
+    ],
+  };
+}
+
+export class Savings extends BaseState {
+  public static size = 129;
+
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["debtType", [32], mappers.pubkey],
+      ["shareToken", [32], mappers.pubkey],
+      ["debtHolder", [32], mappers.pubkey],
+      ["rate", "u64"],
+      ["accumulator", "u128"],
+      // i64 on chain, always positive
+      ["lastUpdate", "u64"],
+    ],
+  };
+}
+
+export class Treasury extends BaseState {
+  public static size = 89;
+
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["debtType", [32], mappers.pubkey],
+      ["surplus", "u64"],
+      ["badDebt", "u64"],
+      ["stabilityFees", "u64"],
+      ["governanceToken", [32], mappers.pubkey],
+    ],
+  };
+}
+
+export enum AuctionKind {
+  Debt = 0,
+  Surplus = 1,
+}
+
+export class Auction extends BaseState {
+  public static size = 186;
+
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["treasury", [32], mappers.pubkey],
+      // AuctionKind
+      ["kind", "u8"],
+      ["debtAmount", "u64"],
+      ["govAmount", "u64"],
+      ["escrow", [32], mappers.pubkey],
+      ["bidder", [32], mappers.pubkey],
+      ["payout", [32], mappers.pubkey],
+      ["payoutToken", [32], mappers.pubkey],
+      // i64 on chain, always positive
+      ["endsAt", "u64"],
+    ],
+  };
+}
+
+export class AuctionRefund extends BaseState {
+  public static size = 105;
+
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["isInitialized", "u8", mappers.bool],
+      ["auction", [32], mappers.pubkey],
+      ["escrow", [32], mappers.pubkey],
+      ["bidder", [32], mappers.pubkey],
+      ["amount", "u64"],
     ],
   };
 }
@@ -106,6 +312,10 @@ export class InitVaultType extends BaseInstruction {
       ["collateral_token", [32], mappers.pubkey],
       ["collateral_token_holder", [32], mappers.pubkey],
       ["price_oracle", [32], mappers.pubkey],
+      // CollateralPricing
+      ["pricing", "u8"],
+      ["params", VaultTypeParams],
+      ["native", "u8", mappers.bool],
     ],
   };
 }
@@ -116,6 +326,7 @@ export class InitVault extends BaseInstruction {
     fields: [
       ["vault_type", [32], mappers.pubkey],
       ["owner", [32], mappers.pubkey],
+      ["tokenize", "u8", mappers.bool],
     ],
   };
 }
@@ -132,7 +343,11 @@ export class Stake extends BaseInstruction {
 export class Unstake extends BaseInstruction {
   public static schema = {
     kind: "struct",
-    fields: [["amount", "u64"]],
+    fields: [
+      ["amount", "u64"],
+      ["collateral_holder_nonce", "u8"],
+      ["optional_accounts", "u8"],
+    ],
   };
 }
 
@@ -149,10 +364,321 @@ export class Borrow extends BaseInstruction {
     fields: [
       ["amount", "u64"],
       ["debtMinterNonce", "u8"],
+      ["optional_accounts", "u8"],
+    ],
+  };
+}
+
+export class CloseVault extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [],
+  };
+}
+
+export class TransferVault extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["new_owner", [32], mappers.pubkey],
+      ["require_accept", "u8", mappers.bool],
+    ],
+  };
+}
+
+export class AcceptVault extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [],
+  };
+}
+
+export class SetVaultDelegate extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["delegate", [32], mappers.pubkey],
+      ["permissions", "u8"],
+      ["borrow_cap", "u64"],
+    ],
+  };
+}
+
+export class RevokeVaultDelegate extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [],
+  };
+}
+
+export class InitPriceFeed extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["owner", [32], mappers.pubkey],
+      ["decimals", "u8"],
+    ],
+  };
+}
+
+export class UpdatePrice extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["price", "u64"]],
+  };
+}
+
+export class InitLpPool extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["owner", [32], mappers.pubkey],
+      ["price_feed_a", [32], mappers.pubkey],
+      ["price_feed_b", [32], mappers.pubkey],
+    ],
+  };
+}
+
+export class UpdateLpPool extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["reserve_a", "u64"],
+      ["reserve_b", "u64"],
+      ["lp_supply", "u64"],
+    ],
+  };
+}
+
+export class RefreshLpPool extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [],
+  };
+}
+
+export class InitMultiVault extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["debt_type", [32], mappers.pubkey],
+      ["owner", [32], mappers.pubkey],
+    ],
+  };
+}
+
+export class StakeMulti extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["amount", "u64"],
+      ["collateral_holder_nonce", "u8"],
+    ],
+  };
+}
+
+export class UnstakeMulti extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["amount", "u64"],
+      ["collateral_holder_nonce", "u8"],
+    ],
+  };
+}
+
+export class BorrowMulti extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["amount", "u64"],
+      ["debtMinterNonce", "u8"],
+    ],
+  };
+}
+
+export class UpdateVaultTypeParams extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["params", VaultTypeParams]],
+  };
+}
+
+export class Deleverage extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["collateral_amount", "u64"],
+      ["minimum_debt_amount", "u64"],
+      ["collateral_holder_nonce", "u8"],
+      ["optional_accounts", "u8"],
+    ],
+  };
+}
+
+export class Leverage extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["amount", "u64"],
+      ["minimum_collateral_amount", "u64"],
+      ["debtMinterNonce", "u8"],
+      ["collateral_holder_nonce", "u8"],
+      ["optional_accounts", "u8"],
+    ],
+  };
+}
+
+export class InitSavings extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["rate", "u64"],
+      ["savings_authority_nonce", "u8"],
+    ],
+  };
+}
+
+export class SetSavingsRate extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["rate", "u64"]],
+  };
+}
+
+export class Drip extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["debtMinterNonce", "u8"]],
+  };
+}
+
+export class JoinSavings extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["amount", "u64"],
+      ["savings_authority_nonce", "u8"],
+    ],
+  };
+}
+
+export class ExitSavings extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["shares", "u64"],
+      ["savings_authority_nonce", "u8"],
+    ],
+  };
+}
+
+export class InitTreasury extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["governance_token", [32], mappers.pubkey]],
+  };
+}
+
+export class WithdrawSurplus extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["amount", "u64"],
+      ["debtMinterNonce", "u8"],
+    ],
+  };
+}
+
+export class WriteOffVault extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [],
+  };
+}
+
+export class StartAuction extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      // AuctionKind
+      ["kind", "u8"],
+      ["debt_amount", "u64"],
+      ["gov_amount", "u64"],
+      ["auction_authority_nonce", "u8"],
+    ],
+  };
+}
+
+export class Bid extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["gov_amount", "u64"],
+      ["auction_authority_nonce", "u8"],
+    ],
+  };
+}
+
+export class SettleAuction extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [
+      ["auction_authority_nonce", "u8"],
+      ["debtMinterNonce", "u8"],
     ],
   };
 }
 
+export class InitAllowlist extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [],
+  };
+}
+
+export class AddToAllowlist extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["address", [32], mappers.pubkey]],
+  };
+}
+
+export class RemoveFromAllowlist extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["address", [32], mappers.pubkey]],
+  };
+}
+
+export class Liquidate extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["collateral_holder_nonce", "u8"]],
+  };
+}
+
+export class RepayMulti extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["amount", "u64"]],
+  };
+}
+
+export class LiquidateMulti extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["collateral_holder_nonces", [4]]],
+  };
+}
+
+export class ClaimRefund extends BaseInstruction {
+  public static schema = {
+    kind: "struct",
+    fields: [["auction_authority_nonce", "u8"]],
+  };
+}
+
 export class InstructionEnum extends BaseEnum {
   public static schema = {
     kind: "enum",
@@ -165,12 +691,59 @@ export class InstructionEnum extends BaseEnum {
       [Unstake.name, Unstake],
       [Repay.name, Repay],
       [Borrow.name, Borrow],
+      [CloseVault.name, CloseVault],
+      [TransferVault.name, TransferVault],
+      [AcceptVault.name, AcceptVault],
+      [SetVaultDelegate.name, SetVaultDelegate],
+      [RevokeVaultDelegate.name, RevokeVaultDelegate],
+      [InitPriceFeed.name, InitPriceFeed],
+      [UpdatePrice.name, UpdatePrice],
+      [InitLpPool.name, InitLpPool],
+      [UpdateLpPool.name, UpdateLpPool],
+      [RefreshLpPool.name, RefreshLpPool],
+      [InitMultiVault.name, InitMultiVault],
+      [StakeMulti.name, StakeMulti],
+      [UnstakeMulti.name, UnstakeMulti],
+      [BorrowMulti.name, BorrowMulti],
+      [UpdateVaultTypeParams.name, UpdateVaultTypeParams],
+      [Deleverage.name, Deleverage],
+      [Leverage.name, Leverage],
+      [InitSavings.name, InitSavings],
+      [SetSavingsRate.name, SetSavingsRate],
+      [Drip.name, Drip],
+      [JoinSavings.name, JoinSavings],
+      [ExitSavings.name, ExitSavings],
+      [InitTreasury.name, InitTreasury],
+      [WithdrawSurplus.name, WithdrawSurplus],
+      [WriteOffVault.name, WriteOffVault],
+      [StartAuction.name, StartAuction],
+      [Bid.name, Bid],
+      [SettleAuction.name, SettleAuction],
+      [InitAllowlist.name, InitAllowlist],
+      [AddToAllowlist.name, AddToAllowlist],
+      [RemoveFromAllowlist.name, RemoveFromAllowlist],
+      [Liquidate.name, Liquidate],
+      [RepayMulti.name, RepayMulti],
+      [LiquidateMulti.name, LiquidateMulti],
+      [ClaimRefund.name, ClaimRefund],
     ],
   };
 }
 
 const schema = new Schema([
   Vault,
+  PriceFeed,
+  LpPool,
+  VaultTypeParams,
+  CollateralBalance,
+  MultiVault,
+  VaultDelegate,
+  Allowlist,
+  DebtType,
+  Savings,
+  Treasury,
+  Auction,
+  AuctionRefund,
   InstructionEnum,
   InitDebtType,
   InitVaultType,
@@ -179,4 +752,39 @@ const schema = new Schema([
   Unstake,
   Repay,
   Borrow,
+  CloseVault,
+  TransferVault,
+  AcceptVault,
+  SetVaultDelegate,
+  RevokeVaultDelegate,
+  InitPriceFeed,
+  UpdatePrice,
+  InitLpPool,
+  UpdateLpPool,
+  RefreshLpPool,
+  InitMultiVault,
+  StakeMulti,
+  UnstakeMulti,
+  BorrowMulti,
+  UpdateVaultTypeParams,
+  Deleverage,
+  Leverage,
+  InitSavings,
+  SetSavingsRate,
+  Drip,
+  JoinSavings,
+  ExitSavings,
+  InitTreasury,
+  WithdrawSurplus,
+  WriteOffVault,
+  StartAuction,
+  Bid,
+  SettleAuction,
+  InitAllowlist,
+  AddToAllowlist,
+  RemoveFromAllowlist,
+  Liquidate,
+  RepayMulti,
+  LiquidateMulti,
+  ClaimRefund,
 ]);